@@ -1,8 +1,16 @@
+use std::sync::Arc;
+
 use internal_testing::TestingCommandClient;
+use webcontr::{
+  tls::clientconfig_from_pem, transport::tcp::client::TcpTransport,
+};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-  let mut client = TestingCommandClient::new("localhost:4000".into());
+  let config = clientconfig_from_pem("./webcontr/tests/certs/root.pem")?;
+  let transport =
+    TcpTransport::new("localhost:4000").with_tls_config(Arc::new(config));
+  let mut client = TestingCommandClient::with_transport(transport);
   let res = client.ping().await?;
   println!("result: {res:?}");
 
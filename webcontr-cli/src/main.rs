@@ -0,0 +1,298 @@
+//! `webcontr`, a command line client for ad-hoc calls against servers with
+//! [webcontr::Server::with_reflection] enabled.
+//!
+//! ```text
+//! webcontr localhost:4000 list
+//! webcontr localhost:4000 describe Store
+//! webcontr unix:/run/store.sock call Store.get '{"key": "a"}'
+//! ```
+
+mod schema;
+mod transcode;
+
+use std::{io, process::ExitCode};
+
+use bytes::Bytes;
+use clap::{Parser, Subcommand};
+use schema::SchemaError;
+use serde_json::Value;
+use thiserror::Error;
+use transcode::{TranscodeError, Transcoder};
+use webcontr::{
+  reflection::{MethodDescriptor, ReflectionClient, Schema, ServiceDescriptor},
+  transport::{
+    frame::{RequestFrame, ResponseErrorKind, ResponseFrame},
+    tcp::client::TcpTransport,
+    ClientTransport,
+  },
+  ClientError,
+};
+
+#[derive(Parser)]
+#[command(name = "webcontr", about = "Calls services of a webcontr server")]
+struct Args {
+  /// `host:port`, or `unix:<path>` for a Unix domain socket.
+  target: String,
+
+  /// PEM file with the root certificates used to verify the server.
+  #[cfg(feature = "tls")]
+  #[arg(long)]
+  cacert: Option<std::path::PathBuf>,
+
+  #[command(subcommand)]
+  command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+  /// Lists the services of the server.
+  List,
+  /// Shows the methods of `Service`, or the signature of `Service.method`.
+  Describe { name: String },
+  /// Calls `Service.method` and prints the response as JSON.
+  Call {
+    method: String,
+    /// A JSON object keyed by argument name, or an array of the arguments in
+    /// order. A single argument that is neither may be given as is. `-`
+    /// reads them from stdin.
+    arguments: Option<String>,
+  },
+}
+
+#[derive(Error, Debug)]
+enum Error {
+  #[error(transparent)]
+  Client(#[from] ClientError),
+  #[error("the server doesn't have reflection enabled")]
+  NoReflection,
+  #[error("unknown service `{0}`")]
+  UnknownService(String),
+  #[error("unknown method `{0}`")]
+  UnknownMethod(String),
+  #[error("expected `Service.method`, found `{0}`")]
+  InvalidMethod(String),
+  #[error("invalid arguments: {0}")]
+  Json(#[from] serde_json::Error),
+  #[error("missing argument `{0}`")]
+  MissingArgument(String),
+  #[error("expected {expected} arguments, found {found}")]
+  ArgumentCount { expected: usize, found: usize },
+  #[error("argument `{name}`: {source}")]
+  Argument { name: String, source: TranscodeError },
+  #[error(transparent)]
+  Schema(#[from] SchemaError),
+  #[error("failed to decode response: {0}")]
+  Response(TranscodeError),
+  #[error(transparent)]
+  Io(#[from] io::Error),
+}
+
+/// Where to send requests, dialed anew for every call like the transports it
+/// wraps.
+enum Target {
+  Tcp(TcpTransport),
+  #[cfg(unix)]
+  Unix(webcontr::transport::unix::client::UnixTransport),
+}
+
+#[webcontr::async_trait]
+impl ClientTransport for Target {
+  async fn call(
+    &mut self,
+    request: RequestFrame,
+  ) -> Result<ResponseFrame, ClientError> {
+    match self {
+      Target::Tcp(transport) => transport.call(request).await,
+      #[cfg(unix)]
+      Target::Unix(transport) => transport.call(request).await,
+    }
+  }
+}
+
+impl Args {
+  fn target(&self) -> Result<Target, Error> {
+    #[cfg(unix)]
+    if let Some(path) = self.target.strip_prefix("unix:") {
+      use webcontr::transport::unix::client::UnixTransport;
+      return Ok(Target::Unix(UnixTransport::new(path)));
+    }
+
+    let transport = TcpTransport::new(self.target.clone());
+    #[cfg(feature = "tls")]
+    let transport = match &self.cacert {
+      Some(path) => {
+        let config = webcontr::tls::clientconfig_from_pem(path)?;
+        transport.with_tls_config(std::sync::Arc::new(config))
+      }
+      None => transport,
+    };
+    Ok(Target::Tcp(transport))
+  }
+
+  fn reflection(&self) -> Result<ReflectionClient, Error> {
+    Ok(ReflectionClient::with_transport(self.target()?))
+  }
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> ExitCode {
+  let args = Args::parse();
+
+  match run(&args).await {
+    Ok(()) => ExitCode::SUCCESS,
+    Err(err) => {
+      eprintln!("error: {err}");
+      ExitCode::FAILURE
+    }
+  }
+}
+
+async fn run(args: &Args) -> Result<(), Error> {
+  match &args.command {
+    Command::List => {
+      for service in list_services(args).await? {
+        println!("{}", service.name);
+      }
+    }
+    Command::Describe { name } => {
+      let (service, method) = match name.rsplit_once('.') {
+        Some((service, method)) => (service, Some(method)),
+        None => (name.as_str(), None),
+      };
+      let service = describe(args, service).await?;
+      match method {
+        Some(method) => println!("{}", signature(find(&service, method)?)),
+        None => {
+          println!("{}", service.name);
+          for method in service.methods.iter() {
+            println!("  {}", signature(method));
+          }
+        }
+      }
+    }
+    Command::Call { method, arguments } => {
+      let arguments = match arguments.as_deref() {
+        Some("-") => serde_json::from_str(&io::read_to_string(io::stdin())?)?,
+        Some(arguments) => serde_json::from_str(arguments)?,
+        None => Value::Null,
+      };
+      let response = call(args, method, arguments).await?;
+      println!("{}", serde_json::to_string_pretty(&response)?);
+    }
+  }
+  Ok(())
+}
+
+async fn list_services(args: &Args) -> Result<Vec<ServiceDescriptor>, Error> {
+  args.reflection()?.list_services().await.map_err(reflection_error)
+}
+
+async fn describe(
+  args: &Args,
+  service: &str,
+) -> Result<ServiceDescriptor, Error> {
+  args
+    .reflection()?
+    .describe(service.to_string())
+    .await
+    .map_err(reflection_error)?
+    .ok_or_else(|| Error::UnknownService(service.to_string()))
+}
+
+fn reflection_error(err: ClientError) -> Error {
+  match err {
+    ClientError::ServerError(ResponseErrorKind::MethodNotFound) => {
+      Error::NoReflection
+    }
+    err => Error::Client(err),
+  }
+}
+
+fn find<'a>(
+  service: &'a ServiceDescriptor,
+  method: &str,
+) -> Result<&'a MethodDescriptor, Error> {
+  service
+    .method(method)
+    .ok_or_else(|| Error::UnknownMethod(format!("{}.{method}", service.name)))
+}
+
+fn signature(method: &MethodDescriptor) -> String {
+  let arguments: Vec<_> = method
+    .arguments
+    .iter()
+    .map(|arg| format!("{}: {}", arg.name, arg.ty))
+    .collect();
+  format!("{}({}) -> {}", method.name, arguments.join(", "), method.output)
+}
+
+async fn call(
+  args: &Args,
+  method: &str,
+  arguments: Value,
+) -> Result<Value, Error> {
+  let (service, method) = method
+    .rsplit_once('.')
+    .ok_or_else(|| Error::InvalidMethod(method.to_string()))?;
+  let service = describe(args, service).await?;
+  let descriptor = find(&service, method)?;
+  // Methods are listed in the order of the request enum variants.
+  let index = service.methods.iter().position(|m| m.name == method).unwrap();
+
+  // The request is an enum variant with the arguments as its fields.
+  let mut body = (index as u32).to_le_bytes().to_vec();
+  let arguments = match arguments {
+    Value::Null => Vec::new(),
+    Value::Array(arguments) => arguments,
+    Value::Object(mut arguments) => descriptor
+      .arguments
+      .iter()
+      .map(|arg| {
+        arguments
+          .remove(&*arg.name)
+          .ok_or_else(|| Error::MissingArgument(arg.name.to_string()))
+      })
+      .collect::<Result<_, _>>()?,
+    value => vec![value],
+  };
+  if arguments.len() != descriptor.arguments.len() {
+    return Err(Error::ArgumentCount {
+      expected: descriptor.arguments.len(),
+      found: arguments.len(),
+    });
+  }
+  // Types the server couldn't trace are parsed from their names instead.
+  let mut types = service.types.to_vec();
+  let mut schema = |schema: &Option<Schema>, ty: &str| match schema {
+    Some(schema) => Ok(schema.clone()),
+    None => schema::parse(ty, &mut types),
+  };
+  let schemas = descriptor
+    .arguments
+    .iter()
+    .map(|arg| schema(&arg.schema, &arg.ty))
+    .collect::<Result<Vec<_>, _>>()?;
+  let output = schema(&descriptor.output_schema, &descriptor.output)?;
+
+  let transcoder = Transcoder::new(&types);
+  for ((arg, ty), value) in
+    descriptor.arguments.iter().zip(&schemas).zip(&arguments)
+  {
+    transcoder.encode(ty, value, &mut body).map_err(|source| {
+      Error::Argument { name: arg.name.to_string(), source }
+    })?;
+  }
+
+  let request = RequestFrame::new(service.name.to_string(), Bytes::from(body));
+  let payload = match args.target()?.call(request).await? {
+    ResponseFrame::Payload(payload) => payload,
+    ResponseFrame::Error(err) => {
+      return Err(ClientError::ServerError(err).into())
+    }
+  };
+
+  let mut payload = &payload[..];
+  transcode::decode_variant(&mut payload)
+    .and_then(|_| transcoder.decode(&output, &mut payload))
+    .map_err(Error::Response)
+}
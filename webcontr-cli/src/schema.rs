@@ -0,0 +1,284 @@
+//! Parses the type names published by the reflection service into a
+//! [Schema], for arguments and outputs the server couldn't trace one for.
+//!
+//! Only standard library types are understood, user defined types are only
+//! known through their traced schema.
+
+use std::borrow::Cow;
+
+use thiserror::Error;
+use webcontr::reflection::{
+  Field, Fields, Schema, TypeDescriptor, TypeKind, Variant,
+};
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum SchemaError {
+  #[error("unexpected end of type `{0}`")]
+  UnexpectedEnd(String),
+  #[error("unexpected `{found}` in type `{ty}`")]
+  Unexpected { found: String, ty: String },
+  #[error("type `{0}` is not supported, only standard library types are")]
+  Unsupported(String),
+}
+
+/// Parses `ty`, adding the structs and enums it names to `types`.
+pub fn parse(
+  ty: &str,
+  types: &mut Vec<TypeDescriptor>,
+) -> Result<Schema, SchemaError> {
+  let tokens = tokenize(ty);
+  let mut parser = Parser { ty, tokens: &tokens, pos: 0, types };
+  let parsed = parser.ty()?;
+  match parser.next() {
+    None => Ok(parsed),
+    Some(token) => Err(parser.unexpected(token)),
+  }
+}
+
+fn tokenize(ty: &str) -> Vec<&str> {
+  let mut tokens = Vec::new();
+  let mut rest = ty.trim_start();
+
+  while let Some(c) = rest.chars().next() {
+    let len = if rest.starts_with("::") {
+      2
+    } else if c.is_alphanumeric() || c == '_' || c == '\'' {
+      rest
+        .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '\''))
+        .unwrap_or(rest.len())
+    } else {
+      c.len_utf8()
+    };
+    tokens.push(&rest[..len]);
+    rest = rest[len..].trim_start();
+  }
+  tokens
+}
+
+struct Parser<'a> {
+  ty: &'a str,
+  tokens: &'a [&'a str],
+  pos: usize,
+  types: &'a mut Vec<TypeDescriptor>,
+}
+
+impl<'a> Parser<'a> {
+  fn next(&mut self) -> Option<&'a str> {
+    let token = self.tokens.get(self.pos).copied();
+    self.pos += 1;
+    token
+  }
+
+  fn peek(&self) -> Option<&'a str> {
+    self.tokens.get(self.pos).copied()
+  }
+
+  fn expect(&mut self, expected: &str) -> Result<(), SchemaError> {
+    match self.next() {
+      Some(token) if token == expected => Ok(()),
+      Some(token) => Err(self.unexpected(token)),
+      None => Err(SchemaError::UnexpectedEnd(self.ty.to_string())),
+    }
+  }
+
+  fn unexpected(&self, found: &str) -> SchemaError {
+    SchemaError::Unexpected {
+      found: found.to_string(),
+      ty: self.ty.to_string(),
+    }
+  }
+
+  fn ty(&mut self) -> Result<Schema, SchemaError> {
+    match self.next() {
+      Some("(") => self.tuple(),
+      Some("[") => self.array(),
+      Some("&") => {
+        // Only `&str` and `&[T]` make sense, the rest can't be deserialized.
+        if self.peek().is_some_and(|token| token.starts_with('\'')) {
+          self.next();
+        }
+        self.ty()
+      }
+      Some(token) => self.path(token),
+      None => Err(SchemaError::UnexpectedEnd(self.ty.to_string())),
+    }
+  }
+
+  fn tuple(&mut self) -> Result<Schema, SchemaError> {
+    let mut items = Vec::new();
+    loop {
+      if self.peek() == Some(")") {
+        self.next();
+        break;
+      }
+      items.push(self.ty()?);
+      match self.next() {
+        Some(",") => continue,
+        Some(")") => break,
+        Some(token) => return Err(self.unexpected(token)),
+        None => return Err(SchemaError::UnexpectedEnd(self.ty.to_string())),
+      }
+    }
+
+    Ok(match items.len() {
+      0 => Schema::Unit,
+      _ => Schema::Tuple(items),
+    })
+  }
+
+  fn array(&mut self) -> Result<Schema, SchemaError> {
+    let item = self.ty()?;
+    match self.next() {
+      // A slice, only reachable through a reference.
+      Some("]") => Ok(Schema::Seq(Box::new(item))),
+      Some(";") => {
+        let len = self.next();
+        let len = len
+          .and_then(|len| len.trim_end_matches("usize").parse().ok())
+          .ok_or_else(|| self.unexpected(len.unwrap_or_default()))?;
+        self.expect("]")?;
+        Ok(Schema::Tuple(vec![item; len]))
+      }
+      Some(token) => Err(self.unexpected(token)),
+      None => Err(SchemaError::UnexpectedEnd(self.ty.to_string())),
+    }
+  }
+
+  fn path(&mut self, first: &'a str) -> Result<Schema, SchemaError> {
+    let mut name = first;
+    if name == "::" {
+      name = self.next().unwrap_or_default();
+    }
+    while self.peek() == Some("::") {
+      self.next();
+      name = self
+        .next()
+        .ok_or_else(|| SchemaError::UnexpectedEnd(self.ty.to_string()))?;
+    }
+
+    let mut args = Vec::new();
+    if self.peek() == Some("<") {
+      self.next();
+      loop {
+        args.push(self.ty()?);
+        match self.next() {
+          Some(",") if self.peek() == Some(">") => {
+            self.next();
+            break;
+          }
+          Some(",") => continue,
+          Some(">") => break,
+          Some(token) => return Err(self.unexpected(token)),
+          None => return Err(SchemaError::UnexpectedEnd(self.ty.to_string())),
+        }
+      }
+    }
+
+    let ty = self.ty;
+    let unsupported = || SchemaError::Unsupported(ty.to_string());
+    let mut args = args.into_iter();
+    let mut arg = || args.next().map(Box::new).ok_or_else(unsupported);
+
+    let schema = match name {
+      "bool" => Schema::Bool,
+      "char" => Schema::Char,
+      "str" | "String" => Schema::String,
+      "u8" => int(8, false),
+      "u16" => int(16, false),
+      "u32" => int(32, false),
+      "u64" | "usize" => int(64, false),
+      "u128" => int(128, false),
+      "i8" => int(8, true),
+      "i16" => int(16, true),
+      "i32" => int(32, true),
+      "i64" | "isize" => int(64, true),
+      "i128" => int(128, true),
+      "f32" => Schema::Float { bits: 32 },
+      "f64" => Schema::Float { bits: 64 },
+      "Bytes" | "BytesMut" => Schema::Bytes,
+      "Option" => Schema::Option(arg()?),
+      "Vec" | "VecDeque" | "LinkedList" | "HashSet" | "BTreeSet"
+      | "BinaryHeap" => Schema::Seq(arg()?),
+      "HashMap" | "BTreeMap" => Schema::Map(arg()?, arg()?),
+      "Result" => {
+        let variant = |name, ty: Box<Schema>| Variant {
+          name: Cow::Borrowed(name),
+          fields: Fields::Newtype(*ty),
+        };
+        let variants = vec![variant("Ok", arg()?), variant("Err", arg()?)];
+        self.named("Result", TypeKind::Enum(variants))
+      }
+      "Duration" => {
+        let field = |name, schema| Field { name: Cow::Borrowed(name), schema };
+        let fields =
+          vec![field("secs", int(64, false)), field("nanos", int(32, false))];
+        self.named("Duration", TypeKind::Struct(Fields::Named(fields)))
+      }
+      "Box" | "Arc" | "Rc" | "Cow" => return arg().map(|ty| *ty),
+      _ => return Err(unsupported()),
+    };
+    Ok(schema)
+  }
+
+  fn named(&mut self, name: &'static str, kind: TypeKind) -> Schema {
+    self.types.push(TypeDescriptor { name: Cow::Borrowed(name), kind });
+    Schema::Named(self.types.len() as u32 - 1)
+  }
+}
+
+fn int(bits: u8, signed: bool) -> Schema {
+  Schema::Int { bits, signed }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn parse(ty: &str) -> Result<Schema, SchemaError> {
+    super::parse(ty, &mut Vec::new())
+  }
+
+  #[test]
+  fn parses_std_types() {
+    assert_eq!(parse("()"), Ok(Schema::Unit));
+    assert_eq!(parse("std::string::String"), Ok(Schema::String));
+    assert_eq!(
+      parse("Option<Vec<u8>>"),
+      Ok(Schema::Option(Box::new(Schema::Seq(Box::new(int(8, false))))))
+    );
+    assert_eq!(
+      parse("HashMap<String, Box<i64>>"),
+      Ok(Schema::Map(Box::new(Schema::String), Box::new(int(64, true))))
+    );
+    assert_eq!(
+      parse("(u32, [bool; 2])"),
+      Ok(Schema::Tuple(vec![
+        int(32, false),
+        Schema::Tuple(vec![Schema::Bool, Schema::Bool])
+      ]))
+    );
+
+    let mut types = Vec::new();
+    assert_eq!(
+      super::parse("Result<(), String>", &mut types),
+      Ok(Schema::Named(0))
+    );
+    assert_eq!(
+      types[0].kind,
+      TypeKind::Enum(vec![
+        Variant { name: "Ok".into(), fields: Fields::Newtype(Schema::Unit) },
+        Variant { name: "Err".into(), fields: Fields::Newtype(Schema::String) },
+      ])
+    );
+  }
+
+  #[test]
+  fn rejects_unknown_types() {
+    assert_eq!(
+      parse("Vec<User>"),
+      Err(SchemaError::Unsupported("Vec<User>".into()))
+    );
+    assert!(parse("Vec<u8").is_err());
+    assert!(parse("u8 u8").is_err());
+  }
+}
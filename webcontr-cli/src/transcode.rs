@@ -0,0 +1,572 @@
+//! Converts between JSON and the bincode encoding webcontr uses on the wire,
+//! guided by a [Schema] from reflection.
+//!
+//! Mirrors the serde data model: options are `null` or their value, tuples
+//! and arrays are JSON arrays, maps are objects (or arrays of `[key, value]`
+//! pairs for keys that aren't strings or numbers). Structs are objects, enum
+//! variants are `"Variant"` without fields and `{"Variant": ..}` otherwise.
+
+use serde_json::{Map, Number, Value};
+use thiserror::Error;
+use webcontr::reflection::{Fields, Schema, TypeDescriptor, TypeKind};
+
+#[derive(Error, Debug)]
+pub enum TranscodeError {
+  #[error("expected {expected}, found {found}")]
+  Expected { expected: &'static str, found: Value },
+  #[error("{0} does not fit into the expected type")]
+  OutOfRange(Value),
+  #[error("expected {expected} elements, found {found}")]
+  Length { expected: usize, found: usize },
+  #[error("missing field `{0}`")]
+  MissingField(String),
+  #[error("unknown variant `{0}`")]
+  UnknownVariant(String),
+  #[error("schema refers to unknown type {0}")]
+  UnknownType(u32),
+  #[error("response ended unexpectedly")]
+  Truncated,
+  #[error("response contains invalid data: {0}")]
+  InvalidData(&'static str),
+}
+
+type Result<T> = std::result::Result<T, TranscodeError>;
+
+fn expected(expected: &'static str, found: &Value) -> TranscodeError {
+  TranscodeError::Expected { expected, found: found.clone() }
+}
+
+/// Transcodes schemas of one service, [Schema::Named] refers to its `types`.
+pub struct Transcoder<'a> {
+  types: &'a [TypeDescriptor],
+}
+
+impl<'a> Transcoder<'a> {
+  pub fn new(types: &'a [TypeDescriptor]) -> Self {
+    Self { types }
+  }
+
+  fn named(&self, index: u32) -> Result<&'a TypeDescriptor> {
+    let named = self.types.get(index as usize);
+    named.ok_or(TranscodeError::UnknownType(index))
+  }
+
+  pub fn encode(
+    &self,
+    ty: &Schema,
+    value: &Value,
+    out: &mut Vec<u8>,
+  ) -> Result<()> {
+    match ty {
+      Schema::Unit => match value {
+        Value::Null => {}
+        _ => return Err(expected("null", value)),
+      },
+      Schema::Bool => {
+        let value = value.as_bool().ok_or_else(|| expected("a bool", value))?;
+        out.push(value as u8);
+      }
+      Schema::Char => {
+        let mut chars = value.as_str().unwrap_or_default().chars();
+        let c = match (chars.next(), chars.next()) {
+          (Some(c), None) => c,
+          _ => return Err(expected("a single character", value)),
+        };
+        out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+      }
+      Schema::String => {
+        let value =
+          value.as_str().ok_or_else(|| expected("a string", value))?;
+        encode_len(value.len(), out);
+        out.extend_from_slice(value.as_bytes());
+      }
+      Schema::Bytes => {
+        let byte = Schema::Int { bits: 8, signed: false };
+        self.encode(&Schema::Seq(Box::new(byte)), value, out)?;
+      }
+      Schema::Int { bits, signed } => encode_int(*bits, *signed, value, out)?,
+      Schema::Float { bits } => {
+        let float =
+          value.as_f64().ok_or_else(|| expected("a number", value))?;
+        match bits {
+          32 => out.extend_from_slice(&(float as f32).to_le_bytes()),
+          _ => out.extend_from_slice(&float.to_le_bytes()),
+        }
+      }
+      Schema::Option(inner) => match value {
+        Value::Null => out.push(0),
+        value => {
+          out.push(1);
+          self.encode(inner, value, out)?;
+        }
+      },
+      Schema::Seq(item) => {
+        let items =
+          value.as_array().ok_or_else(|| expected("an array", value))?;
+        encode_len(items.len(), out);
+        for value in items {
+          self.encode(item, value, out)?;
+        }
+      }
+      Schema::Map(key_ty, value_ty) => match value {
+        Value::Object(entries) => {
+          encode_len(entries.len(), out);
+          for (key, value) in entries {
+            self.encode(key_ty, &key_from_str(key_ty, key), out)?;
+            self.encode(value_ty, value, out)?;
+          }
+        }
+        Value::Array(entries) => {
+          encode_len(entries.len(), out);
+          for entry in entries {
+            match entry.as_array().map(Vec::as_slice) {
+              Some([key, value]) => {
+                self.encode(key_ty, key, out)?;
+                self.encode(value_ty, value, out)?;
+              }
+              _ => return Err(expected("a [key, value] pair", entry)),
+            }
+          }
+        }
+        _ => return Err(expected("an object", value)),
+      },
+      Schema::Tuple(items) => self.encode_tuple(items, value, out)?,
+      Schema::Named(index) => match &self.named(*index)?.kind {
+        TypeKind::Struct(fields) => self.encode_fields(fields, value, out)?,
+        TypeKind::Enum(variants) => {
+          let (name, fields) = match value {
+            Value::String(name) => (name, &Value::Null),
+            Value::Object(object) if object.len() == 1 => {
+              object.iter().next().unwrap()
+            }
+            _ => return Err(expected("a variant", value)),
+          };
+          let (index, variant) = variants
+            .iter()
+            .enumerate()
+            .find(|(_, variant)| variant.name == *name)
+            .ok_or_else(|| TranscodeError::UnknownVariant(name.clone()))?;
+          out.extend_from_slice(&(index as u32).to_le_bytes());
+          self.encode_fields(&variant.fields, fields, out)?;
+        }
+      },
+    }
+    Ok(())
+  }
+
+  fn encode_tuple(
+    &self,
+    items: &[Schema],
+    value: &Value,
+    out: &mut Vec<u8>,
+  ) -> Result<()> {
+    let values = value.as_array().ok_or_else(|| expected("an array", value))?;
+    if values.len() != items.len() {
+      return Err(TranscodeError::Length {
+        expected: items.len(),
+        found: values.len(),
+      });
+    }
+    for (item, value) in items.iter().zip(values) {
+      self.encode(item, value, out)?;
+    }
+    Ok(())
+  }
+
+  fn encode_fields(
+    &self,
+    fields: &Fields,
+    value: &Value,
+    out: &mut Vec<u8>,
+  ) -> Result<()> {
+    match fields {
+      Fields::Unit => self.encode(&Schema::Unit, value, out),
+      Fields::Newtype(inner) => self.encode(inner, value, out),
+      Fields::Tuple(items) => self.encode_tuple(items, value, out),
+      Fields::Named(fields) => match value {
+        Value::Object(object) => {
+          for field in fields {
+            let value = match (object.get(&*field.name), &field.schema) {
+              (Some(value), _) => value,
+              // Like serde, a missing option is `None`.
+              (None, Schema::Option(_)) => &Value::Null,
+              (None, _) => {
+                return Err(TranscodeError::MissingField(
+                  field.name.to_string(),
+                ))
+              }
+            };
+            self.encode(&field.schema, value, out)?;
+          }
+          Ok(())
+        }
+        Value::Array(_) => {
+          let items: Vec<_> =
+            fields.iter().map(|field| field.schema.clone()).collect();
+          self.encode_tuple(&items, value, out)
+        }
+        _ => Err(expected("an object", value)),
+      },
+    }
+  }
+
+  pub fn decode(&self, ty: &Schema, input: &mut &[u8]) -> Result<Value> {
+    Ok(match ty {
+      Schema::Unit => Value::Null,
+      Schema::Bool => match take(input, 1)?[0] {
+        0 => Value::Bool(false),
+        1 => Value::Bool(true),
+        _ => return Err(TranscodeError::InvalidData("invalid bool")),
+      },
+      Schema::Char => {
+        let len = match input.first().ok_or(TranscodeError::Truncated)? {
+          0x00..=0x7f => 1,
+          0xc0..=0xdf => 2,
+          0xe0..=0xef => 3,
+          _ => 4,
+        };
+        let c = std::str::from_utf8(take(input, len)?)
+          .map_err(|_| TranscodeError::InvalidData("invalid char"))?;
+        Value::String(c.to_string())
+      }
+      Schema::String => {
+        let len = decode_len(input)?;
+        let string = std::str::from_utf8(take(input, len)?)
+          .map_err(|_| TranscodeError::InvalidData("invalid UTF-8 string"))?;
+        Value::String(string.to_string())
+      }
+      Schema::Bytes => {
+        let len = decode_len(input)?;
+        let bytes = take(input, len)?.iter();
+        Value::Array(bytes.map(|byte| Value::Number((*byte).into())).collect())
+      }
+      Schema::Int { bits, signed } => decode_int(*bits, *signed, input)?,
+      Schema::Float { bits: 32 } => {
+        let bytes = take(input, 4)?.try_into().unwrap();
+        float(f32::from_le_bytes(bytes) as f64)
+      }
+      Schema::Float { .. } => {
+        let bytes = take(input, 8)?.try_into().unwrap();
+        float(f64::from_le_bytes(bytes))
+      }
+      Schema::Option(inner) => match take(input, 1)?[0] {
+        0 => Value::Null,
+        1 => self.decode(inner, input)?,
+        _ => return Err(TranscodeError::InvalidData("invalid option tag")),
+      },
+      Schema::Seq(item) => {
+        let len = decode_len(input)?;
+        let items = (0..len).map(|_| self.decode(item, input));
+        Value::Array(items.collect::<Result<_>>()?)
+      }
+      Schema::Map(key_ty, value_ty) => {
+        let len = decode_len(input)?;
+        let mut object = Map::new();
+        let mut pairs = Vec::new();
+        for _ in 0..len {
+          let key = self.decode(key_ty, input)?;
+          let value = self.decode(value_ty, input)?;
+          match &key {
+            Value::String(key) => drop(object.insert(key.clone(), value)),
+            Value::Number(key) => drop(object.insert(key.to_string(), value)),
+            _ => pairs.push(Value::Array(vec![key, value])),
+          }
+        }
+        match pairs.is_empty() {
+          true => Value::Object(object),
+          false => Value::Array(pairs),
+        }
+      }
+      Schema::Tuple(items) => self.decode_tuple(items, input)?,
+      Schema::Named(index) => match &self.named(*index)?.kind {
+        TypeKind::Struct(fields) => self.decode_fields(fields, input)?,
+        TypeKind::Enum(variants) => {
+          let variant = variants
+            .get(decode_variant(input)? as usize)
+            .ok_or(TranscodeError::InvalidData("invalid enum variant"))?;
+          match &variant.fields {
+            Fields::Unit => Value::String(variant.name.to_string()),
+            fields => {
+              let mut object = Map::new();
+              let fields = self.decode_fields(fields, input)?;
+              object.insert(variant.name.to_string(), fields);
+              Value::Object(object)
+            }
+          }
+        }
+      },
+    })
+  }
+
+  fn decode_tuple(&self, items: &[Schema], input: &mut &[u8]) -> Result<Value> {
+    let items = items.iter().map(|item| self.decode(item, input));
+    Ok(Value::Array(items.collect::<Result<_>>()?))
+  }
+
+  fn decode_fields(&self, fields: &Fields, input: &mut &[u8]) -> Result<Value> {
+    match fields {
+      Fields::Unit => Ok(Value::Null),
+      Fields::Newtype(inner) => self.decode(inner, input),
+      Fields::Tuple(items) => self.decode_tuple(items, input),
+      Fields::Named(fields) => {
+        let mut object = Map::new();
+        for field in fields {
+          let value = self.decode(&field.schema, input)?;
+          object.insert(field.name.to_string(), value);
+        }
+        Ok(Value::Object(object))
+      }
+    }
+  }
+}
+
+fn encode_len(len: usize, out: &mut Vec<u8>) {
+  out.extend_from_slice(&(len as u64).to_le_bytes());
+}
+
+fn encode_int(
+  bits: u8,
+  signed: bool,
+  value: &Value,
+  out: &mut Vec<u8>,
+) -> Result<()> {
+  let out_of_range = || TranscodeError::OutOfRange(value.clone());
+  let bytes = bits as usize / 8;
+
+  // 128 bit integers may not survive as JSON numbers, accept strings too.
+  let parsed = match value {
+    Value::Number(number) if signed => number.as_i64().map(i128::from),
+    Value::Number(number) => number.as_u64().map(i128::from),
+    Value::String(string) if bits == 128 => string.parse().ok(),
+    _ => return Err(expected("an integer", value)),
+  };
+
+  if signed {
+    let int = parsed.ok_or_else(out_of_range)?;
+    let min = i128::MIN >> (128 - bits as u32);
+    let max = i128::MAX >> (128 - bits as u32);
+    if int < min || int > max {
+      return Err(out_of_range());
+    }
+    out.extend_from_slice(&int.to_le_bytes()[..bytes]);
+  } else {
+    let int = match value {
+      Value::String(string) => string.parse::<u128>().ok(),
+      _ => parsed.and_then(|int| u128::try_from(int).ok()),
+    }
+    .ok_or_else(out_of_range)?;
+    if bits < 128 && int >> bits != 0 {
+      return Err(out_of_range());
+    }
+    out.extend_from_slice(&int.to_le_bytes()[..bytes]);
+  }
+  Ok(())
+}
+
+/// Object keys are always strings in JSON, turn them back into numbers etc.
+fn key_from_str(ty: &Schema, key: &str) -> Value {
+  match ty {
+    Schema::String | Schema::Char => Value::String(key.to_string()),
+    _ => serde_json::from_str(key).unwrap_or(Value::String(key.to_string())),
+  }
+}
+
+/// Reads the index of an enum variant, which is also how requests and
+/// responses start.
+pub fn decode_variant(input: &mut &[u8]) -> Result<u32> {
+  Ok(u32::from_le_bytes(take(input, 4)?.try_into().unwrap()))
+}
+
+fn decode_len(input: &mut &[u8]) -> Result<usize> {
+  let len = u64::from_le_bytes(take(input, 8)?.try_into().unwrap());
+  usize::try_from(len).map_err(|_| TranscodeError::Truncated)
+}
+
+fn decode_int(bits: u8, signed: bool, input: &mut &[u8]) -> Result<Value> {
+  let bytes = take(input, bits as usize / 8)?;
+  let fill = match signed && bytes[bytes.len() - 1] & 0x80 != 0 {
+    true => 0xff,
+    false => 0,
+  };
+  let mut buf = [fill; 16];
+  buf[..bytes.len()].copy_from_slice(bytes);
+
+  Ok(if signed {
+    let int = i128::from_le_bytes(buf);
+    match i64::try_from(int) {
+      Ok(int) => Value::Number(int.into()),
+      Err(_) => Value::String(int.to_string()),
+    }
+  } else {
+    let int = u128::from_le_bytes(buf);
+    match u64::try_from(int) {
+      Ok(int) => Value::Number(int.into()),
+      Err(_) => Value::String(int.to_string()),
+    }
+  })
+}
+
+fn float(float: f64) -> Value {
+  Number::from_f64(float).map_or(Value::Null, Value::Number)
+}
+
+fn take<'a>(input: &mut &'a [u8], len: usize) -> Result<&'a [u8]> {
+  if input.len() < len {
+    return Err(TranscodeError::Truncated);
+  }
+  let (taken, rest) = input.split_at(len);
+  *input = rest;
+  Ok(taken)
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{collections::BTreeMap, time::Duration};
+
+  use serde::{Deserialize, Serialize};
+  use serde_json::json;
+  use webcontr::reflection::Tracer;
+
+  use super::*;
+
+  /// Encodes `json` as `ty` and checks it matches what bincode produces for
+  /// `value`, then decodes it back.
+  fn roundtrip<T: serde::Serialize>(ty: &str, json: Value, value: T) {
+    let mut types = Vec::new();
+    let ty = crate::schema::parse(ty, &mut types).unwrap();
+    check(&ty, &types, json, value);
+  }
+
+  /// Like [roundtrip], with the schema traced from `T`.
+  fn traced<T: serde::Serialize + serde::de::DeserializeOwned>(
+    json: Value,
+    value: T,
+  ) {
+    let mut tracer = Tracer::default();
+    let ty = tracer.trace::<T>().unwrap();
+    check(&ty, &tracer.into_types(), json, value);
+  }
+
+  fn check<T: serde::Serialize>(
+    ty: &Schema,
+    types: &[TypeDescriptor],
+    json: Value,
+    value: T,
+  ) {
+    let transcoder = Transcoder::new(types);
+    let expected = bincode::serialize(&value).unwrap();
+
+    let mut encoded = Vec::new();
+    transcoder.encode(ty, &json, &mut encoded).unwrap();
+    assert_eq!(encoded, expected);
+
+    let mut input = encoded.as_slice();
+    assert_eq!(transcoder.decode(ty, &mut input).unwrap(), json);
+    assert!(input.is_empty());
+  }
+
+  fn parse(ty: &str) -> Schema {
+    crate::schema::parse(ty, &mut Vec::new()).unwrap()
+  }
+
+  #[test]
+  fn matches_bincode() {
+    roundtrip("()", json!(null), ());
+    roundtrip("bool", json!(true), true);
+    roundtrip("char", json!("é"), 'é');
+    roundtrip("String", json!("hello"), "hello");
+    roundtrip("i8", json!(-3), -3i8);
+    roundtrip("u16", json!(513), 513u16);
+    roundtrip("i64", json!(i64::MIN), i64::MIN);
+    roundtrip("u128", json!(u128::MAX.to_string()), u128::MAX);
+    roundtrip("f32", json!(1.5), 1.5f32);
+    roundtrip("Option<u32>", json!(null), None::<u32>);
+    roundtrip("Option<u32>", json!(7), Some(7u32));
+    roundtrip("Vec<String>", json!(["a", "b"]), vec!["a", "b"]);
+    roundtrip("(u8, bool)", json!([1, false]), (1u8, false));
+    roundtrip("[u16; 2]", json!([1, 2]), [1u16, 2]);
+    roundtrip(
+      "BTreeMap<u32, String>",
+      json!({"1": "a", "2": "b"}),
+      BTreeMap::from([(1u32, "a"), (2, "b")]),
+    );
+    roundtrip(
+      "Result<u8, String>",
+      json!({"Err": "nope"}),
+      Err::<u8, _>("nope"),
+    );
+    roundtrip("Duration", json!({"secs": 3, "nanos": 5}), Duration::new(3, 5));
+  }
+
+  #[derive(Serialize, Deserialize)]
+  struct User {
+    name: String,
+    age: Option<u8>,
+    roles: Vec<Role>,
+  }
+
+  #[derive(Serialize, Deserialize)]
+  enum Role {
+    Guest,
+    Member(u32),
+    Admin { since: (u16, u8) },
+  }
+
+  #[test]
+  fn matches_bincode_for_traced_types() {
+    traced(json!("Guest"), Role::Guest);
+    traced(json!({"Member": 7}), Role::Member(7));
+    traced(
+      json!({"Admin": {"since": [2024, 5]}}),
+      Role::Admin { since: (2024, 5) },
+    );
+    traced(
+      json!({"name": "ada", "age": null, "roles": ["Guest", {"Member": 1}]}),
+      User {
+        name: "ada".into(),
+        age: None,
+        roles: vec![Role::Guest, Role::Member(1)],
+      },
+    );
+  }
+
+  #[test]
+  fn rejects_mismatches() {
+    let mut out = Vec::new();
+    let transcoder = Transcoder::new(&[]);
+    let ty = parse("u8");
+    assert!(matches!(
+      transcoder.encode(&ty, &json!(256), &mut out),
+      Err(TranscodeError::OutOfRange(_))
+    ));
+    assert!(matches!(
+      transcoder.encode(&ty, &json!("1"), &mut out),
+      Err(TranscodeError::Expected { .. })
+    ));
+
+    let ty = parse("(u8, u8)");
+    assert!(matches!(
+      transcoder.encode(&ty, &json!([1]), &mut out),
+      Err(TranscodeError::Length { expected: 2, found: 1 })
+    ));
+
+    let ty = parse("String");
+    assert!(matches!(
+      transcoder.decode(&ty, &mut [5, 0, 0, 0, 0, 0, 0, 0, b'a'].as_slice()),
+      Err(TranscodeError::Truncated)
+    ));
+
+    let mut tracer = Tracer::default();
+    let ty = tracer.trace::<User>().unwrap();
+    let types = tracer.into_types();
+    let transcoder = Transcoder::new(&types);
+    assert!(matches!(
+      transcoder.encode(&ty, &json!({"roles": []}), &mut out),
+      Err(TranscodeError::MissingField(field)) if field == "name"
+    ));
+    assert!(matches!(
+      transcoder.encode(&ty, &json!({"name": "", "roles": ["Root"]}), &mut out),
+      Err(TranscodeError::UnknownVariant(variant)) if variant == "Root"
+    ));
+  }
+}
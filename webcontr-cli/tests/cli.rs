@@ -0,0 +1,168 @@
+#![cfg(unix)]
+
+use std::{collections::HashMap, future::IntoFuture, path::Path};
+
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+use webcontr::{
+  prelude::*, transport::frame::ResponseErrorKind, transport::unix::UnixSocket,
+  Server,
+};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Entry {
+  key: String,
+  value: Vec<u8>,
+  expiry: Expiry,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Expiry {
+  Never,
+  After { secs: u64 },
+}
+
+#[webcontr::service]
+pub trait Store {
+  async fn get(key: String) -> Option<Vec<u8>>;
+  async fn count(
+    keys: Vec<String>,
+    prefix: Option<String>,
+  ) -> HashMap<String, u32>;
+  async fn put(entry: Entry) -> Entry;
+}
+
+#[derive(Clone)]
+struct StoreServer;
+
+#[webcontr::async_trait]
+impl Store for StoreServer {
+  async fn get(&self, key: String) -> Option<Vec<u8>> {
+    (key == "a").then(|| vec![1, 2, 3])
+  }
+
+  async fn count(
+    &self,
+    keys: Vec<String>,
+    prefix: Option<String>,
+  ) -> HashMap<String, u32> {
+    let prefix = prefix.unwrap_or_default();
+    HashMap::from([(
+      prefix.clone(),
+      keys.iter().filter(|key| key.starts_with(&prefix)).count() as u32,
+    )])
+  }
+
+  async fn put(&self, entry: Entry) -> Entry {
+    let expiry = match entry.expiry {
+      Expiry::Never => Expiry::After { secs: 60 },
+      Expiry::After { .. } => Expiry::Never,
+    };
+    Entry { key: entry.key.to_uppercase(), expiry, ..entry }
+  }
+}
+
+async fn webcontr(socket: &Path, args: &[&str]) -> (bool, String, String) {
+  let output = Command::new(env!("CARGO_BIN_EXE_webcontr"))
+    .arg(format!("unix:{}", socket.display()))
+    .args(args)
+    .output()
+    .await
+    .unwrap();
+  (
+    output.status.success(),
+    String::from_utf8(output.stdout).unwrap(),
+    String::from_utf8(output.stderr).unwrap(),
+  )
+}
+
+fn serve(socket: &Path, reflection: bool) {
+  let listener = UnixSocket::new(socket).bind().unwrap();
+  let mut server = Server::default().add_service(StoreServer.into_serve());
+  if reflection {
+    server = server.with_reflection();
+  }
+  tokio::spawn(server.serve_unix(listener).into_future());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn list_describe_and_call() {
+  let dir =
+    std::env::temp_dir().join(format!("webcontr-cli-{}", std::process::id()));
+  std::fs::create_dir_all(&dir).unwrap();
+  let socket = dir.join("store.sock");
+  serve(&socket, true);
+
+  let (ok, stdout, _) = webcontr(&socket, &["list"]).await;
+  assert!(ok);
+  assert_eq!(stdout, "Store\nReflection\n");
+
+  let (ok, stdout, _) = webcontr(&socket, &["describe", "Store"]).await;
+  assert!(ok);
+  assert_eq!(
+    stdout,
+    "Store\n  get(key: String) -> Option<Vec<u8>>\n  count(keys: Vec<String>, \
+     prefix: Option<String>) -> HashMap<String, u32>\n  put(entry: Entry) -> \
+     Entry\n"
+  );
+
+  let (ok, stdout, _) =
+    webcontr(&socket, &["call", "Store.get", r#"{"key": "a"}"#]).await;
+  assert!(ok);
+  assert_eq!(stdout, "[\n  1,\n  2,\n  3\n]\n");
+
+  let (ok, stdout, _) =
+    webcontr(&socket, &["call", "Store.get", r#""b""#]).await;
+  assert!(ok);
+  assert_eq!(stdout, "null\n");
+
+  let (ok, stdout, _) =
+    webcontr(&socket, &["call", "Store.count", r#"[["ab", "ac", "b"], "a"]"#])
+      .await;
+  assert!(ok);
+  assert_eq!(stdout, "{\n  \"a\": 2\n}\n");
+
+  let entry = r#"{"entry": {"key": "a", "value": [1], "expiry": "Never"}}"#;
+  let (ok, stdout, _) = webcontr(&socket, &["call", "Store.put", entry]).await;
+  assert!(ok);
+  let response: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+  assert_eq!(
+    response,
+    serde_json::json!({
+      "key": "A",
+      "value": [1],
+      "expiry": {"After": {"secs": 60}},
+    })
+  );
+
+  let entry = r#"[{"key": "a", "value": [], "expiry": "Later"}]"#;
+  let (ok, _, stderr) = webcontr(&socket, &["call", "Store.put", entry]).await;
+  assert!(!ok);
+  assert_eq!(stderr, "error: argument `entry`: unknown variant `Later`\n");
+
+  let (ok, _, stderr) =
+    webcontr(&socket, &["call", "Store.get", r#"{"key": 1}"#]).await;
+  assert!(!ok);
+  assert_eq!(stderr, "error: argument `key`: expected a string, found 1\n");
+
+  let (ok, _, stderr) = webcontr(&socket, &["call", "Store.delete"]).await;
+  assert!(!ok);
+  assert_eq!(stderr, "error: unknown method `Store.delete`\n");
+
+  std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn requires_reflection() {
+  let dir = std::env::temp_dir()
+    .join(format!("webcontr-cli-noreflect-{}", std::process::id()));
+  std::fs::create_dir_all(&dir).unwrap();
+  let socket = dir.join("store.sock");
+  serve(&socket, false);
+
+  let (ok, _, stderr) = webcontr(&socket, &["list"]).await;
+  assert!(!ok);
+  assert_eq!(stderr, "error: the server doesn't have reflection enabled\n");
+
+  std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -33,7 +33,7 @@ impl ServiceGenerator {
     let res_args = service
       .rpcs
       .iter()
-      .map(|rpc| (rpc.ident.clone(), rpc.output.clone()))
+      .map(|rpc| (rpc.ident.clone(), rpc.response.clone()))
       .collect();
 
     ServiceGenerator {
@@ -61,7 +61,7 @@ impl ServiceGenerator {
       self.service.ident.span(),
     );
     quote! {
-       #(#attrs_iter),*
+       #(#attrs_iter)*
        #[webcontr::async_trait]
        #vis trait #ident: Sized + Clone {
            #(#rpcs_iter)*
@@ -90,6 +90,14 @@ impl ServiceGenerator {
       self.service_request.args.iter().map(|variant| variant.0.clone());
     let res_ident = &self.service_response.ident;
 
+    // Errors of `Result<T, Status>` methods are sent as statuses.
+    let handled = rpcs.iter().map(|rpc| match rpc.fallible {
+      true => quote! {
+        .map_err(webcontr::transport::frame::ResponseErrorKind::Status)?
+      },
+      false => quote! {},
+    });
+
     let serve_struct_ident = Ident::new(
       &format!("{}Serve", self.service.ident),
       self.service.ident.span(),
@@ -117,7 +125,8 @@ impl ServiceGenerator {
           }
 
           fn call(&mut self, req: webcontr::prelude::Bytes) -> Self::Future {
-            let service = self.service.clone();
+            // Named so it can't clash with an rpc argument.
+            let __webcontr_service = self.service.clone();
 
             Box::pin(async move {
               let req: #req_ident = bincode::deserialize(&req)
@@ -126,9 +135,11 @@ impl ServiceGenerator {
               match req {
                 #(
                   #req_ident::#variants { #(#rpcs_args),* } => {
-                    let out = #ident::#variants(&service, #(#rpcs_args),*).await;
-                    let bytes_vec =
-                      bincode::serialize(&#res_ident::#variants(out)).unwrap();
+                    let out = #ident::#variants(&__webcontr_service, #(#rpcs_args),*).await #handled;
+                    let bytes_vec = bincode::serialize(&#res_ident::#variants(out))
+                      .map_err(|err| ResponseErrorKind::Internal(Some(format!(
+                        "failed to encode response: {err}"
+                      ))))?;
                     Ok(Bytes::from(bytes_vec))
                   }
                 ),*
@@ -136,21 +147,88 @@ impl ServiceGenerator {
             })
           }
         }
+
+        #[webcontr::async_trait]
+        impl<A: #ident + Send + Clone + Sync + 'static> webcontr::Serve
+          for #serve_struct_ident<A>
+        {
+          async fn serve(
+            &self,
+            req: webcontr::prelude::Bytes,
+          ) -> Result<
+            webcontr::prelude::Bytes,
+            webcontr::transport::frame::ResponseErrorKind,
+          > {
+            webcontr::prelude::Service::call(&mut self.clone(), req).await
+          }
+        }
     }
   }
 
   fn impl_servicename_fn(&self) -> TokenStream2 {
     let ident = &self.service.ident;
+    let rpc_ident = self.service.rpcs.iter().map(|rpc| &rpc.ident);
     let serve_struct_ident = Ident::new(
       &format!("{}Serve", self.service.ident),
       self.service.ident.span(),
     );
 
+    let method_descriptors = self.service.rpcs.iter().map(|rpc| {
+      let name = rpc.ident.to_string();
+      let arg_names =
+        rpc.args.iter().map(|arg| arg.pat.to_token_stream().to_string());
+      let arg_types = rpc.args.iter().map(|arg| type_name(&arg.ty));
+      let arg_tys = rpc.args.iter().map(|arg| &arg.ty);
+      let (output, output_ty) = match &rpc.response {
+        ReturnType::Default => ("()".to_string(), quote! { () }),
+        ReturnType::Type(_, ty) => (type_name(ty), ty.to_token_stream()),
+      };
+
+      quote! {
+        webcontr::reflection::MethodDescriptor {
+          name: Cow::Borrowed(#name),
+          arguments: Cow::Owned(vec![#(
+            webcontr::reflection::ArgumentDescriptor {
+              name: Cow::Borrowed(#arg_names),
+              ty: Cow::Borrowed(#arg_types),
+              schema: tracer.trace::<#arg_tys>(),
+            }
+          ),*]),
+          output: Cow::Borrowed(#output),
+          output_schema: tracer.trace::<#output_ty>(),
+        }
+      }
+    });
+
+    let timeouts =
+      self.service.rpcs.iter().map(|rpc| timeout_tokens(rpc.timeout));
+
     quote! {
             impl<A: Clone> webcontr::ServiceName for #serve_struct_ident<A> {
             fn name(&self) -> &'static str {
                 stringify!(#ident)
             }
+
+            fn methods(&self) -> &'static [&'static str] {
+                &[#(stringify!(#rpc_ident)),*]
+            }
+
+            fn timeouts(&self) -> &'static [Option<std::time::Duration>] {
+                const TIMEOUTS: &[Option<std::time::Duration>] = &[#(#timeouts),*];
+                TIMEOUTS
+            }
+
+            fn descriptor(&self) -> webcontr::reflection::ServiceDescriptor {
+                use std::borrow::Cow;
+
+                let mut tracer = webcontr::reflection::Tracer::default();
+                let methods = vec![#(#method_descriptors),*];
+                webcontr::reflection::ServiceDescriptor {
+                  name: Cow::Borrowed(stringify!(#ident)),
+                  methods: Cow::Owned(methods),
+                  types: Cow::Owned(tracer.into_types()),
+                }
+            }
         }
     }
   }
@@ -179,23 +257,61 @@ impl ServiceGenerator {
       .service
       .rpcs
       .iter()
-      .map(|rpc| match &rpc.output {
+      .map(|rpc| match &rpc.response {
         ReturnType::Default => quote! {()},
         ReturnType::Type(_, _type) => quote! {#_type},
       })
       .collect();
 
+    let rpc_timeouts =
+      self.service.rpcs.iter().map(|rpc| timeout_tokens(rpc.timeout));
+
     let rpc_res_ident = &self.service_response.ident;
     let rpc_req_ident = &self.service_request.ident;
 
     quote! {
         pub struct #client_ident {
-            addr: String
+            transport: Box<dyn webcontr::transport::ClientTransport>,
+            timeout: Option<std::time::Duration>,
+            rate_limit_retries: u32,
         }
 
         impl #client_ident {
             pub fn new(addr: String) -> Self {
-                Self { addr }
+                Self::with_transport(webcontr::transport::tcp::client::TcpTransport::new(addr))
+            }
+
+            /// Keeps a connection to `addr` open, reconnecting whenever it
+            /// breaks. See [webcontr::transport::connection::Connection] to
+            /// configure it and follow its state.
+            pub fn connect(addr: String) -> Self {
+                let connector = webcontr::transport::tcp::client::TcpTransport::new(addr);
+                Self::with_transport(webcontr::transport::connection::Connection::new(connector))
+            }
+
+            pub fn with_transport<T: webcontr::transport::ClientTransport + 'static>(transport: T) -> Self {
+                Self { transport: Box::new(transport), timeout: None, rate_limit_retries: 0 }
+            }
+
+            /// Gives up on every call after `timeout`, instead of the timeouts
+            /// declared on the methods.
+            pub fn with_timeout(mut self, timeout: std::time::Duration) -> Self {
+                self.timeout = Some(timeout);
+                self
+            }
+
+            /// Sends credentials from `provider` along with every call, see
+            /// [webcontr::auth].
+            pub fn with_credentials(mut self, provider: impl webcontr::auth::CredentialsProvider + 'static) -> Self {
+                self.transport = Box::new(webcontr::auth::WithCredentials::new(self.transport, provider));
+                self
+            }
+
+            /// Retries rate limited calls up to `retries` times, waiting as long
+            /// as the server asks in between.
+            pub fn with_rate_limit_retries(mut self, retries: u32) -> Self {
+                self.rate_limit_retries = retries;
+                self
             }
 
             #(
@@ -203,11 +319,16 @@ impl ServiceGenerator {
                 pub async fn #rpc_ident(&mut self, #(#rpc_args_types),*) -> Result<#rpc_return_type, webcontr::ClientError> {
 
                     let req = #rpc_req_ident::#rpc_ident { #(#rpc_args),* };
-                    let res: #rpc_res_ident = webcontr::transport::tcp::client::send_client_req(stringify!(#ident), req, &self.addr).await?;
+                    let options = webcontr::transport::CallOptions {
+                        timeout: self.timeout.or(#rpc_timeouts),
+                        rate_limit_retries: self.rate_limit_retries,
+                    };
+                    let res: #rpc_res_ident = webcontr::transport::send_request(&mut *self.transport, stringify!(#ident), stringify!(#rpc_ident), req, options).await?;
 
+                    #[allow(unreachable_patterns)]
                     match res {
                         #rpc_res_ident::#rpc_ident(response) => Ok(response),
-                        _ => unreachable!()
+                        _ => Err(webcontr::ClientError::MismatchedResponse(stringify!(#rpc_ident))),
                     }
                 }
             )*
@@ -216,6 +337,38 @@ impl ServiceGenerator {
   }
 }
 
+fn timeout_tokens(timeout: Option<u64>) -> TokenStream2 {
+  match timeout {
+    Some(nanos) => quote! { Some(std::time::Duration::from_nanos(#nanos)) },
+    None => quote! { None },
+  }
+}
+
+/// Renders a type the way it would be written by hand, e.g. `Vec<String>`
+/// instead of `Vec < String >`.
+fn type_name(ty: impl ToTokens) -> String {
+  let tokens = ty.to_token_stream().to_string();
+  let chars: Vec<char> = tokens.chars().collect();
+  let is_word = |c: char| c.is_alphanumeric() || c == '_' || c == '\'';
+
+  let mut name = String::with_capacity(tokens.len());
+  for (i, &c) in chars.iter().enumerate() {
+    if c == ' ' {
+      let between_words = i > 0
+        && is_word(chars[i - 1])
+        && chars.get(i + 1).is_some_and(|&next| is_word(next));
+      if !between_words {
+        continue;
+      }
+    }
+    name.push(c);
+    if c == ',' || c == ';' {
+      name.push(' ');
+    }
+  }
+  name
+}
+
 impl ToTokens for ServiceGenerator {
   fn to_tokens(&self, tokens: &mut TokenStream2) {
     tokens.extend([
@@ -1,8 +1,9 @@
-use proc_macro2::TokenStream as TokenStream2;
+use proc_macro2::{Span, TokenStream as TokenStream2};
 use quote::{quote, ToTokens};
 use syn::{
-  parenthesized, parse::Parse, spanned::Spanned, Attribute, FnArg, Ident, Pat,
-  PatType, ReturnType, Token,
+  parenthesized, parse::Parse, spanned::Spanned, Attribute, Expr, ExprLit,
+  FnArg, GenericArgument, Ident, Lit, Meta, MetaNameValue, Pat, PatType,
+  PathArguments, ReturnType, Token, Type,
 };
 
 #[derive(Debug)]
@@ -11,18 +12,26 @@ pub struct Rpc {
   pub ident: Ident,
   pub args: Vec<PatType>,
   pub output: ReturnType,
+  /// What is sent back on success. Same as `output`, except for methods
+  /// returning `Result<T, Status>` where it is `T`, see [status_result].
+  pub response: ReturnType,
+  /// Whether the method returns `Result<T, Status>`, its errors are sent as
+  /// `ResponseErrorKind::Status`.
+  pub fallible: bool,
+  /// From `#[timeout = "500ms"]`, in nanoseconds.
+  pub timeout: Option<u64>,
 }
 
 impl ToTokens for Rpc {
   fn to_tokens(&self, tokens: &mut TokenStream2) {
-    let Self { attrs, ident, args, output } = self;
+    let Self { attrs, ident, args, output, .. } = self;
 
     let args = args.iter().map(|pat| FnArg::Typed(pat.clone()));
 
     let attrs_iter = attrs.iter();
 
     let out = quote! {
-        #(#attrs_iter),*
+        #(#attrs_iter)*
         async fn #ident(&self, #(#args),* ) #output;
     };
 
@@ -32,7 +41,10 @@ impl ToTokens for Rpc {
 
 impl Parse for Rpc {
   fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
-    let attrs = input.call(Attribute::parse_outer)?;
+    let mut attrs = input.call(Attribute::parse_outer)?;
+    let timeout = take_timeout(&mut attrs)?;
+    let status = take_status(&mut attrs)?;
+
     let _async = input.parse::<Token![async]>()?;
     let _fn = input.parse::<Token![fn]>()?;
 
@@ -64,10 +76,143 @@ impl Parse for Rpc {
       };
     }
 
-    let output = input.parse()?;
+    let output: ReturnType = input.parse()?;
 
     input.parse::<Token![;]>()?;
 
-    Ok(Rpc { attrs, ident, args: parsed_params, output })
+    let (response, fallible) = match &output {
+      ReturnType::Type(arrow, ty) => match status_result(ty, status) {
+        Some(ok) => (ReturnType::Type(*arrow, Box::new(ok.clone())), true),
+        None if status.is_some() => {
+          return Err(syn::Error::new(
+            ty.span(),
+            "#[status] methods must return Result<T, Status>",
+          ))
+        }
+        None => (output.clone(), false),
+      },
+      ReturnType::Default => match status {
+        Some(span) => {
+          return Err(syn::Error::new(
+            span,
+            "#[status] methods must return Result<T, Status>",
+          ))
+        }
+        None => (output.clone(), false),
+      },
+    };
+
+    Ok(Rpc {
+      attrs,
+      ident,
+      args: parsed_params,
+      output,
+      response,
+      fallible,
+      timeout,
+    })
+  }
+}
+
+/// `T` if `ty` is `Result<T, webcontr::status::Status>`, or any
+/// `Result<T, E>` for methods marked `#[status]`, e.g. with `Status`
+/// imported. A `Status` of another crate is sent like any other type.
+fn status_result(ty: &Type, status: Option<Span>) -> Option<&Type> {
+  let Type::Path(path) = ty else {
+    return None;
+  };
+  let result = path.path.segments.last()?;
+  if result.ident != "Result" {
+    return None;
+  }
+  let PathArguments::AngleBracketed(generics) = &result.arguments else {
+    return None;
+  };
+  let mut generics = generics.args.iter();
+  let (
+    Some(GenericArgument::Type(ok)),
+    Some(GenericArgument::Type(Type::Path(err))),
+    None,
+  ) = (generics.next(), generics.next(), generics.next())
+  else {
+    return None;
+  };
+
+  if status.is_some() {
+    return Some(ok);
+  }
+  let path: Vec<_> = err.path.segments.iter().map(|s| &s.ident).collect();
+  let arguments = err.path.segments.iter().all(|s| s.arguments.is_none());
+  (arguments && path == ["webcontr", "status", "Status"]).then_some(ok)
+}
+
+/// Removes `#[status]` from `attrs`, returning where it was.
+fn take_status(attrs: &mut Vec<Attribute>) -> syn::Result<Option<Span>> {
+  let mut status = None;
+  let mut result = Ok(());
+  attrs.retain(|attr| {
+    if !attr.path().is_ident("status") {
+      return true;
+    }
+    match attr.meta.require_path_only() {
+      Ok(_) => status = Some(attr.span()),
+      Err(err) => result = Err(err),
+    }
+    false
+  });
+  result.map(|()| status)
+}
+
+/// Removes `#[timeout]` from `attrs`, it isn't valid on the trait method.
+fn take_timeout(attrs: &mut Vec<Attribute>) -> syn::Result<Option<u64>> {
+  let mut timeout = None;
+  let mut result = Ok(());
+  attrs.retain(|attr| {
+    if !attr.path().is_ident("timeout") {
+      return true;
+    }
+    match parse_timeout(attr) {
+      Ok(nanos) => timeout = Some(nanos),
+      Err(err) => result = Err(err),
+    }
+    false
+  });
+  result.map(|()| timeout)
+}
+
+fn parse_timeout(attr: &Attribute) -> syn::Result<u64> {
+  let error = || {
+    syn::Error::new(
+      attr.span(),
+      "expected a timeout like #[timeout = \"500ms\"], with one of the units \
+       ns, us, ms, s, m or h",
+    )
+  };
+
+  let Meta::NameValue(MetaNameValue {
+    value: Expr::Lit(ExprLit { lit: Lit::Str(lit), .. }),
+    ..
+  }) = &attr.meta
+  else {
+    return Err(error());
+  };
+
+  let value = lit.value();
+  let split = value.find(|c: char| !c.is_ascii_digit()).ok_or_else(error)?;
+  let (amount, unit) = value.split_at(split);
+  let amount: u64 = amount.parse().map_err(|_| error())?;
+  let nanos_per_unit: u64 = match unit.trim() {
+    "ns" => 1,
+    "us" => 1_000,
+    "ms" => 1_000_000,
+    "s" => 1_000_000_000,
+    "m" => 60 * 1_000_000_000,
+    "h" => 60 * 60 * 1_000_000_000,
+    _ => return Err(error()),
+  };
+
+  match amount.checked_mul(nanos_per_unit) {
+    Some(0) | None => Err(error()),
+    Some(nanos) => Ok(nanos),
   }
 }
@@ -29,8 +29,8 @@ fn request() {
 }
 
 fn bench_fibonacci(c: &mut Criterion) {
-  c.bench_function("from response frame codec", |b| b.iter(|| response()));
-  c.bench_function("from request frame codec", |b| b.iter(|| request()));
+  c.bench_function("from response frame codec", |b| b.iter(response));
+  c.bench_function("from request frame codec", |b| b.iter(request));
 }
 
 criterion_group!(benches, bench_fibonacci);
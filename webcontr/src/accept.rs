@@ -0,0 +1,110 @@
+use std::{
+  io,
+  sync::{Arc, OnceLock},
+};
+
+use async_trait::async_trait;
+use tokio::{
+  io::{AsyncRead, AsyncWrite},
+  net::TcpListener,
+};
+
+use crate::{
+  context::{ClientIdentity, PeerInfo},
+  trace,
+};
+
+/// A byte stream a connection can be served on.
+pub trait Io: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> Io for T {}
+
+/// A source of incoming connections, e.g. a bound socket.
+///
+/// Implement this to drive a [crate::serve::ServerServe] from a listener
+/// webcontr does not know about.
+#[async_trait]
+pub trait Accept: Send {
+  type Io: Io + 'static;
+
+  /// Waits for the next connection.
+  ///
+  /// Any setup that talks to the peer, like a TLS handshake, should happen
+  /// lazily inside [Accept::Io] so one slow peer does not hold up the rest.
+  async fn accept(&mut self) -> io::Result<(Self::Io, PeerInfo)>;
+
+  /// Where the verified identity of the client on `io` is stored once its
+  /// handshake is done, for listeners that authenticate clients.
+  fn client_identity(_io: &Self::Io) -> Option<IdentityCell> {
+    None
+  }
+}
+
+/// Filled in with the [ClientIdentity] of a connection once it is known.
+pub type IdentityCell = Arc<OnceLock<ClientIdentity>>;
+
+#[async_trait]
+impl Accept for TcpListener {
+  type Io = tokio::net::TcpStream;
+
+  async fn accept(&mut self) -> io::Result<(Self::Io, PeerInfo)> {
+    let (stream, addr) = TcpListener::accept(self).await?;
+    Ok((stream, PeerInfo::Tcp(addr)))
+  }
+}
+
+#[cfg(unix)]
+#[async_trait]
+impl Accept for tokio::net::UnixListener {
+  type Io = tokio::net::UnixStream;
+
+  /// Drops connections whose credentials can't be read, without failing
+  /// the listener for everyone else.
+  async fn accept(&mut self) -> io::Result<(Self::Io, PeerInfo)> {
+    loop {
+      let (stream, _) = tokio::net::UnixListener::accept(self).await?;
+      let cred = match stream.peer_cred() {
+        Ok(cred) => cred,
+        // Turns away this client only.
+        Err(_err) => {
+          trace::warn_event!(error = %_err, "failed to read peer credentials");
+          continue;
+        }
+      };
+      let peer = crate::context::UnixPeer {
+        uid: cred.uid(),
+        gid: cred.gid(),
+        pid: cred.pid(),
+      };
+      return Ok((stream, PeerInfo::Unix(peer)));
+    }
+  }
+}
+
+pub(crate) type BoxIo = Box<dyn Io>;
+pub(crate) type BoxAccept = Box<dyn AcceptBoxed>;
+
+pub(crate) struct Accepted {
+  pub(crate) io: BoxIo,
+  pub(crate) peer: PeerInfo,
+  pub(crate) identity: Option<IdentityCell>,
+}
+
+/// [Accept] with the stream type erased.
+#[async_trait]
+pub(crate) trait AcceptBoxed: Send {
+  async fn accept(&mut self) -> io::Result<Accepted>;
+}
+
+#[async_trait]
+impl<A: Accept> AcceptBoxed for A {
+  async fn accept(&mut self) -> io::Result<Accepted> {
+    let (io, peer) = Accept::accept(self).await?;
+    let identity = A::client_identity(&io);
+    Ok(Accepted { io: Box::new(io), peer, identity })
+  }
+}
+
+pub(crate) fn boxed<A: Accept + 'static>(acceptor: A) -> BoxAccept {
+  Box::new(acceptor)
+}
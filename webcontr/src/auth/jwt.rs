@@ -0,0 +1,110 @@
+//! Verifies JWT bearer tokens with the keys of a JWKS file, e.g. one
+//! exported from an identity provider.
+//!
+//! The token's `sub` claim becomes the [Principal]'s subject, its other
+//! claims are kept as claims of the principal, JSON encoded unless they are
+//! strings.
+
+use std::{io, path::Path, str::FromStr};
+
+use async_trait::async_trait;
+use jsonwebtoken::{jwk::JwkSet, Algorithm, DecodingKey, Validation};
+use serde_json::{Map, Value};
+
+use super::{unauthenticated, Authenticate, Credentials, Principal};
+use crate::status::Status;
+
+#[derive(Clone)]
+pub struct JwtVerifier {
+  keys: JwkSet,
+  issuer: Option<String>,
+  audience: Option<String>,
+}
+
+impl JwtVerifier {
+  pub fn from_jwks(jwks: &str) -> io::Result<Self> {
+    let keys = serde_json::from_str(jwks)
+      .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    Ok(Self { keys, issuer: None, audience: None })
+  }
+
+  pub fn from_jwks_file(path: impl AsRef<Path>) -> io::Result<Self> {
+    Self::from_jwks(&std::fs::read_to_string(path)?)
+  }
+
+  /// Only accepts tokens issued by `issuer`.
+  pub fn with_issuer(mut self, issuer: impl Into<String>) -> Self {
+    self.issuer = Some(issuer.into());
+    self
+  }
+
+  /// Only accepts tokens meant for `audience`.
+  pub fn with_audience(mut self, audience: impl Into<String>) -> Self {
+    self.audience = Some(audience.into());
+    self
+  }
+
+  fn verify(&self, token: &str) -> Result<Principal, Status> {
+    let invalid = |err: jsonwebtoken::errors::Error| {
+      unauthenticated(format!("invalid token: {err}"))
+    };
+
+    let header = jsonwebtoken::decode_header(token).map_err(invalid)?;
+    // Without a key id, only a set of one key is unambiguous.
+    let jwk = match (&header.kid, &self.keys.keys[..]) {
+      (Some(kid), _) => self.keys.find(kid),
+      (None, [jwk]) => Some(jwk),
+      (None, _) => None,
+    };
+    let Some(jwk) = jwk else {
+      return Err(unauthenticated("token signed with an unknown key"));
+    };
+    // A key meant for one algorithm can't be used with another.
+    if let Some(algorithm) = jwk.common.key_algorithm {
+      if Algorithm::from_str(&algorithm.to_string()).ok() != Some(header.alg) {
+        return Err(unauthenticated("token algorithm doesn't match its key"));
+      }
+    }
+    let key = DecodingKey::from_jwk(jwk).map_err(invalid)?;
+
+    let mut validation = Validation::new(header.alg);
+    validation.set_required_spec_claims(&["exp", "sub"]);
+    if let Some(issuer) = &self.issuer {
+      validation.set_issuer(&[issuer]);
+    }
+    match &self.audience {
+      Some(audience) => validation.set_audience(&[audience]),
+      None => validation.validate_aud = false,
+    }
+
+    let claims =
+      jsonwebtoken::decode::<Map<String, Value>>(token, &key, &validation)
+        .map_err(invalid)?
+        .claims;
+    let Some(Value::String(subject)) = claims.get("sub") else {
+      return Err(unauthenticated("token subject isn't a string"));
+    };
+
+    let principal = Principal::new(subject.clone());
+    Ok(claims.iter().filter(|(name, _)| *name != "sub").fold(
+      principal,
+      |principal, (name, value)| match value {
+        Value::String(value) => principal.with_claim(name, value),
+        value => principal.with_claim(name, value.to_string()),
+      },
+    ))
+  }
+}
+
+#[async_trait]
+impl Authenticate for JwtVerifier {
+  async fn authenticate(
+    &self,
+    credentials: &Credentials,
+  ) -> Result<Principal, Status> {
+    match credentials {
+      Credentials::Bearer(token) => self.verify(token),
+      Credentials::ApiKey(_) => Err(unauthenticated("expected a bearer token")),
+    }
+  }
+}
@@ -0,0 +1,316 @@
+//! Authentication of requests, see [crate::Server::with_authenticator].
+//!
+//! ```ignore
+//! let keys = ApiKeys::new().with_key("s3cr3t", Principal::new("backup"));
+//! let server = Server::default().add_service(..).with_authenticator(keys);
+//!
+//! let client = StoreClient::new(addr)
+//!   .with_credentials(Credentials::ApiKey("s3cr3t".into()));
+//! ```
+//!
+//! Clients send [Credentials] in the request metadata, as
+//! `authorization: Bearer <token>` or `x-api-key: <key>`. Requests without
+//! valid ones are answered with [Code::Unauthenticated] before reaching a
+//! handler, handlers find out who made the others with
+//! [crate::context::principal]. Services listed in
+//! [crate::Server::with_public_services], only [crate::health] by default,
+//! are served to anyone.
+
+#[cfg(feature = "jwt")]
+pub mod jwt;
+
+use std::{
+  collections::{BTreeMap, HashMap},
+  fmt,
+  future::Future,
+  pin::Pin,
+  time::Duration,
+};
+
+use async_trait::async_trait;
+use tokio::{sync::Mutex, time::Instant};
+
+use crate::{
+  metadata::Metadata,
+  status::{Code, Status},
+  transport::{
+    frame::{RequestFrame, ResponseFrame},
+    ClientTransport,
+  },
+  ClientError,
+};
+
+/// Metadata entry bearer tokens are sent in.
+pub const AUTHORIZATION: &str = "authorization";
+/// Metadata entry API keys are sent in.
+pub const API_KEY: &str = "x-api-key";
+
+#[derive(Clone, PartialEq, Eq)]
+pub enum Credentials {
+  Bearer(String),
+  ApiKey(String),
+}
+
+impl Credentials {
+  /// Reads the credentials a client sent, preferring a bearer token. Other
+  /// `authorization` schemes are ignored, e.g. ones meant for a proxy.
+  pub fn from_metadata(metadata: &Metadata) -> Option<Self> {
+    let bearer = metadata
+      .get(AUTHORIZATION)
+      .and_then(|value| value.split_once(' '))
+      .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("bearer"))
+      .map(|(_, token)| Credentials::Bearer(token.trim().to_string()));
+    bearer.or_else(|| {
+      metadata.get(API_KEY).map(|key| Credentials::ApiKey(key.to_string()))
+    })
+  }
+
+  fn attach(&self, metadata: &mut Metadata) {
+    match self {
+      Credentials::Bearer(token) => {
+        metadata.insert(AUTHORIZATION, format!("Bearer {token}"))
+      }
+      Credentials::ApiKey(key) => metadata.insert(API_KEY, key.clone()),
+    };
+  }
+}
+
+// Keeps secrets out of logs.
+impl fmt::Debug for Credentials {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Credentials::Bearer(_) => f.write_str("Bearer(..)"),
+      Credentials::ApiKey(_) => f.write_str("ApiKey(..)"),
+    }
+  }
+}
+
+/// Who made a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Principal {
+  subject: String,
+  claims: BTreeMap<String, String>,
+}
+
+impl Principal {
+  pub fn new(subject: impl Into<String>) -> Self {
+    Self { subject: subject.into(), claims: BTreeMap::new() }
+  }
+
+  /// Anything else known about the principal, e.g. its roles.
+  pub fn with_claim(
+    mut self,
+    name: impl Into<String>,
+    value: impl Into<String>,
+  ) -> Self {
+    self.claims.insert(name.into(), value.into());
+    self
+  }
+
+  pub fn subject(&self) -> &str {
+    &self.subject
+  }
+
+  pub fn claim(&self, name: &str) -> Option<&str> {
+    self.claims.get(name).map(String::as_str)
+  }
+}
+
+/// Checks the credentials of requests, see [crate::Server::with_authenticator].
+#[async_trait]
+pub trait Authenticate: Send + Sync {
+  /// Who `credentials` belong to, or the status to answer with, usually
+  /// [Code::Unauthenticated].
+  async fn authenticate(
+    &self,
+    credentials: &Credentials,
+  ) -> Result<Principal, Status>;
+}
+
+pub(crate) fn unauthenticated(message: impl Into<String>) -> Status {
+  Status::new(Code::Unauthenticated).with_message(message)
+}
+
+/// A fixed set of API keys, e.g. for other services.
+#[derive(Debug, Clone, Default)]
+pub struct ApiKeys {
+  keys: HashMap<String, Principal>,
+}
+
+impl ApiKeys {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn with_key(
+    mut self,
+    key: impl Into<String>,
+    principal: Principal,
+  ) -> Self {
+    self.keys.insert(key.into(), principal);
+    self
+  }
+}
+
+#[async_trait]
+impl Authenticate for ApiKeys {
+  async fn authenticate(
+    &self,
+    credentials: &Credentials,
+  ) -> Result<Principal, Status> {
+    let Credentials::ApiKey(key) = credentials else {
+      return Err(unauthenticated("expected an api key"));
+    };
+    self
+      .keys
+      .get(key)
+      .cloned()
+      .ok_or_else(|| unauthenticated("unknown api key"))
+  }
+}
+
+/// Credentials for a client, asked for before every call, see
+/// [WithCredentials].
+#[async_trait]
+pub trait CredentialsProvider: Send + Sync {
+  async fn credentials(&self) -> Result<Credentials, ClientError>;
+
+  /// Called when the server rejected the credentials, e.g. as a token was
+  /// revoked. Returns whether they changed, the call is made once more if
+  /// so.
+  async fn invalidate(&self) -> bool {
+    false
+  }
+}
+
+#[async_trait]
+impl CredentialsProvider for Credentials {
+  async fn credentials(&self) -> Result<Credentials, ClientError> {
+    Ok(self.clone())
+  }
+}
+
+type Fetch = Box<
+  dyn Fn() -> Pin<
+      Box<dyn Future<Output = Result<(String, Duration), ClientError>> + Send>,
+    > + Send
+    + Sync,
+>;
+
+/// A bearer token that expires, fetched again once 90% of the time it's
+/// valid for passed, or when the server rejects it.
+pub struct RefreshingToken {
+  fetch: Fetch,
+  cached: Mutex<Option<(String, Instant)>>,
+}
+
+impl RefreshingToken {
+  /// `fetch` returns a new token and how long it's valid for.
+  pub fn new<F, Fut>(fetch: F) -> Self
+  where
+    F: Fn() -> Fut + Send + Sync + 'static,
+    Fut:
+      Future<Output = Result<(String, Duration), ClientError>> + Send + 'static,
+  {
+    Self {
+      fetch: Box::new(move || Box::pin(fetch())),
+      cached: Mutex::new(None),
+    }
+  }
+}
+
+#[async_trait]
+impl CredentialsProvider for RefreshingToken {
+  async fn credentials(&self) -> Result<Credentials, ClientError> {
+    // Held while fetching, so concurrent calls wait for one new token.
+    let mut cached = self.cached.lock().await;
+    match &*cached {
+      Some((token, refresh_at)) if Instant::now() < *refresh_at => {
+        Ok(Credentials::Bearer(token.clone()))
+      }
+      _ => {
+        let (token, valid_for) = (self.fetch)().await?;
+        let refresh_at = Instant::now() + valid_for.mul_f64(0.9);
+        *cached = Some((token.clone(), refresh_at));
+        Ok(Credentials::Bearer(token))
+      }
+    }
+  }
+
+  async fn invalidate(&self) -> bool {
+    *self.cached.lock().await = None;
+    true
+  }
+}
+
+/// Attaches credentials from a [CredentialsProvider] to every call made over
+/// another transport.
+pub struct WithCredentials<T> {
+  inner: T,
+  provider: Box<dyn CredentialsProvider>,
+}
+
+impl<T> WithCredentials<T> {
+  pub fn new(inner: T, provider: impl CredentialsProvider + 'static) -> Self {
+    Self { inner, provider: Box::new(provider) }
+  }
+}
+
+impl<T: ClientTransport> WithCredentials<T> {
+  async fn attempt(
+    &mut self,
+    mut request: RequestFrame,
+  ) -> Result<ResponseFrame, ClientError> {
+    let credentials = self.provider.credentials().await?;
+    credentials.attach(&mut request.metadata);
+    self.inner.call(request).await
+  }
+}
+
+#[async_trait]
+impl<T: ClientTransport> ClientTransport for WithCredentials<T> {
+  async fn call(
+    &mut self,
+    request: RequestFrame,
+  ) -> Result<ResponseFrame, ClientError> {
+    let response = self.attempt(request.clone()).await?;
+    match &response {
+      ResponseFrame::Error(err)
+        if err.code() == Code::Unauthenticated
+          && self.provider.invalidate().await =>
+      {
+        self.attempt(request).await
+      }
+      _ => Ok(response),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn credentials_roundtrip_through_metadata() {
+    for credentials in
+      [Credentials::Bearer("token".into()), Credentials::ApiKey("key".into())]
+    {
+      let mut metadata = Metadata::new();
+      credentials.attach(&mut metadata);
+      assert_eq!(Credentials::from_metadata(&metadata), Some(credentials));
+    }
+
+    let mut metadata = Metadata::new();
+    metadata.insert(AUTHORIZATION, "Basic dXNlcjpwYXNz".to_string());
+    assert_eq!(Credentials::from_metadata(&metadata), None);
+
+    for authorization in ["Basic dXNlcjpwYXNz", "Bearer"] {
+      metadata.insert(AUTHORIZATION, authorization);
+      metadata.insert(API_KEY, "s3cr3t");
+      assert_eq!(
+        Credentials::from_metadata(&metadata),
+        Some(Credentials::ApiKey("s3cr3t".into()))
+      );
+    }
+  }
+}
@@ -0,0 +1,274 @@
+//! Payload compression, negotiated per connection in the
+//! [crate::transport::preamble]. Each [Algorithm] is behind the cargo feature
+//! of the same name.
+//!
+//! Once an algorithm is negotiated, every request and response payload
+//! starts with a byte telling which one compressed it, `0` for none. Payloads
+//! below [Compression::with_threshold] are sent as is.
+
+// Without any algorithm there is nothing to compress with.
+#![cfg_attr(
+  not(any(feature = "zstd", feature = "lz4", feature = "gzip")),
+  allow(dead_code, unused_variables)
+)]
+
+use std::io;
+
+use bytes::{BufMut, Bytes, BytesMut};
+
+use crate::transport::preamble::Features;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+  #[cfg(feature = "zstd")]
+  Zstd, // 1
+  #[cfg(feature = "lz4")]
+  Lz4, // 2
+  #[cfg(feature = "gzip")]
+  Gzip, // 3
+}
+
+/// Decompressing to more than this fails, so a small payload can't blow up
+/// to take all memory.
+#[cfg(any(feature = "zstd", feature = "lz4", feature = "gzip"))]
+const MAX_DECOMPRESSED: usize = 16 * 1024 * 1024;
+
+impl Algorithm {
+  fn feature(self) -> Features {
+    match self {
+      #[cfg(feature = "zstd")]
+      Algorithm::Zstd => Features::ZSTD,
+      #[cfg(feature = "lz4")]
+      Algorithm::Lz4 => Features::LZ4,
+      #[cfg(feature = "gzip")]
+      Algorithm::Gzip => Features::GZIP,
+    }
+  }
+
+  fn id(self) -> u8 {
+    match self {
+      #[cfg(feature = "zstd")]
+      Algorithm::Zstd => 1,
+      #[cfg(feature = "lz4")]
+      Algorithm::Lz4 => 2,
+      #[cfg(feature = "gzip")]
+      Algorithm::Gzip => 3,
+    }
+  }
+
+  fn from_id(id: u8) -> Option<Algorithm> {
+    match id {
+      #[cfg(feature = "zstd")]
+      1 => Some(Algorithm::Zstd),
+      #[cfg(feature = "lz4")]
+      2 => Some(Algorithm::Lz4),
+      #[cfg(feature = "gzip")]
+      3 => Some(Algorithm::Gzip),
+      _ => None,
+    }
+  }
+
+  fn compress(self, data: &[u8]) -> io::Result<Vec<u8>> {
+    #[cfg(any(feature = "lz4", feature = "gzip"))]
+    use std::io::Write;
+
+    match self {
+      #[cfg(feature = "zstd")]
+      Algorithm::Zstd => zstd::stream::encode_all(data, 0),
+      #[cfg(feature = "lz4")]
+      Algorithm::Lz4 => {
+        let mut encoder = lz4_flex::frame::FrameEncoder::new(Vec::new());
+        encoder.write_all(data)?;
+        encoder.finish().map_err(io::Error::other)
+      }
+      #[cfg(feature = "gzip")]
+      Algorithm::Gzip => {
+        let mut encoder = flate2::write::GzEncoder::new(
+          Vec::new(),
+          flate2::Compression::default(),
+        );
+        encoder.write_all(data)?;
+        encoder.finish()
+      }
+    }
+  }
+
+  fn decompress(self, data: &[u8]) -> io::Result<Vec<u8>> {
+    match self {
+      #[cfg(feature = "zstd")]
+      Algorithm::Zstd => read_limited(zstd::stream::read::Decoder::new(data)?),
+      #[cfg(feature = "lz4")]
+      Algorithm::Lz4 => read_limited(lz4_flex::frame::FrameDecoder::new(data)),
+      #[cfg(feature = "gzip")]
+      Algorithm::Gzip => read_limited(flate2::read::GzDecoder::new(data)),
+    }
+  }
+}
+
+#[cfg(any(feature = "zstd", feature = "lz4", feature = "gzip"))]
+fn read_limited(reader: impl io::Read) -> io::Result<Vec<u8>> {
+  use std::io::Read;
+
+  let mut data = Vec::new();
+  reader.take(MAX_DECOMPRESSED as u64 + 1).read_to_end(&mut data)?;
+  if data.len() > MAX_DECOMPRESSED {
+    return Err(invalid("decompressed payload is too large"));
+  }
+  Ok(data)
+}
+
+fn invalid(message: &'static str) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Which algorithms one side offers, none by default.
+#[derive(Debug, Clone)]
+pub struct Compression {
+  algorithms: Vec<Algorithm>,
+  threshold: usize,
+}
+
+impl Default for Compression {
+  fn default() -> Self {
+    Self { algorithms: Vec::new(), threshold: 1024 }
+  }
+}
+
+impl Compression {
+  /// Offers `algorithms`. This side compresses with the first one the peer
+  /// supports as well, and decompresses any of them.
+  pub fn new(algorithms: impl IntoIterator<Item = Algorithm>) -> Self {
+    Self { algorithms: algorithms.into_iter().collect(), ..Self::default() }
+  }
+
+  /// Payloads smaller than `bytes` aren't compressed, 1024 by default.
+  pub fn with_threshold(mut self, bytes: usize) -> Self {
+    self.threshold = bytes;
+    self
+  }
+
+  pub(crate) fn features(&self) -> Features {
+    self.algorithms.iter().fold(Features::empty(), |features, algorithm| {
+      features | algorithm.feature()
+    })
+  }
+
+  /// What to do with payloads on a connection that negotiated `features`.
+  pub(crate) fn negotiated(&self, features: Features) -> Payloads {
+    let enabled = features & self.features();
+    Payloads {
+      enabled,
+      send: self
+        .algorithms
+        .iter()
+        .copied()
+        .find(|algorithm| enabled.contains(algorithm.feature())),
+      threshold: self.threshold,
+    }
+  }
+}
+
+/// Compresses and decompresses the payloads of one connection.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Payloads {
+  enabled: Features,
+  send: Option<Algorithm>,
+  threshold: usize,
+}
+
+impl Payloads {
+  pub(crate) fn compress(&self, payload: Bytes) -> io::Result<Bytes> {
+    if self.enabled.is_empty() {
+      return Ok(payload); // No flag either.
+    }
+
+    if let Some(algorithm) = self.send {
+      if payload.len() >= self.threshold {
+        let compressed = algorithm.compress(&payload)?;
+        // Incompressible data only gets bigger.
+        if compressed.len() < payload.len() {
+          return Ok(flagged(algorithm.id(), &compressed));
+        }
+      }
+    }
+    Ok(flagged(0, &payload))
+  }
+
+  pub(crate) fn decompress(&self, payload: Bytes) -> io::Result<Bytes> {
+    if self.enabled.is_empty() {
+      return Ok(payload);
+    }
+
+    let Some((&id, data)) = payload.split_first() else {
+      return Err(invalid("payload is missing its compression flag"));
+    };
+    if id == 0 {
+      return Ok(payload.slice(1..));
+    }
+    match Algorithm::from_id(id) {
+      Some(algorithm) if self.enabled.contains(algorithm.feature()) => {
+        algorithm.decompress(data).map(Bytes::from)
+      }
+      _ => Err(invalid("payload compressed with an algorithm not negotiated")),
+    }
+  }
+}
+
+fn flagged(id: u8, data: &[u8]) -> Bytes {
+  let mut payload = BytesMut::with_capacity(1 + data.len());
+  payload.put_u8(id);
+  payload.put_slice(data);
+  payload.freeze()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn nothing_negotiated_leaves_payloads_alone() {
+    let payloads = Compression::default().negotiated(Features::empty());
+    let payload = Bytes::from_static(b"payload");
+
+    assert_eq!(payloads.compress(payload.clone()).unwrap(), payload);
+    assert_eq!(payloads.decompress(payload.clone()).unwrap(), payload);
+  }
+
+  #[cfg(all(feature = "zstd", feature = "lz4", feature = "gzip"))]
+  #[test]
+  fn roundtrip_above_threshold() {
+    let payload = Bytes::from("webcontr ".repeat(200));
+
+    for algorithm in [Algorithm::Zstd, Algorithm::Lz4, Algorithm::Gzip] {
+      let compression = Compression::new([algorithm]).with_threshold(100);
+      let payloads = compression.negotiated(Features::COMPRESSION);
+
+      let compressed = payloads.compress(payload.clone()).unwrap();
+      assert_eq!(compressed[0], algorithm.id());
+      assert!(compressed.len() < payload.len());
+      assert_eq!(payloads.decompress(compressed).unwrap(), payload);
+
+      let small = payloads.compress(Bytes::from_static(b"small")).unwrap();
+      assert_eq!(&small[..], b"\0small");
+      assert_eq!(&payloads.decompress(small).unwrap()[..], b"small");
+    }
+  }
+
+  #[cfg(all(feature = "zstd", feature = "gzip"))]
+  #[test]
+  fn only_negotiated_algorithms_are_used() {
+    let compression =
+      Compression::new([Algorithm::Zstd, Algorithm::Gzip]).with_threshold(0);
+    let payloads = compression.negotiated(Features::GZIP);
+    let payload = Bytes::from("webcontr ".repeat(20));
+
+    let compressed = payloads.compress(payload.clone()).unwrap();
+    assert_eq!(compressed[0], Algorithm::Gzip.id());
+
+    let zstd = Compression::new([Algorithm::Zstd])
+      .with_threshold(0)
+      .negotiated(Features::ZSTD);
+    let compressed = zstd.compress(payload).unwrap();
+    assert!(payloads.decompress(compressed).is_err());
+  }
+}
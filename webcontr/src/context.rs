@@ -0,0 +1,129 @@
+use std::{future::Future, net::SocketAddr, sync::Arc};
+
+use bytes::Bytes;
+
+use crate::{auth::Principal, metadata::Metadata};
+
+tokio::task_local! {
+  static CONTEXT: Context;
+}
+
+/// Who sent a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PeerInfo {
+  Tcp(SocketAddr),
+  #[cfg(unix)]
+  Unix(UnixPeer),
+  /// The request came from the same process, e.g. over
+  /// [crate::transport::channel].
+  Local,
+}
+
+/// Credentials of the process on the other end of a Unix socket, as reported
+/// by the kernel (`SO_PEERCRED`).
+#[cfg(unix)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnixPeer {
+  pub uid: tokio::net::unix::uid_t,
+  pub gid: tokio::net::unix::gid_t,
+  pub pid: Option<tokio::net::unix::pid_t>,
+}
+
+/// The certificate chain a client was verified with during the TLS
+/// handshake, see [crate::tls::TLSPaths::with_client_ca].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientIdentity {
+  certificates: Arc<[Bytes]>,
+}
+
+impl ClientIdentity {
+  /// `certificates` are DER encoded, the client's own first. Returns `None`
+  /// for an empty chain.
+  pub fn new(certificates: impl IntoIterator<Item = Bytes>) -> Option<Self> {
+    let certificates: Arc<[Bytes]> = certificates.into_iter().collect();
+    (!certificates.is_empty()).then_some(Self { certificates })
+  }
+
+  /// The DER encoded certificate of the client itself.
+  pub fn certificate(&self) -> &Bytes {
+    &self.certificates[0]
+  }
+
+  /// The whole chain as the client sent it, its own certificate first.
+  pub fn certificates(&self) -> &[Bytes] {
+    &self.certificates
+  }
+}
+
+/// Information about the request currently being handled.
+#[derive(Debug, Clone)]
+pub struct Context {
+  peer: PeerInfo,
+  client_identity: Option<ClientIdentity>,
+  metadata: Metadata,
+  principal: Option<Principal>,
+}
+
+impl Context {
+  pub(crate) fn new(peer: PeerInfo, metadata: Metadata) -> Self {
+    Self { peer, client_identity: None, metadata, principal: None }
+  }
+
+  pub(crate) fn with_client_identity(
+    mut self,
+    identity: Option<ClientIdentity>,
+  ) -> Self {
+    self.client_identity = identity;
+    self
+  }
+
+  pub fn peer(&self) -> &PeerInfo {
+    &self.peer
+  }
+
+  /// The certificate the client authenticated with, if it connected over
+  /// TLS and the server asked for one.
+  pub fn client_identity(&self) -> Option<&ClientIdentity> {
+    self.client_identity.as_ref()
+  }
+
+  /// Metadata the client sent along with the request.
+  pub fn metadata(&self) -> &Metadata {
+    &self.metadata
+  }
+
+  /// Who made the request, if the server authenticates requests, see
+  /// [crate::auth].
+  pub fn principal(&self) -> Option<&Principal> {
+    self.principal.as_ref()
+  }
+
+  pub(crate) fn set_principal(&mut self, principal: Principal) {
+    self.principal = Some(principal);
+  }
+
+  pub(crate) fn scope<F: Future>(
+    self,
+    future: F,
+  ) -> impl Future<Output = F::Output> {
+    CONTEXT.scope(self, future)
+  }
+}
+
+/// Returns the context of the request being handled by the calling task.
+///
+/// Only available from inside a service method, tasks spawned from it have
+/// to carry the context themselves.
+pub fn current() -> Option<Context> {
+  CONTEXT.try_with(Context::clone).ok()
+}
+
+/// Shorthand for the peer of [current].
+pub fn peer() -> Option<PeerInfo> {
+  CONTEXT.try_with(|ctx| ctx.peer.clone()).ok()
+}
+
+/// Shorthand for the principal of [current].
+pub fn principal() -> Option<Principal> {
+  CONTEXT.try_with(|ctx| ctx.principal.clone()).ok().flatten()
+}
@@ -0,0 +1,161 @@
+//! A standard health checking service, modeled after `grpc.health.v1`.
+//!
+//! ```ignore
+//! let (reporter, health) = webcontr::health::reporter();
+//! reporter.set_serving("Greeter");
+//!
+//! let server = Server::default()
+//!   .add_service(GreeterServer.into_serve())
+//!   .add_service(health);
+//! ```
+//!
+//! The empty service name stands for the server as a whole and starts out as
+//! [ServingStatus::Serving].
+
+use std::{collections::HashMap, sync::Arc};
+
+use futures_util::{stream, Stream};
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+
+use crate::{
+  prelude::*, transport::frame::ResponseErrorKind, ClientError, ServiceName,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ServingStatus {
+  /// No status has been reported for the service.
+  Unknown,
+  Serving,
+  NotServing,
+}
+
+#[crate::service]
+pub trait Health {
+  /// Status of `service`, or of the whole server if it is empty.
+  async fn check(service: String) -> ServingStatus;
+
+  /// Waits until the status of `service` is no longer `last` and returns the
+  /// new one.
+  async fn watch(service: String, last: ServingStatus) -> ServingStatus;
+}
+
+type Statuses = HashMap<String, ServingStatus>;
+
+/// Updates the statuses reported by a [HealthService].
+#[derive(Clone)]
+pub struct HealthReporter {
+  statuses: Arc<watch::Sender<Statuses>>,
+}
+
+impl HealthReporter {
+  pub fn set_status(&self, service: impl Into<String>, status: ServingStatus) {
+    let service = service.into();
+    self.statuses.send_if_modified(|statuses| {
+      statuses.insert(service, status) != Some(status)
+    });
+  }
+
+  pub fn set_serving(&self, service: impl Into<String>) {
+    self.set_status(service, ServingStatus::Serving);
+  }
+
+  pub fn set_not_serving(&self, service: impl Into<String>) {
+    self.set_status(service, ServingStatus::NotServing);
+  }
+
+  /// Shorthand for [HealthReporter::set_serving] with the name of a service
+  /// added to the server.
+  pub fn set_service_serving<S: ServiceName>(&self, service: &S) {
+    self.set_serving(service.name());
+  }
+
+  /// Forgets `service`, it is reported as [ServingStatus::Unknown] again.
+  pub fn clear(&self, service: &str) {
+    self
+      .statuses
+      .send_if_modified(|statuses| statuses.remove(service).is_some());
+  }
+}
+
+/// Answers [Health] requests with the statuses of its [HealthReporter].
+#[derive(Clone)]
+pub struct HealthService {
+  statuses: watch::Receiver<Statuses>,
+}
+
+/// Creates a reporter and the service it reports to, ready to be added to a
+/// [crate::Server].
+pub fn reporter() -> (HealthReporter, HealthServe<HealthService>) {
+  let statuses = HashMap::from([(String::new(), ServingStatus::Serving)]);
+  let (sender, receiver) = watch::channel(statuses);
+
+  (
+    HealthReporter { statuses: Arc::new(sender) },
+    HealthService { statuses: receiver }.into_serve(),
+  )
+}
+
+fn status_of(statuses: &Statuses, service: &str) -> ServingStatus {
+  statuses.get(service).copied().unwrap_or(ServingStatus::Unknown)
+}
+
+#[crate::async_trait]
+impl Health for HealthService {
+  async fn check(&self, service: String) -> ServingStatus {
+    status_of(&self.statuses.borrow(), &service)
+  }
+
+  async fn watch(&self, service: String, last: ServingStatus) -> ServingStatus {
+    let mut statuses = self.statuses.clone();
+    loop {
+      let status = status_of(&statuses.borrow_and_update(), &service);
+      if status != last || statuses.changed().await.is_err() {
+        return status;
+      }
+    }
+  }
+}
+
+impl HealthClient {
+  /// Whether `service` reports [ServingStatus::Serving].
+  pub async fn is_serving(
+    &mut self,
+    service: &str,
+  ) -> Result<bool, ClientError> {
+    Ok(self.check(service.to_string()).await? == ServingStatus::Serving)
+  }
+
+  /// Yields the current status of `service`, then every change to it.
+  ///
+  /// Calls that hit the server's request timeout are retried, so the stream
+  /// only ends on other errors.
+  pub fn watch_status(
+    &mut self,
+    service: impl Into<String>,
+  ) -> impl Stream<Item = Result<ServingStatus, ClientError>> + '_ {
+    let service = service.into();
+    stream::unfold(Some((self, None)), move |state| {
+      let service = service.clone();
+      async move {
+        let (client, last) = state?;
+        let result = match last {
+          None => client.check(service).await,
+          Some(last) => loop {
+            match client.watch(service.clone(), last).await {
+              Err(ClientError::ServerError(ResponseErrorKind::Timeout)) => {
+                continue
+              }
+              result => break result,
+            }
+          },
+        };
+
+        match result {
+          Ok(status) => Some((Ok(status), Some((client, Some(status))))),
+          Err(err) => Some((Err(err), None)),
+        }
+      }
+    })
+  }
+}
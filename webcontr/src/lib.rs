@@ -1,6 +1,20 @@
+extern crate self as webcontr;
+
+pub mod accept;
+pub mod auth;
+pub mod compression;
+pub mod context;
+pub mod health;
+mod limit;
+pub mod metadata;
+pub mod metrics;
 pub mod prelude;
+pub mod rate_limit;
+pub mod reflection;
 pub mod serve;
 mod server;
+pub mod status;
+mod trace;
 pub mod transport;
 use std::io;
 mod utils;
@@ -13,6 +27,7 @@ pub use server::*;
 pub use async_trait::async_trait;
 
 use bytes::Bytes;
+use status::Code;
 use transport::frame::ResponseErrorKind;
 pub use webcontr_macros::service;
 
@@ -23,6 +38,22 @@ pub trait Serve: Send + Sync {
 
 pub trait ServiceName {
   fn name(&self) -> &'static str;
+
+  /// Method names, in the order they are declared in the service trait.
+  fn methods(&self) -> &'static [&'static str] {
+    &[]
+  }
+
+  /// Timeouts declared with `#[timeout]`, in the same order as
+  /// [ServiceName::methods].
+  fn timeouts(&self) -> &'static [Option<std::time::Duration>] {
+    &[]
+  }
+
+  /// Methods and their signatures, served by [Server::with_reflection].
+  fn descriptor(&self) -> reflection::ServiceDescriptor {
+    reflection::ServiceDescriptor::unknown(self.name())
+  }
 }
 
 #[cfg(test)]
@@ -32,10 +63,94 @@ use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum ClientError {
+  /// The server couldn't be reached.
+  #[error("failed to connect: {0}")]
+  ConnectError(io::Error),
+  /// TLS couldn't be set up, or its handshake failed.
+  #[error("tls error: {0}")]
+  TlsError(io::Error),
+  /// The connection failed once established.
   #[error("io error: {0}")]
-  IoError(io::Error),
+  IoError(#[from] io::Error),
   #[error("server error: {0}")]
   ServerError(ResponseErrorKind),
+  /// The request couldn't be encoded.
   #[error("encoding error: {0}")]
   EncodingError(Box<bincode::ErrorKind>),
+  /// The response couldn't be decoded into what the method returns.
+  #[error("decoding error: {0}")]
+  DecodingError(Box<bincode::ErrorKind>),
+  /// The server sent something the protocol doesn't allow.
+  #[error("protocol violation: {0}")]
+  ProtocolError(String),
+  /// The server answered with the response of another method than the one
+  /// called.
+  #[error("response doesn't match method {0}")]
+  MismatchedResponse(&'static str),
+  /// No response arrived before the client side deadline.
+  #[error("request timed out")]
+  Timeout,
+}
+
+impl ClientError {
+  /// How long the server asked to wait before retrying, if it rate limited
+  /// the request.
+  pub fn retry_after(&self) -> Option<std::time::Duration> {
+    match self {
+      ClientError::ServerError(ResponseErrorKind::RateLimited {
+        retry_after,
+      }) => Some(*retry_after),
+      _ => None,
+    }
+  }
+
+  /// Whether making the same call again may succeed. After a timeout or a
+  /// broken connection the server may have handled the call already, so
+  /// only retry calls that are safe to repeat.
+  pub fn is_retryable(&self) -> bool {
+    match self {
+      ClientError::ConnectError(_) | ClientError::Timeout => true,
+      ClientError::IoError(err) => matches!(
+        err.kind(),
+        io::ErrorKind::ConnectionReset
+          | io::ErrorKind::ConnectionAborted
+          | io::ErrorKind::BrokenPipe
+          | io::ErrorKind::UnexpectedEof
+          | io::ErrorKind::TimedOut
+      ),
+      ClientError::ServerError(kind) => matches!(
+        kind.code(),
+        Code::DeadlineExceeded
+          | Code::Unavailable
+          | Code::ResourceExhausted
+          | Code::Aborted
+      ),
+      _ => false,
+    }
+  }
+
+  /// Whether a deadline passed, on this side or the server's.
+  pub fn is_timeout(&self) -> bool {
+    match self {
+      ClientError::Timeout => true,
+      ClientError::ServerError(kind) => kind.code() == Code::DeadlineExceeded,
+      ClientError::IoError(err) => err.kind() == io::ErrorKind::TimedOut,
+      _ => false,
+    }
+  }
+
+  /// Sorts out what [transport::connection::Connect::connect] failed with,
+  /// TLS failures carry a `rustls::Error`.
+  pub(crate) fn connecting(err: io::Error) -> Self {
+    #[cfg(feature = "tls")]
+    if err.get_ref().is_some_and(|err| err.is::<tokio_rustls::rustls::Error>())
+    {
+      return ClientError::TlsError(err);
+    }
+    ClientError::ConnectError(err)
+  }
+
+  pub(crate) fn protocol(err: impl std::fmt::Display) -> Self {
+    ClientError::ProtocolError(err.to_string())
+  }
 }
@@ -0,0 +1,119 @@
+use std::sync::{
+  atomic::{AtomicUsize, Ordering},
+  Arc,
+};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// What happens to connections and requests arriving while a limit set on
+/// [crate::serve::ServerServe] is reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Overload {
+  /// Answer right away with
+  /// [crate::transport::frame::ResponseErrorKind::Overloaded].
+  #[default]
+  Reject,
+  /// Wait for a slot to free up, with at most this many waiting. The ones
+  /// beyond are rejected.
+  Queue(usize),
+}
+
+/// A maximum number of things running at once.
+#[derive(Clone)]
+pub(crate) struct Limit {
+  semaphore: Arc<Semaphore>,
+  queued: Arc<AtomicUsize>,
+  overload: Overload,
+}
+
+impl Limit {
+  pub(crate) fn new(max: usize, overload: Overload) -> Self {
+    Self {
+      semaphore: Arc::new(Semaphore::new(max)),
+      queued: Arc::new(AtomicUsize::new(0)),
+      overload,
+    }
+  }
+
+  /// Takes a slot, or returns `None` if the caller should be rejected.
+  pub(crate) async fn acquire(&self) -> Option<OwnedSemaphorePermit> {
+    if let Ok(permit) = self.semaphore.clone().try_acquire_owned() {
+      return Some(permit);
+    }
+
+    let Overload::Queue(max_queued) = self.overload else {
+      return None;
+    };
+
+    let queued = self.queued.fetch_add(1, Ordering::AcqRel);
+    let _dequeue = Dequeue(&self.queued);
+    if queued >= max_queued {
+      return None;
+    }
+
+    self.semaphore.clone().acquire_owned().await.ok()
+  }
+}
+
+struct Dequeue<'a>(&'a AtomicUsize);
+
+impl Drop for Dequeue<'_> {
+  fn drop(&mut self) {
+    self.0.fetch_sub(1, Ordering::AcqRel);
+  }
+}
+
+/// The limit is reached and the [Overload] policy says to reject.
+pub(crate) struct Rejected;
+
+/// Takes a slot of `limit`, if there is one.
+pub(crate) async fn acquire(
+  limit: Option<&Limit>,
+) -> Result<Option<OwnedSemaphorePermit>, Rejected> {
+  match limit {
+    Some(limit) => limit.acquire().await.map(Some).ok_or(Rejected),
+    None => Ok(None),
+  }
+}
+
+/// Limits shared by every listener of a [crate::serve::ServerServe].
+#[derive(Clone)]
+pub(crate) struct Limits {
+  pub(crate) connections: Option<Limit>,
+  pub(crate) in_flight: Option<Limit>,
+  pub(crate) in_flight_per_connection: Option<usize>,
+  pub(crate) overload: Overload,
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[tokio::test]
+  async fn reject_when_full() {
+    let limit = Limit::new(1, Overload::Reject);
+    let permit = limit.acquire().await.unwrap();
+    assert!(limit.acquire().await.is_none());
+
+    drop(permit);
+    assert!(limit.acquire().await.is_some());
+  }
+
+  #[tokio::test]
+  async fn queue_is_bounded() {
+    let limit = Limit::new(1, Overload::Queue(1));
+    let permit = limit.acquire().await.unwrap();
+
+    let queued = tokio::spawn({
+      let limit = limit.clone();
+      async move { limit.acquire().await.is_some() }
+    });
+    tokio::task::yield_now().await;
+
+    // The queue is full.
+    assert!(limit.acquire().await.is_none());
+
+    drop(permit);
+    assert!(queued.await.unwrap());
+  }
+}
@@ -0,0 +1,66 @@
+/// Key/value pairs sent along with a request, similar to HTTP headers.
+///
+/// Keys are case-insensitive and stored in lowercase. A request carries at
+/// most [crate::transport::frame::MAX_METADATA_ENTRIES] entries taking up to
+/// [crate::transport::frame::MAX_METADATA_LEN] bytes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Metadata {
+  entries: Vec<(String, String)>,
+}
+
+impl Metadata {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Sets `key` to `value`, replacing and returning a previous value.
+  pub fn insert(
+    &mut self,
+    key: impl Into<String>,
+    value: impl Into<String>,
+  ) -> Option<String> {
+    let key = key.into().to_ascii_lowercase();
+    let value = value.into();
+
+    match self.entries.iter_mut().find(|(k, _)| *k == key) {
+      Some((_, existing)) => Some(std::mem::replace(existing, value)),
+      None => {
+        self.entries.push((key, value));
+        None
+      }
+    }
+  }
+
+  /// Adds an entry without looking for one with the same key, for decoding
+  /// requests where that would take a pass over the entries for each one.
+  /// Duplicate keys are kept, [Metadata::get] returns the first.
+  pub(crate) fn append(&mut self, key: String, value: String) {
+    self.entries.push((key.to_ascii_lowercase(), value));
+  }
+
+  pub fn get(&self, key: &str) -> Option<&str> {
+    self
+      .entries
+      .iter()
+      .find(|(k, _)| k.eq_ignore_ascii_case(key))
+      .map(|(_, value)| value.as_str())
+  }
+
+  pub fn remove(&mut self, key: &str) -> Option<String> {
+    let index =
+      self.entries.iter().position(|(k, _)| k.eq_ignore_ascii_case(key))?;
+    Some(self.entries.remove(index).1)
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+    self.entries.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+  }
+
+  pub fn len(&self) -> usize {
+    self.entries.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.entries.is_empty()
+  }
+}
@@ -0,0 +1,328 @@
+//! Request, connection and traffic metrics, recorded through the [metrics]
+//! facade when the `metrics` feature is enabled.
+//!
+//! Server side:
+//! - `webcontr_server_requests_total{service, method, status}`
+//! - `webcontr_server_errors_total{service, method, kind}`
+//! - `webcontr_server_request_duration_seconds{service, method}`
+//! - `webcontr_server_requests_in_flight{service}`
+//! - `webcontr_server_connections_active`
+//! - `webcontr_server_received_bytes_total` and `webcontr_server_sent_bytes_total`
+//!
+//! The client records the same request metrics under `webcontr_client_`.
+//!
+//! `status` is `ok` or the [ResponseErrorKind::name] of the error, services
+//! and methods that are not registered are labelled `unknown`. Calls that
+//! failed without an error response are labelled by what failed instead,
+//! e.g. `connect`, `io` or `decoding` on the client. Connections the server
+//! failed to read a request from or send a response to count as `read` and
+//! `write` errors of service and method `unknown`.
+//!
+//! The byte totals count payloads as handlers see them, uncompressed and
+//! without framing, command or metadata.
+
+use std::time::Duration;
+
+#[cfg(feature = "metrics")]
+use crate::transport::frame::ResponseErrorKind;
+use crate::{transport::frame::ResponseFrame, ClientError};
+
+#[cfg(feature = "prometheus")]
+pub use prometheus::{install_prometheus_recorder, serve_prometheus};
+
+#[derive(Clone, Copy)]
+pub(crate) enum Side {
+  Server,
+  Client,
+}
+
+#[cfg(feature = "metrics")]
+impl Side {
+  fn name(self, metric: &str) -> String {
+    match self {
+      Side::Server => format!("webcontr_server_{metric}"),
+      Side::Client => format!("webcontr_client_{metric}"),
+    }
+  }
+}
+
+/// Counts a request as in flight until dropped.
+pub(crate) struct InFlight {
+  #[cfg(feature = "metrics")]
+  gauge: metrics::Gauge,
+}
+
+impl InFlight {
+  pub(crate) fn new(side: Side, service: &'static str) -> Self {
+    #[cfg(feature = "metrics")]
+    {
+      let gauge =
+        metrics::gauge!(side.name("requests_in_flight"), "service" => service);
+      gauge.increment(1);
+      InFlight { gauge }
+    }
+    #[cfg(not(feature = "metrics"))]
+    {
+      let _ = (side, service);
+      InFlight {}
+    }
+  }
+}
+
+impl Drop for InFlight {
+  fn drop(&mut self) {
+    #[cfg(feature = "metrics")]
+    self.gauge.decrement(1);
+  }
+}
+
+/// Counts a server connection as active until dropped.
+pub(crate) struct ActiveConnection {
+  #[cfg(feature = "metrics")]
+  gauge: metrics::Gauge,
+}
+
+impl ActiveConnection {
+  pub(crate) fn new() -> Self {
+    #[cfg(feature = "metrics")]
+    {
+      let gauge = metrics::gauge!("webcontr_server_connections_active");
+      gauge.increment(1);
+      ActiveConnection { gauge }
+    }
+    #[cfg(not(feature = "metrics"))]
+    ActiveConnection {}
+  }
+}
+
+impl Drop for ActiveConnection {
+  fn drop(&mut self) {
+    #[cfg(feature = "metrics")]
+    self.gauge.decrement(1);
+  }
+}
+
+pub(crate) fn record_request(
+  side: Side,
+  service: &'static str,
+  method: &'static str,
+  request_size: usize,
+  response: &ResponseFrame,
+  elapsed: Duration,
+) {
+  #[cfg(feature = "metrics")]
+  {
+    let (status, response_size) = match response {
+      ResponseFrame::Payload(payload) => ("ok", payload.len()),
+      ResponseFrame::Error(err) => (err.name(), 0),
+    };
+    let error = matches!(response, ResponseFrame::Error(_));
+    record(side, service, method, status, error, elapsed);
+    record_bytes(side, request_size, response_size);
+  }
+  #[cfg(not(feature = "metrics"))]
+  let _ = (side, service, method, request_size, response, elapsed);
+}
+
+/// A call that failed on the client without an error response, e.g. because
+/// the connection broke or the response couldn't be decoded.
+pub(crate) fn record_failed_request(
+  service: &'static str,
+  method: &'static str,
+  request_size: usize,
+  response_size: usize,
+  err: &ClientError,
+  elapsed: Duration,
+) {
+  #[cfg(feature = "metrics")]
+  {
+    let kind = client_error_kind(err);
+    record(Side::Client, service, method, kind, true, elapsed);
+    record_bytes(Side::Client, request_size, response_size);
+  }
+  #[cfg(not(feature = "metrics"))]
+  let _ = (service, method, request_size, response_size, err, elapsed);
+}
+
+/// A server connection that failed to read a request or send a response,
+/// `kind` is `read` or `write`.
+pub(crate) fn record_connection_error(kind: &'static str) {
+  #[cfg(feature = "metrics")]
+  metrics::counter!(
+    Side::Server.name("errors_total"),
+    "service" => "unknown",
+    "method" => "unknown",
+    "kind" => kind,
+  )
+  .increment(1);
+  #[cfg(not(feature = "metrics"))]
+  let _ = kind;
+}
+
+#[cfg(feature = "metrics")]
+fn record(
+  side: Side,
+  service: &'static str,
+  method: &'static str,
+  status: &'static str,
+  error: bool,
+  elapsed: Duration,
+) {
+  metrics::counter!(
+    side.name("requests_total"),
+    "service" => service,
+    "method" => method,
+    "status" => status,
+  )
+  .increment(1);
+  if error {
+    metrics::counter!(
+      side.name("errors_total"),
+      "service" => service,
+      "method" => method,
+      "kind" => status,
+    )
+    .increment(1);
+  }
+  metrics::histogram!(
+    side.name("request_duration_seconds"),
+    "service" => service,
+    "method" => method,
+  )
+  .record(elapsed);
+}
+
+#[cfg(feature = "metrics")]
+fn record_bytes(side: Side, request_size: usize, response_size: usize) {
+  let (received, sent) = match side {
+    Side::Server => (request_size, response_size),
+    Side::Client => (response_size, request_size),
+  };
+  metrics::counter!(side.name("received_bytes_total"))
+    .increment(received as u64);
+  metrics::counter!(side.name("sent_bytes_total")).increment(sent as u64);
+}
+
+#[cfg(feature = "metrics")]
+fn client_error_kind(err: &ClientError) -> &'static str {
+  match err {
+    ClientError::ConnectError(_) => "connect",
+    ClientError::TlsError(_) => "tls",
+    ClientError::IoError(_) => "io",
+    ClientError::ServerError(err) => err.name(),
+    ClientError::EncodingError(_) => "encoding",
+    ClientError::DecodingError(_) => "decoding",
+    ClientError::ProtocolError(_) => "protocol",
+    ClientError::MismatchedResponse(_) => "mismatched_response",
+    ClientError::Timeout => ResponseErrorKind::Timeout.name(),
+  }
+}
+
+#[cfg(feature = "prometheus")]
+mod prometheus {
+  use std::{io, time::Duration};
+
+  use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+  use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    time::{sleep, timeout},
+  };
+
+  use crate::trace;
+
+  /// Installs a Prometheus recorder as the global [metrics] recorder.
+  ///
+  /// Fails if another global recorder is already installed.
+  pub fn install_prometheus_recorder() -> io::Result<PrometheusHandle> {
+    PrometheusBuilder::new().install_recorder().map_err(io::Error::other)
+  }
+
+  /// Answers `GET /metrics` on `listener` with the Prometheus text format of
+  /// everything recorded through `handle`. Failing to accept a connection is
+  /// logged and retried, so this only returns once dropped.
+  pub async fn serve_prometheus(
+    listener: TcpListener,
+    handle: PrometheusHandle,
+  ) -> io::Result<()> {
+    let mut backoff = MIN_ACCEPT_BACKOFF;
+    loop {
+      let stream = match listener.accept().await {
+        Ok((stream, _)) => {
+          backoff = MIN_ACCEPT_BACKOFF;
+          stream
+        }
+        Err(err) => {
+          trace::warn_event!(error = %err, "failed to accept scrape");
+          // Like the server's accept loop, only back off from errors that
+          // aren't about a single connection.
+          if !matches!(
+            err.kind(),
+            io::ErrorKind::ConnectionRefused
+              | io::ErrorKind::ConnectionAborted
+              | io::ErrorKind::ConnectionReset
+          ) {
+            sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_ACCEPT_BACKOFF);
+          }
+          continue;
+        }
+      };
+      let handle = handle.clone();
+      tokio::spawn(async move {
+        let _ = respond(stream, &handle).await;
+      });
+    }
+  }
+
+  const MIN_ACCEPT_BACKOFF: Duration = Duration::from_millis(5);
+  const MAX_ACCEPT_BACKOFF: Duration = Duration::from_secs(1);
+
+  const MAX_REQUEST_HEAD: usize = 8 * 1024;
+
+  /// How long a scraper may take to send its request.
+  const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+  async fn respond(
+    mut stream: TcpStream,
+    handle: &PrometheusHandle,
+  ) -> io::Result<()> {
+    let mut head = Vec::new();
+    let mut buf = [0; 1024];
+    let read_head = async {
+      while !head.windows(4).any(|window| window == b"\r\n\r\n") {
+        let read = stream.read(&mut buf).await?;
+        if read == 0 || head.len() + read > MAX_REQUEST_HEAD {
+          return Ok(false);
+        }
+        head.extend_from_slice(&buf[..read]);
+      }
+      io::Result::Ok(true)
+    };
+    match timeout(READ_TIMEOUT, read_head).await {
+      Ok(Ok(true)) => {}
+      Ok(Ok(false)) | Err(_) => return Ok(()),
+      Ok(Err(err)) => return Err(err),
+    }
+
+    let response = match head.starts_with(b"GET /metrics ") {
+      true => {
+        let body = handle.render();
+        format!(
+          "HTTP/1.1 200 OK\r\n\
+           Content-Type: text/plain; version=0.0.4\r\n\
+           Content-Length: {}\r\n\
+           Connection: close\r\n\r\n{body}",
+          body.len()
+        )
+      }
+      false => "HTTP/1.1 404 Not Found\r\n\
+                Content-Length: 0\r\n\
+                Connection: close\r\n\r\n"
+        .to_string(),
+    };
+
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+  }
+}
@@ -0,0 +1,276 @@
+//! Token bucket rate limiting per client, see [crate::Server::with_rate_limiter].
+//!
+//! ```ignore
+//! let limiter = RateLimiter::new(RateLimitKey::Metadata("authorization"))
+//!   .with_default(Rate::per_second(100).with_burst(200))
+//!   .with_method("Store", "export", Rate::per_second(1));
+//! ```
+//!
+//! Limited requests are answered with [ResponseErrorKind::RateLimited], which
+//! tells the client how long to wait. Generated clients can retry on their own,
+//! see their `with_rate_limit_retries`.
+
+use std::{
+  collections::HashMap,
+  sync::{Arc, Mutex},
+  time::{Duration, Instant},
+};
+
+use crate::{
+  context::{Context, PeerInfo},
+  transport::frame::ResponseErrorKind,
+};
+
+/// How many requests a client may make.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rate {
+  per_second: f64,
+  burst: u32,
+}
+
+impl Rate {
+  /// Allows `requests` per second on average, and as many at once.
+  pub fn per_second(requests: u32) -> Self {
+    Self { per_second: requests as f64, burst: requests.max(1) }
+  }
+
+  /// Allows `requests` per minute on average, and as many at once.
+  pub fn per_minute(requests: u32) -> Self {
+    Self { per_second: requests as f64 / 60.0, burst: requests.max(1) }
+  }
+
+  /// How many requests may be made at once after a quiet period.
+  pub fn with_burst(mut self, burst: u32) -> Self {
+    self.burst = burst.max(1);
+    self
+  }
+}
+
+/// Which requests share a bucket.
+#[derive(Clone)]
+pub enum RateLimitKey {
+  /// The IP address for TCP, the user id for Unix sockets. In-process
+  /// requests share one bucket.
+  Peer,
+  /// The value of a metadata entry, e.g. an API token. Requests without it
+  /// share one bucket.
+  Metadata(&'static str),
+  /// Anything derived from the request, e.g. the client's certificate, see
+  /// [Context::client_identity]. Requests mapped to `None` aren't limited.
+  Custom(KeyFn),
+}
+
+type KeyFn = Arc<dyn Fn(&Context) -> Option<String> + Send + Sync>;
+
+impl RateLimitKey {
+  fn key(&self, context: &Context) -> Option<String> {
+    match self {
+      RateLimitKey::Peer => Some(match context.peer() {
+        PeerInfo::Tcp(addr) => addr.ip().to_string(),
+        #[cfg(unix)]
+        PeerInfo::Unix(peer) => format!("uid:{}", peer.uid),
+        PeerInfo::Local => "local".to_string(),
+      }),
+      RateLimitKey::Metadata(name) => {
+        Some(context.metadata().get(name).unwrap_or_default().to_string())
+      }
+      RateLimitKey::Custom(key) => key(context),
+    }
+  }
+}
+
+struct Bucket {
+  tokens: f64,
+  updated: Instant,
+}
+
+impl Bucket {
+  fn refill(&mut self, rate: &Rate, now: Instant) {
+    let elapsed = now.duration_since(self.updated).as_secs_f64();
+    self.tokens =
+      (self.tokens + elapsed * rate.per_second).min(rate.burst as f64);
+    self.updated = now;
+  }
+}
+
+/// Limits the requests of each client, by default to all methods together.
+/// Methods given their own [Rate] are counted separately.
+#[derive(Clone)]
+pub struct RateLimiter {
+  key: RateLimitKey,
+  default: Option<Rate>,
+  methods: HashMap<(String, String), Rate>,
+  buckets: Arc<Mutex<Buckets>>,
+}
+
+struct Buckets {
+  buckets: HashMap<(String, Option<(String, String)>), Bucket>,
+  /// Full buckets are dropped once there are this many, as they behave the
+  /// same as new ones.
+  sweep_at: usize,
+}
+
+const MIN_SWEEP_AT: usize = 1024;
+
+impl RateLimiter {
+  /// Doesn't limit anything until [RateLimiter::with_default] or
+  /// [RateLimiter::with_method] is set.
+  pub fn new(key: RateLimitKey) -> Self {
+    Self {
+      key,
+      default: None,
+      methods: HashMap::new(),
+      buckets: Arc::new(Mutex::new(Buckets {
+        buckets: HashMap::new(),
+        sweep_at: MIN_SWEEP_AT,
+      })),
+    }
+  }
+
+  /// Rate for methods without their own.
+  pub fn with_default(mut self, rate: Rate) -> Self {
+    self.default = Some(rate);
+    self
+  }
+
+  pub fn with_method(
+    mut self,
+    service: impl Into<String>,
+    method: impl Into<String>,
+    rate: Rate,
+  ) -> Self {
+    self.methods.insert((service.into(), method.into()), rate);
+    self
+  }
+
+  /// Takes a token for a request, or tells how long to wait for one.
+  pub(crate) fn check(
+    &self,
+    context: &Context,
+    service: &str,
+    method: Option<&str>,
+  ) -> Result<(), ResponseErrorKind> {
+    let method = method
+      .map(|method| (service.to_string(), method.to_string()))
+      .filter(|method| self.methods.contains_key(method));
+    let Some(rate) = self.rate(method.as_ref()) else {
+      return Ok(());
+    };
+    let Some(key) = self.key.key(context) else {
+      return Ok(());
+    };
+
+    let now = Instant::now();
+    let mut buckets = self.buckets.lock().unwrap();
+    buckets.sweep(self, now);
+
+    let bucket = buckets
+      .buckets
+      .entry((key, method))
+      .or_insert(Bucket { tokens: rate.burst as f64, updated: now });
+    bucket.refill(&rate, now);
+
+    if bucket.tokens >= 1.0 {
+      bucket.tokens -= 1.0;
+      return Ok(());
+    }
+
+    let retry_after = (1.0 - bucket.tokens) / rate.per_second;
+    Err(ResponseErrorKind::RateLimited {
+      retry_after: Duration::try_from_secs_f64(retry_after)
+        .unwrap_or(Duration::MAX),
+    })
+  }
+
+  fn rate(&self, method: Option<&(String, String)>) -> Option<Rate> {
+    match method {
+      Some(method) => self.methods.get(method).copied(),
+      None => self.default,
+    }
+  }
+}
+
+impl Buckets {
+  fn sweep(&mut self, limiter: &RateLimiter, now: Instant) {
+    if self.buckets.len() < self.sweep_at {
+      return;
+    }
+
+    self.buckets.retain(|(_, method), bucket| {
+      let Some(rate) = limiter.rate(method.as_ref()) else {
+        return false;
+      };
+      bucket.refill(&rate, now);
+      bucket.tokens < rate.burst as f64
+    });
+    self.sweep_at = (self.buckets.len() * 2).max(MIN_SWEEP_AT);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::metadata::Metadata;
+
+  fn context(token: Option<&str>) -> Context {
+    let mut metadata = Metadata::new();
+    if let Some(token) = token {
+      metadata.insert("token", token);
+    }
+    Context::new(PeerInfo::Local, metadata)
+  }
+
+  #[test]
+  fn limits_each_key_separately() {
+    let limiter = RateLimiter::new(RateLimitKey::Metadata("token"))
+      .with_default(Rate::per_second(1).with_burst(2));
+
+    let a = context(Some("a"));
+    assert!(limiter.check(&a, "Store", Some("get")).is_ok());
+    assert!(limiter.check(&a, "Store", Some("set")).is_ok());
+    let Err(ResponseErrorKind::RateLimited { retry_after }) =
+      limiter.check(&a, "Store", Some("get"))
+    else {
+      panic!("expected to be rate limited");
+    };
+    assert!(retry_after > Duration::ZERO);
+    assert!(retry_after <= Duration::from_secs(1));
+
+    assert!(limiter.check(&context(Some("b")), "Store", Some("get")).is_ok());
+    assert!(limiter.check(&context(None), "Store", Some("get")).is_ok());
+  }
+
+  #[test]
+  fn methods_have_own_buckets() {
+    let limiter = RateLimiter::new(RateLimitKey::Peer).with_method(
+      "Store",
+      "export",
+      Rate::per_minute(1),
+    );
+    let context = context(None);
+
+    assert!(limiter.check(&context, "Store", Some("export")).is_ok());
+    assert!(limiter.check(&context, "Store", Some("export")).is_err());
+
+    // No default rate, other methods are unlimited.
+    for _ in 0..10 {
+      assert!(limiter.check(&context, "Store", Some("get")).is_ok());
+    }
+  }
+
+  #[test]
+  fn sweeps_full_buckets() {
+    let limiter = RateLimiter::new(RateLimitKey::Custom(Arc::new(|context| {
+      context.metadata().get("token").map(str::to_string)
+    })))
+    .with_default(Rate::per_second(1000));
+
+    for i in 0..MIN_SWEEP_AT * 2 {
+      let token = i.to_string();
+      assert!(limiter.check(&context(Some(&token)), "Store", None).is_ok());
+    }
+
+    let buckets = limiter.buckets.lock().unwrap();
+    assert!(buckets.buckets.len() < MIN_SWEEP_AT * 2);
+  }
+}
@@ -0,0 +1,147 @@
+//! Lets clients discover the services of a server, see
+//! [crate::Server::with_reflection].
+//!
+//! Descriptors are emitted by `#[webcontr::service]`. Types are the Rust types
+//! as written in the service trait, along with their [Schema] when it could be
+//! traced. Arguments and outputs are encoded with bincode like any other
+//! request.
+
+mod tracer;
+
+use std::borrow::Cow;
+
+use serde::{Deserialize, Serialize};
+
+pub use tracer::Tracer;
+
+use crate::{prelude::*, transport::frame::ResponseErrorKind};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServiceDescriptor {
+  pub name: Cow<'static, str>,
+  /// In declaration order, which is also the order of the request enum
+  /// variants.
+  pub methods: Cow<'static, [MethodDescriptor]>,
+  /// The structs and enums [Schema::Named] refers to.
+  pub types: Cow<'static, [TypeDescriptor]>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MethodDescriptor {
+  pub name: Cow<'static, str>,
+  pub arguments: Cow<'static, [ArgumentDescriptor]>,
+  pub output: Cow<'static, str>,
+  pub output_schema: Option<Schema>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArgumentDescriptor {
+  pub name: Cow<'static, str>,
+  pub ty: Cow<'static, str>,
+  pub schema: Option<Schema>,
+}
+
+/// A type as serde's data model sees it, enough to encode and decode it
+/// without its Rust definition.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Schema {
+  Unit,
+  Bool,
+  Char,
+  String,
+  Bytes,
+  Int {
+    bits: u8,
+    signed: bool,
+  },
+  Float {
+    bits: u8,
+  },
+  Option(Box<Schema>),
+  /// Any length prefixed sequence, e.g. `Vec` or `HashSet`.
+  Seq(Box<Schema>),
+  Map(Box<Schema>, Box<Schema>),
+  /// Tuples and fixed size arrays, encoded without a length.
+  Tuple(Vec<Schema>),
+  /// A struct or enum, by its index in [ServiceDescriptor::types].
+  Named(u32),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TypeDescriptor {
+  /// The name serde knows the type by, without generic arguments.
+  pub name: Cow<'static, str>,
+  pub kind: TypeKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TypeKind {
+  Struct(Fields),
+  /// Variants in declaration order, their index is what goes on the wire.
+  Enum(Vec<Variant>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Variant {
+  pub name: Cow<'static, str>,
+  pub fields: Fields,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Fields {
+  Unit,
+  Newtype(Schema),
+  Tuple(Vec<Schema>),
+  Named(Vec<Field>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Field {
+  pub name: Cow<'static, str>,
+  pub schema: Schema,
+}
+
+impl ServiceDescriptor {
+  /// Descriptor of a service that doesn't provide one, only its name is known.
+  pub(crate) fn unknown(name: &'static str) -> Self {
+    Self {
+      name: Cow::Borrowed(name),
+      methods: Cow::Borrowed(&[]),
+      types: Cow::Borrowed(&[]),
+    }
+  }
+
+  pub fn method(&self, name: &str) -> Option<&MethodDescriptor> {
+    self.methods.iter().find(|method| method.name == name)
+  }
+}
+
+#[crate::service]
+pub trait Reflection {
+  /// Every service registered on the server, including this one.
+  async fn list_services() -> Vec<ServiceDescriptor>;
+
+  async fn describe(service: String) -> Option<ServiceDescriptor>;
+}
+
+#[derive(Clone)]
+pub struct ReflectionService {
+  services: Vec<ServiceDescriptor>,
+}
+
+impl ReflectionService {
+  pub(crate) fn new(services: Vec<ServiceDescriptor>) -> Self {
+    Self { services }
+  }
+}
+
+#[crate::async_trait]
+impl Reflection for ReflectionService {
+  async fn list_services(&self) -> Vec<ServiceDescriptor> {
+    self.services.clone()
+  }
+
+  async fn describe(&self, service: String) -> Option<ServiceDescriptor> {
+    self.services.iter().find(|desc| desc.name == service).cloned()
+  }
+}
@@ -0,0 +1,659 @@
+//! Works out the [Schema] of a type by deserializing a made up value of it,
+//! noting what its `Deserialize` implementation asks for along the way.
+
+use std::{any::type_name, borrow::Cow, collections::HashMap, fmt};
+
+use serde::de::{
+  self, DeserializeOwned, DeserializeSeed, EnumAccess, IntoDeserializer,
+  MapAccess, SeqAccess, VariantAccess, Visitor,
+};
+
+use super::{Field, Fields, Schema, TypeDescriptor, TypeKind, Variant};
+
+/// Traces the schemas of a service's types, sharing the structs and enums
+/// among them.
+#[derive(Default)]
+pub struct Tracer {
+  types: Vec<Traced>,
+  /// Indices into `types` by the Rust type of their visitor, serde names are
+  /// the same for every instance of a generic type.
+  indices: HashMap<&'static str, u32>,
+  /// Structs and enums being traced, with the variant taken for enums.
+  stack: Vec<(u32, Option<usize>)>,
+  /// Enums reached in the current pass.
+  seen: Vec<u32>,
+}
+
+struct Traced {
+  name: &'static str,
+  kind: TracedKind,
+}
+
+/// `None` until traced.
+enum TracedKind {
+  Struct(Option<Fields>),
+  Enum(Vec<(&'static str, Option<Fields>)>),
+}
+
+impl Tracer {
+  /// The schema of `T`, or `None` if it can't be traced, e.g. because it
+  /// needs a self-describing format or contains itself through an `Option`.
+  pub fn trace<T: DeserializeOwned>(&mut self) -> Option<Schema> {
+    // Every pass takes one variant of each enum, until all are known.
+    loop {
+      let traced = self.traced_variants();
+      self.stack.clear();
+      self.seen.clear();
+      let mut schema = None;
+      T::deserialize(Tracing { tracer: self, schema: &mut schema }).ok()?;
+
+      let pending = self.seen.iter().any(|&index| self.is_pending(index));
+      if !pending {
+        return schema;
+      }
+      if self.traced_variants() == traced {
+        return None;
+      }
+    }
+  }
+
+  /// The structs and enums [Schema::Named] refers to, by index.
+  pub fn into_types(self) -> Vec<TypeDescriptor> {
+    // Only types of failed traces are left incomplete, no schema refers to
+    // them.
+    let fields = |fields: Option<Fields>| fields.unwrap_or(Fields::Unit);
+    self
+      .types
+      .into_iter()
+      .map(|traced| TypeDescriptor {
+        name: Cow::Borrowed(traced.name),
+        kind: match traced.kind {
+          TracedKind::Struct(traced) => TypeKind::Struct(fields(traced)),
+          TracedKind::Enum(variants) => TypeKind::Enum(
+            variants
+              .into_iter()
+              .map(|(name, traced)| Variant {
+                name: Cow::Borrowed(name),
+                fields: fields(traced),
+              })
+              .collect(),
+          ),
+        },
+      })
+      .collect()
+  }
+
+  fn traced_variants(&self) -> usize {
+    self
+      .types
+      .iter()
+      .map(|traced| match &traced.kind {
+        TracedKind::Struct(_) => 0,
+        TracedKind::Enum(variants) => {
+          variants.iter().filter(|(_, fields)| fields.is_some()).count()
+        }
+      })
+      .sum()
+  }
+
+  fn is_pending(&self, index: u32) -> bool {
+    match &self.types[index as usize].kind {
+      TracedKind::Struct(_) => false,
+      TracedKind::Enum(variants) => {
+        variants.iter().any(|(_, fields)| fields.is_none())
+      }
+    }
+  }
+
+  /// Index of the type visited by `V`, added with `kind` if it's new.
+  fn index<V>(
+    &mut self,
+    name: &'static str,
+    kind: impl FnOnce() -> TracedKind,
+  ) -> u32 {
+    let types = &mut self.types;
+    *self.indices.entry(type_name::<V>()).or_insert_with(|| {
+      types.push(Traced { name, kind: kind() });
+      types.len() as u32 - 1
+    })
+  }
+
+  /// Enters a struct, which fails if it is already being traced.
+  fn enter_struct<V>(
+    &mut self,
+    name: &'static str,
+    schema: &mut Option<Schema>,
+  ) -> Result<u32, Error> {
+    let index = self.index::<V>(name, || TracedKind::Struct(None));
+    *schema = Some(Schema::Named(index));
+    if self.stack.iter().any(|(entered, _)| *entered == index) {
+      return Err(Error::Recursive(index));
+    }
+    self.stack.push((index, None));
+    Ok(index)
+  }
+
+  /// Leaves the struct entered last, keeping `fields` if it was traced.
+  fn leave_struct<T>(
+    &mut self,
+    index: u32,
+    result: Result<T, Error>,
+    fields: impl FnOnce() -> Fields,
+  ) -> Result<T, Error> {
+    self.stack.pop();
+    let value = result?;
+    self.types[index as usize].kind = TracedKind::Struct(Some(fields()));
+    Ok(value)
+  }
+
+  /// The variant of the enum at `index` to take, one that wasn't traced yet
+  /// if possible. Variants already being traced are never taken again.
+  fn variant(&self, index: u32) -> Option<usize> {
+    let TracedKind::Enum(variants) = &self.types[index as usize].kind else {
+      return None;
+    };
+    let busy = |variant| self.stack.contains(&(index, Some(variant)));
+    (0..variants.len())
+      .filter(|&variant| !busy(variant))
+      .min_by_key(|&variant| variants[variant].1.is_some())
+  }
+}
+
+#[derive(Debug)]
+enum Error {
+  /// Reached the struct at this index while tracing it.
+  Recursive(u32),
+  Unsupported(String),
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Error::Recursive(_) => f.write_str("recursive type"),
+      Error::Unsupported(msg) => f.write_str(msg),
+    }
+  }
+}
+
+impl std::error::Error for Error {}
+
+impl de::Error for Error {
+  fn custom<T: fmt::Display>(msg: T) -> Self {
+    Error::Unsupported(msg.to_string())
+  }
+}
+
+/// Deserializes one value, storing its schema in `schema`.
+struct Tracing<'a> {
+  tracer: &'a mut Tracer,
+  schema: &'a mut Option<Schema>,
+}
+
+/// Traces a value with `seed`, its schema is set even if that fails.
+fn trace_seed<'de, S: DeserializeSeed<'de>>(
+  tracer: &mut Tracer,
+  seed: S,
+  schema: &mut Option<Schema>,
+) -> Result<S::Value, Error> {
+  seed.deserialize(Tracing { tracer, schema })
+}
+
+macro_rules! primitives {
+  ($($method:ident => $schema:expr, $visit:ident($($value:expr)?);)*) => {$(
+    fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+      *self.schema = Some($schema);
+      visitor.$visit($($value)?)
+    }
+  )*};
+}
+
+impl<'de> de::Deserializer<'de> for Tracing<'_> {
+  type Error = Error;
+
+  primitives! {
+    deserialize_bool => Schema::Bool, visit_bool(false);
+    deserialize_i8 => int(8, true), visit_i8(1);
+    deserialize_i16 => int(16, true), visit_i16(1);
+    deserialize_i32 => int(32, true), visit_i32(1);
+    deserialize_i64 => int(64, true), visit_i64(1);
+    deserialize_i128 => int(128, true), visit_i128(1);
+    deserialize_u8 => int(8, false), visit_u8(1);
+    deserialize_u16 => int(16, false), visit_u16(1);
+    deserialize_u32 => int(32, false), visit_u32(1);
+    deserialize_u64 => int(64, false), visit_u64(1);
+    deserialize_u128 => int(128, false), visit_u128(1);
+    deserialize_f32 => Schema::Float { bits: 32 }, visit_f32(0.0);
+    deserialize_f64 => Schema::Float { bits: 64 }, visit_f64(0.0);
+    deserialize_char => Schema::Char, visit_char('a');
+    deserialize_str => Schema::String, visit_str("");
+    deserialize_string => Schema::String, visit_string(String::new());
+    deserialize_bytes => Schema::Bytes, visit_bytes(&[]);
+    deserialize_byte_buf => Schema::Bytes, visit_byte_buf(Vec::new());
+    deserialize_unit => Schema::Unit, visit_unit();
+  }
+
+  fn deserialize_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Error> {
+    Err(Error::Unsupported("needs a self-describing format".into()))
+  }
+
+  fn deserialize_option<V: Visitor<'de>>(
+    self,
+    visitor: V,
+  ) -> Result<V::Value, Error> {
+    let mut inner = None;
+    let value =
+      visitor.visit_some(Tracing { tracer: self.tracer, schema: &mut inner });
+    *self.schema = inner.map(|inner| Schema::Option(Box::new(inner)));
+    value
+  }
+
+  fn deserialize_unit_struct<V: Visitor<'de>>(
+    self,
+    name: &'static str,
+    visitor: V,
+  ) -> Result<V::Value, Error> {
+    let index = self.tracer.enter_struct::<V>(name, self.schema)?;
+    let value = visitor.visit_unit();
+    self.tracer.leave_struct(index, value, || Fields::Unit)
+  }
+
+  fn deserialize_newtype_struct<V: Visitor<'de>>(
+    self,
+    name: &'static str,
+    visitor: V,
+  ) -> Result<V::Value, Error> {
+    let index = self.tracer.enter_struct::<V>(name, self.schema)?;
+    let mut inner = None;
+    let value = visitor.visit_newtype_struct(Tracing {
+      tracer: &mut *self.tracer,
+      schema: &mut inner,
+    });
+    self.tracer.leave_struct(index, value, || {
+      Fields::Newtype(inner.unwrap_or(Schema::Unit))
+    })
+  }
+
+  fn deserialize_seq<V: Visitor<'de>>(
+    self,
+    visitor: V,
+  ) -> Result<V::Value, Error> {
+    let mut items = Vec::new();
+    let value = visitor.visit_seq(Seq::variable(self.tracer, &mut items));
+    let item = items.pop().unwrap_or(Schema::Unit);
+    *self.schema = Some(Schema::Seq(Box::new(item)));
+    value
+  }
+
+  fn deserialize_tuple<V: Visitor<'de>>(
+    self,
+    len: usize,
+    visitor: V,
+  ) -> Result<V::Value, Error> {
+    let mut items = Vec::new();
+    let value = visitor.visit_seq(Seq::fixed(self.tracer, len, &mut items));
+    *self.schema = Some(Schema::Tuple(items));
+    value
+  }
+
+  fn deserialize_tuple_struct<V: Visitor<'de>>(
+    self,
+    name: &'static str,
+    len: usize,
+    visitor: V,
+  ) -> Result<V::Value, Error> {
+    let index = self.tracer.enter_struct::<V>(name, self.schema)?;
+    let mut items = Vec::new();
+    let value = visitor.visit_seq(Seq::fixed(self.tracer, len, &mut items));
+    self.tracer.leave_struct(index, value, || Fields::Tuple(items))
+  }
+
+  fn deserialize_map<V: Visitor<'de>>(
+    self,
+    visitor: V,
+  ) -> Result<V::Value, Error> {
+    let (mut key, mut value) = (None, None);
+    let map = Map {
+      tracer: self.tracer,
+      done: false,
+      key: &mut key,
+      value: &mut value,
+    };
+    let result = visitor.visit_map(map);
+    let (key, value) =
+      (key.unwrap_or(Schema::Unit), value.unwrap_or(Schema::Unit));
+    *self.schema = Some(Schema::Map(Box::new(key), Box::new(value)));
+    result
+  }
+
+  fn deserialize_struct<V: Visitor<'de>>(
+    self,
+    name: &'static str,
+    fields: &'static [&'static str],
+    visitor: V,
+  ) -> Result<V::Value, Error> {
+    let index = self.tracer.enter_struct::<V>(name, self.schema)?;
+    let mut items = Vec::new();
+    let seq = Seq::fixed(self.tracer, fields.len(), &mut items);
+    let value = visitor.visit_seq(seq);
+    self.tracer.leave_struct(index, value, || named(fields, items))
+  }
+
+  fn deserialize_enum<V: Visitor<'de>>(
+    self,
+    name: &'static str,
+    variants: &'static [&'static str],
+    visitor: V,
+  ) -> Result<V::Value, Error> {
+    let tracer = self.tracer;
+    let index = tracer.index::<V>(name, || {
+      TracedKind::Enum(variants.iter().map(|name| (*name, None)).collect())
+    });
+    *self.schema = Some(Schema::Named(index));
+    tracer.seen.push(index);
+    let variant = tracer.variant(index).ok_or(Error::Recursive(index))?;
+
+    tracer.stack.push((index, Some(variant)));
+    let mut fields = None;
+    let value = visitor.visit_enum(Enum {
+      tracer: &mut *tracer,
+      variant,
+      fields: &mut fields,
+    });
+    tracer.stack.pop();
+
+    let value = value?;
+    if let TracedKind::Enum(variants) = &mut tracer.types[index as usize].kind {
+      variants[variant].1 = fields;
+    }
+    Ok(value)
+  }
+
+  fn deserialize_identifier<V: Visitor<'de>>(
+    self,
+    visitor: V,
+  ) -> Result<V::Value, Error> {
+    self.deserialize_any(visitor)
+  }
+
+  fn deserialize_ignored_any<V: Visitor<'de>>(
+    self,
+    visitor: V,
+  ) -> Result<V::Value, Error> {
+    self.deserialize_any(visitor)
+  }
+
+  fn is_human_readable(&self) -> bool {
+    // Like bincode, which is what the schema describes.
+    false
+  }
+}
+
+fn int(bits: u8, signed: bool) -> Schema {
+  Schema::Int { bits, signed }
+}
+
+fn named(names: &'static [&'static str], schemas: Vec<Schema>) -> Fields {
+  let fields = names.iter().zip(schemas);
+  Fields::Named(
+    fields
+      .map(|(name, schema)| Field { name: Cow::Borrowed(name), schema })
+      .collect(),
+  )
+}
+
+/// Yields `len` elements, or a single one for sequences of any length.
+struct Seq<'a> {
+  tracer: &'a mut Tracer,
+  remaining: usize,
+  variable: bool,
+  schemas: &'a mut Vec<Schema>,
+}
+
+impl<'a> Seq<'a> {
+  fn fixed(
+    tracer: &'a mut Tracer,
+    len: usize,
+    schemas: &'a mut Vec<Schema>,
+  ) -> Self {
+    Self { tracer, remaining: len, variable: false, schemas }
+  }
+
+  fn variable(tracer: &'a mut Tracer, schemas: &'a mut Vec<Schema>) -> Self {
+    Self { tracer, remaining: 1, variable: true, schemas }
+  }
+}
+
+impl<'de> SeqAccess<'de> for Seq<'_> {
+  type Error = Error;
+
+  fn next_element_seed<T: DeserializeSeed<'de>>(
+    &mut self,
+    seed: T,
+  ) -> Result<Option<T::Value>, Error> {
+    if self.remaining == 0 {
+      return Ok(None);
+    }
+    self.remaining -= 1;
+
+    let mut schema = None;
+    let result = trace_seed(self.tracer, seed, &mut schema);
+    let schema = schema.unwrap_or(Schema::Unit);
+    match result {
+      Ok(value) => {
+        self.schemas.push(schema);
+        Ok(Some(value))
+      }
+      // A sequence of the struct it is in, which stays empty.
+      Err(Error::Recursive(index))
+        if self.variable && schema == Schema::Named(index) =>
+      {
+        self.schemas.push(schema);
+        Ok(None)
+      }
+      Err(err) => Err(err),
+    }
+  }
+
+  fn size_hint(&self) -> Option<usize> {
+    Some(self.remaining)
+  }
+}
+
+/// Yields a single entry.
+struct Map<'a> {
+  tracer: &'a mut Tracer,
+  done: bool,
+  key: &'a mut Option<Schema>,
+  value: &'a mut Option<Schema>,
+}
+
+impl<'de> MapAccess<'de> for Map<'_> {
+  type Error = Error;
+
+  fn next_key_seed<K: DeserializeSeed<'de>>(
+    &mut self,
+    seed: K,
+  ) -> Result<Option<K::Value>, Error> {
+    if std::mem::replace(&mut self.done, true) {
+      return Ok(None);
+    }
+    trace_seed(self.tracer, seed, self.key).map(Some)
+  }
+
+  fn next_value_seed<V: DeserializeSeed<'de>>(
+    &mut self,
+    seed: V,
+  ) -> Result<V::Value, Error> {
+    trace_seed(self.tracer, seed, self.value)
+  }
+
+  fn size_hint(&self) -> Option<usize> {
+    Some(!self.done as usize)
+  }
+}
+
+/// Takes `variant`, storing its fields in `fields`.
+struct Enum<'a> {
+  tracer: &'a mut Tracer,
+  variant: usize,
+  fields: &'a mut Option<Fields>,
+}
+
+impl<'de> EnumAccess<'de> for Enum<'_> {
+  type Error = Error;
+  type Variant = Self;
+
+  fn variant_seed<S: DeserializeSeed<'de>>(
+    self,
+    seed: S,
+  ) -> Result<(S::Value, Self), Error> {
+    let variant: de::value::U32Deserializer<Error> =
+      (self.variant as u32).into_deserializer();
+    Ok((seed.deserialize(variant)?, self))
+  }
+}
+
+impl<'de> VariantAccess<'de> for Enum<'_> {
+  type Error = Error;
+
+  fn unit_variant(self) -> Result<(), Error> {
+    *self.fields = Some(Fields::Unit);
+    Ok(())
+  }
+
+  fn newtype_variant_seed<S: DeserializeSeed<'de>>(
+    self,
+    seed: S,
+  ) -> Result<S::Value, Error> {
+    let mut schema = None;
+    let value = trace_seed(self.tracer, seed, &mut schema)?;
+    *self.fields = Some(Fields::Newtype(schema.unwrap_or(Schema::Unit)));
+    Ok(value)
+  }
+
+  fn tuple_variant<V: Visitor<'de>>(
+    self,
+    len: usize,
+    visitor: V,
+  ) -> Result<V::Value, Error> {
+    let mut items = Vec::new();
+    let value = visitor.visit_seq(Seq::fixed(self.tracer, len, &mut items))?;
+    *self.fields = Some(Fields::Tuple(items));
+    Ok(value)
+  }
+
+  fn struct_variant<V: Visitor<'de>>(
+    self,
+    fields: &'static [&'static str],
+    visitor: V,
+  ) -> Result<V::Value, Error> {
+    let mut items = Vec::new();
+    let seq = Seq::fixed(self.tracer, fields.len(), &mut items);
+    let value = visitor.visit_seq(seq)?;
+    *self.fields = Some(named(fields, items));
+    Ok(value)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::time::Duration;
+
+  use serde::Deserialize;
+
+  use super::*;
+
+  #[derive(Deserialize)]
+  #[allow(dead_code)]
+  struct Node {
+    name: String,
+    children: Vec<Node>,
+  }
+
+  #[derive(Deserialize)]
+  #[allow(dead_code)]
+  enum Expr {
+    Neg(Box<Expr>),
+    Add { left: Box<Expr>, right: Box<Expr> },
+    Lit(i64),
+  }
+
+  #[test]
+  fn traces_std_types() {
+    let mut tracer = Tracer::default();
+    assert_eq!(
+      tracer.trace::<Option<Vec<(u8, String)>>>(),
+      Some(Schema::Option(Box::new(Schema::Seq(Box::new(Schema::Tuple(
+        vec![int(8, false), Schema::String]
+      ))))))
+    );
+    assert_eq!(tracer.trace::<Result<u32, String>>(), Some(Schema::Named(0)));
+    assert_eq!(tracer.trace::<Result<(), String>>(), Some(Schema::Named(1)));
+    assert_eq!(tracer.trace::<Duration>(), Some(Schema::Named(2)));
+
+    let types = tracer.into_types();
+    assert_eq!(types[0].name, "Result");
+    assert_eq!(
+      types[0].kind,
+      TypeKind::Enum(vec![
+        Variant { name: "Ok".into(), fields: Fields::Newtype(int(32, false)) },
+        Variant { name: "Err".into(), fields: Fields::Newtype(Schema::String) },
+      ])
+    );
+    assert_eq!(
+      types[2].kind,
+      TypeKind::Struct(Fields::Named(vec![
+        Field { name: "secs".into(), schema: int(64, false) },
+        Field { name: "nanos".into(), schema: int(32, false) },
+      ]))
+    );
+  }
+
+  #[test]
+  fn traces_recursive_types() {
+    let mut tracer = Tracer::default();
+    assert_eq!(tracer.trace::<Node>(), Some(Schema::Named(0)));
+    assert_eq!(tracer.trace::<Expr>(), Some(Schema::Named(1)));
+
+    let types = tracer.into_types();
+    assert_eq!(
+      types[0].kind,
+      TypeKind::Struct(Fields::Named(vec![
+        Field { name: "name".into(), schema: Schema::String },
+        Field {
+          name: "children".into(),
+          schema: Schema::Seq(Box::new(Schema::Named(0))),
+        },
+      ]))
+    );
+    let expr = Schema::Named(1);
+    assert_eq!(
+      types[1].kind,
+      TypeKind::Enum(vec![
+        Variant { name: "Neg".into(), fields: Fields::Newtype(expr.clone()) },
+        Variant {
+          name: "Add".into(),
+          fields: Fields::Named(vec![
+            Field { name: "left".into(), schema: expr.clone() },
+            Field { name: "right".into(), schema: expr },
+          ]),
+        },
+        Variant { name: "Lit".into(), fields: Fields::Newtype(int(64, true)) },
+      ])
+    );
+  }
+
+  #[test]
+  fn untraceable_types_have_no_schema() {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    #[allow(dead_code)]
+    enum Untagged {
+      Int(u32),
+      String(String),
+    }
+
+    let mut tracer = Tracer::default();
+    assert_eq!(tracer.trace::<Untagged>(), None);
+  }
+}
@@ -1,4 +1,5 @@
 use std::{
+  collections::HashSet,
   future::{Future, IntoFuture},
   io,
   pin::Pin,
@@ -6,115 +7,1025 @@ use std::{
   task::{Context, Poll},
 };
 
-use futures_util::{SinkExt, StreamExt};
+use bytes::BytesMut;
+use futures_util::{
+  future::join_all,
+  stream::{FuturesOrdered, FuturesUnordered},
+  Sink, SinkExt, Stream, StreamExt,
+};
+use thiserror::Error;
 use tokio::{
-  net::TcpListener,
-  sync::watch,
-  time::{sleep, Duration, Sleep},
+  io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+  sync::{watch, OwnedSemaphorePermit, Semaphore},
+  time::{sleep, sleep_until, timeout_at, Duration, Instant, Sleep},
+};
+use tokio_util::{
+  codec::{Decoder, Encoder},
+  task::TaskTracker,
 };
-use tokio_util::task::TaskTracker;
-use tower::Service;
+
+pub use crate::limit::Overload;
 
 use crate::{
+  accept::{self, Accept, BoxAccept, IdentityCell},
+  compression::{Compression, Payloads},
+  context::{ClientIdentity, PeerInfo},
+  limit::{self, Limit, Limits, Rejected},
+  metrics,
+  status::{Code, Status},
+  trace,
   transport::{
-    frame::{ResponseErrorKind, ResponseFrame},
-    tcp,
+    frame::{
+      payload_len, Frame, FrameCodec, RequestFrame, RequestFrameCodec,
+      ResponseErrorKind, ResponseFrame, ResponseFrameCodec,
+    },
+    keepalive::{Due, Keepalive, Pings},
+    preamble::{
+      Preamble, PreambleCodec, ServerHello, ServerHelloCodec,
+      MULTIPLEXED_VERSION,
+    },
   },
   FrozenServer,
 };
 
+/// Settings that only apply to connections from one listener of a
+/// [ServerServe].
+#[derive(Clone, Default)]
+pub struct ListenerOptions {
+  timeout: Option<Duration>,
+  max_connections: Option<usize>,
+  #[cfg(feature = "tls")]
+  tls_paths: Option<crate::tls::TLSPaths>,
+  #[cfg(feature = "tls")]
+  handshake_timeout: Option<Duration>,
+}
+
+impl ListenerOptions {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Overrides [ServerServe::with_timeout] for this listener.
+  pub fn with_timeout(mut self, dur: Duration) -> Self {
+    self.timeout = Some(dur);
+    self
+  }
+
+  /// Stops accepting from this listener while `max` of its connections are
+  /// open, unlike [ServerServe::with_max_connections] which accepts and then
+  /// applies its [Overload] policy.
+  pub fn with_max_connections(mut self, max: usize) -> Self {
+    self.max_connections = Some(max);
+    self
+  }
+
+  /// Serves TLS on top of the listener's connections.
+  #[cfg(feature = "tls")]
+  pub fn with_tls(mut self, tls_paths: crate::tls::TLSPaths) -> Self {
+    self.tls_paths = Some(tls_paths);
+    self
+  }
+
+  /// How long a TLS handshake may take, 10 seconds by default. See
+  /// [crate::tls::TlsListener::with_handshake_timeout].
+  #[cfg(feature = "tls")]
+  pub fn with_handshake_timeout(mut self, dur: Duration) -> Self {
+    self.handshake_timeout = Some(dur);
+    self
+  }
+}
+
+pub(crate) struct Listener {
+  acceptor: BoxAccept,
+  timeout: Option<Duration>,
+  connections: Option<Arc<Semaphore>>,
+}
+
+impl Listener {
+  pub(crate) fn new<A: Accept + 'static>(
+    acceptor: A,
+    options: ListenerOptions,
+  ) -> io::Result<Self> {
+    #[cfg(feature = "tls")]
+    let acceptor = match options.tls_paths {
+      Some(tls_paths) => {
+        let acceptor =
+          crate::tls::TlsListener::from_paths(acceptor, tls_paths)?;
+        accept::boxed(match options.handshake_timeout {
+          Some(dur) => acceptor.with_handshake_timeout(dur),
+          None => acceptor,
+        })
+      }
+      None => accept::boxed(acceptor),
+    };
+    #[cfg(not(feature = "tls"))]
+    let acceptor = accept::boxed(acceptor);
+
+    Ok(Listener {
+      acceptor,
+      timeout: options.timeout,
+      connections: options
+        .max_connections
+        .map(|max| Arc::new(Semaphore::new(max))),
+    })
+  }
+}
+
+/// Serves a [FrozenServer] on one or more listeners until Ctrl+C is received,
+/// or [ServerServe::with_shutdown] resolves, then waits for open connections
+/// to finish. Clients of protocol version 2 are told to go elsewhere, see
+/// [crate::transport::frame::Frame::GoAway].
 pub struct ServerServe {
   pub(crate) server: FrozenServer,
-  pub(crate) listener: TcpListener,
+  pub(crate) listeners: Vec<Listener>,
+  /// Why a listener couldn't be set up, returned once served.
+  pub(crate) listener_error: Option<io::Error>,
   pub(crate) timeout: Option<Duration>,
+  pub(crate) on_error: Option<ErrorHook>,
+  pub(crate) max_connections: Option<usize>,
+  pub(crate) max_in_flight: Option<usize>,
+  pub(crate) max_in_flight_per_connection: Option<usize>,
+  pub(crate) overload: Overload,
+  pub(crate) timeouts: ConnectionTimeouts,
+  pub(crate) compression: Compression,
+  pub(crate) keepalive: Option<Keepalive>,
+  pub(crate) shutdown: Option<Pin<Box<dyn Future<Output = ()> + Send>>>,
+}
 
-  #[cfg(feature = "tls")]
-  pub(crate) tls_paths: crate::tls::TLSPaths,
+/// How long a connection may stall, to free up tasks held by clients that
+/// stop talking halfway.
+#[derive(Clone, Copy)]
+pub(crate) struct ConnectionTimeouts {
+  pub(crate) idle: Duration,
+  pub(crate) read: Duration,
+  pub(crate) write: Duration,
+  pub(crate) drain: Duration,
+}
+
+impl Default for ConnectionTimeouts {
+  fn default() -> Self {
+    Self {
+      idle: Duration::from_secs(60),
+      read: Duration::from_secs(30),
+      write: Duration::from_secs(30),
+      drain: Duration::from_secs(30),
+    }
+  }
+}
+
+/// Something went wrong outside of a handler while serving. The server keeps
+/// running, these are only reported through [ServerServe::on_error].
+#[derive(Error, Debug)]
+pub enum ServeError {
+  #[error("failed to accept connection: {0}")]
+  Accept(io::Error),
+  /// Reading a request failed, e.g. a TLS handshake or decoding error, or it
+  /// didn't arrive in time.
+  #[error("failed to read request from {peer:?}: {source}")]
+  Read { peer: PeerInfo, source: io::Error },
+  #[error("failed to send response to {peer:?}: {source}")]
+  Write { peer: PeerInfo, source: io::Error },
 }
 
+type ErrorHook = Arc<dyn Fn(&ServeError) + Send + Sync>;
+
 impl ServerServe {
   pub fn with_timeout(mut self, dur: Duration) -> Self {
     self.timeout = Some(dur);
     self
   }
+
+  /// Also serves the same services on `acceptor`. Serving fails right away
+  /// if it can't be set up, e.g. TLS from files that can't be read.
+  pub fn with_listener<A: Accept + 'static>(
+    mut self,
+    acceptor: A,
+    options: ListenerOptions,
+  ) -> Self {
+    match Listener::new(acceptor, options) {
+      Ok(listener) => self.listeners.push(listener),
+      Err(err) => {
+        self.listener_error.get_or_insert(err);
+      }
+    }
+    self
+  }
+
+  /// Limits the number of open connections across all listeners.
+  pub fn with_max_connections(mut self, max: usize) -> Self {
+    self.max_connections = Some(max);
+    self
+  }
+
+  /// Limits the number of requests handled at once across all connections.
+  pub fn with_max_in_flight(mut self, max: usize) -> Self {
+    self.max_in_flight = Some(max);
+    self
+  }
+
+  /// Limits the number of requests handled at once on each connection.
+  pub fn with_max_in_flight_per_connection(mut self, max: usize) -> Self {
+    self.max_in_flight_per_connection = Some(max);
+    self
+  }
+
+  /// What to do once one of the limits is reached, [Overload::Reject] by
+  /// default.
+  pub fn with_overload(mut self, overload: Overload) -> Self {
+    self.overload = overload;
+    self
+  }
+
+  /// Closes connections that don't start a request within `dur`, 60 seconds
+  /// by default. Connections are kept open for more requests until then.
+  pub fn with_idle_timeout(mut self, dur: Duration) -> Self {
+    self.timeouts.idle = dur;
+    self
+  }
+
+  /// How long a request may take to arrive once it started, 30 seconds by
+  /// default.
+  pub fn with_read_timeout(mut self, dur: Duration) -> Self {
+    self.timeouts.read = dur;
+    self
+  }
+
+  /// How long sending a response may take, 30 seconds by default.
+  pub fn with_write_timeout(mut self, dur: Duration) -> Self {
+    self.timeouts.write = dur;
+    self
+  }
+
+  /// How long requests may still take once shutting down, 30 seconds by
+  /// default. The ones that don't finish in time are answered with
+  /// [crate::status::Code::Unavailable], so a handler that never returns
+  /// can't hold up shutdown.
+  pub fn with_drain_timeout(mut self, dur: Duration) -> Self {
+    self.timeouts.drain = dur;
+    self
+  }
+
+  /// Compresses payloads for clients that support one of the algorithms of
+  /// `compression`.
+  pub fn with_compression(mut self, compression: Compression) -> Self {
+    self.compression = compression;
+    self
+  }
+
+  /// Pings clients on connections of protocol version 2, and closes the
+  /// ones that don't answer. Off by default.
+  pub fn with_keepalive(mut self, keepalive: Keepalive) -> Self {
+    self.keepalive = Some(keepalive);
+    self
+  }
+
+  /// Shuts down once `signal` resolves instead of on Ctrl+C, e.g. on SIGTERM
+  /// during a deploy.
+  pub fn with_shutdown<F>(mut self, signal: F) -> Self
+  where
+    F: Future<Output = ()> + Send + 'static,
+  {
+    self.shutdown = Some(Box::pin(signal));
+    self
+  }
+
+  /// Called for every [ServeError]. Errors are dropped silently otherwise.
+  pub fn on_error<F>(mut self, hook: F) -> Self
+  where
+    F: Fn(&ServeError) + Send + Sync + 'static,
+  {
+    self.on_error = Some(Arc::new(hook));
+    self
+  }
 }
 
 impl IntoFuture for ServerServe {
   type Output = io::Result<()>;
 
-  type IntoFuture = Pin<Box<dyn Future<Output = Self::Output>>>;
+  type IntoFuture = Pin<Box<dyn Future<Output = Self::Output> + Send>>;
 
   fn into_future(self) -> Self::IntoFuture {
-    let (shutdown_tx, mut shutdown_rx) = watch::channel::<bool>(false);
+    if let Some(err) = self.listener_error {
+      return Box::pin(async { Err(err) });
+    }
+
+    let (shutdown_tx, shutdown_rx) = watch::channel::<bool>(false);
     let task_tracker = TaskTracker::default();
 
-    task_tracker.spawn(async move {
-      tokio::signal::ctrl_c().await.unwrap();
-      shutdown_tx.send(true).unwrap();
+    let signal = self.shutdown.unwrap_or_else(|| {
+      Box::pin(async {
+        if tokio::signal::ctrl_c().await.is_ok() {
+          trace::info_event!("received Ctrl+C, shutting down");
+          return;
+        }
 
-      eprintln!("Received Ctrl+C signal");
+        // No signal handling available, only stop on errors.
+        std::future::pending().await
+      })
+    });
+    task_tracker.spawn(async move {
+      signal.await;
+      let _ = shutdown_tx.send(true);
     });
 
-    #[cfg(feature = "tls")]
-    let acceptor = {
-      let config = self.tls_paths.clone().serverconfig_from_paths();
-      tokio_rustls::TlsAcceptor::from(Arc::new(config))
+    let on_error = self.on_error.unwrap_or_else(|| Arc::new(|_| {}));
+    let limits = Limits {
+      connections: self
+        .max_connections
+        .map(|max| Limit::new(max, self.overload)),
+      in_flight: self.max_in_flight.map(|max| Limit::new(max, self.overload)),
+      in_flight_per_connection: self.max_in_flight_per_connection,
+      overload: self.overload,
     };
+    let shared = Shared {
+      server: self.server,
+      timeout: self.timeout,
+      on_error,
+      limits,
+      timeouts: self.timeouts,
+      compression: self.compression,
+      keepalive: self.keepalive,
+      shutdown_rx,
+    };
+    let accept_loops = self.listeners.into_iter().map(|listener| {
+      accept_loop(shared.clone(), listener, task_tracker.clone())
+    });
+    let accept_loops = join_all(accept_loops);
 
     Box::pin(async move {
-      loop {
-        let (stream, _) = tokio::select! {
-            listener = self.listener.accept() => listener?,
-            _ = shutdown_rx.changed() => {
-                task_tracker.close();
-                task_tracker.wait().await;
-                return Ok(())
-            },
-        };
+      accept_loops.await;
+
+      task_tracker.close();
+      task_tracker.wait().await;
+      Ok(())
+    })
+  }
+}
+
+const MIN_ACCEPT_BACKOFF: Duration = Duration::from_millis(5);
+const MAX_ACCEPT_BACKOFF: Duration = Duration::from_secs(1);
+
+/// What every connection of a [ServerServe] is served with.
+#[derive(Clone)]
+struct Shared {
+  server: FrozenServer,
+  timeout: Option<Duration>,
+  on_error: ErrorHook,
+  limits: Limits,
+  timeouts: ConnectionTimeouts,
+  compression: Compression,
+  keepalive: Option<Keepalive>,
+  shutdown_rx: watch::Receiver<bool>,
+}
+
+async fn accept_loop(
+  mut shared: Shared,
+  mut listener: Listener,
+  task_tracker: TaskTracker,
+) {
+  shared.timeout = listener.timeout.or(shared.timeout);
+  let mut shutdown_rx = shared.shutdown_rx.clone();
+  let mut backoff = MIN_ACCEPT_BACKOFF;
+
+  loop {
+    let permit = match &listener.connections {
+      Some(connections) => tokio::select! {
+          permit = connections.clone().acquire_owned() => {
+            permit.ok()
+          },
+          _ = shutdown_rx.changed() => return,
+      },
+      None => None,
+    };
+
+    let accepted = tokio::select! {
+        accepted = listener.acceptor.accept() => accepted,
+        _ = shutdown_rx.changed() => return,
+    };
+
+    let accept::Accepted { io: stream, peer, identity } = match accepted {
+      Ok(accepted) => {
+        backoff = MIN_ACCEPT_BACKOFF;
+        accepted
+      }
+      Err(err) => {
+        let per_connection = is_connection_error(&err);
+        trace::warn_event!(error = %err, "failed to accept connection");
+        (shared.on_error)(&ServeError::Accept(err));
+
+        // Only the one connection failed, unless the listener ran out of
+        // file descriptors or similar. Those resolve themselves eventually,
+        // don't spin on them meanwhile.
+        if !per_connection {
+          tokio::select! {
+              _ = sleep(backoff) => {},
+              _ = shutdown_rx.changed() => return,
+          }
+          backoff = (backoff * 2).min(MAX_ACCEPT_BACKOFF);
+        }
+        continue;
+      }
+    };
+
+    let span = trace::connection_span(&peer);
+    let conn = Connection { shared: shared.clone(), peer, identity };
+    let connection = serve_connection(conn, stream);
+    let connection = trace::instrument(connection, &span);
+    task_tracker.spawn(async move {
+      connection.await;
+      drop(permit);
+    });
+  }
+}
+
+/// Errors that only concern the connection that was being accepted.
+fn is_connection_error(err: &io::Error) -> bool {
+  matches!(
+    err.kind(),
+    io::ErrorKind::ConnectionRefused
+      | io::ErrorKind::ConnectionAborted
+      | io::ErrorKind::ConnectionReset
+  )
+}
+
+struct Connection {
+  shared: Shared,
+  peer: PeerInfo,
+  identity: Option<IdentityCell>,
+}
+
+impl Connection {
+  /// Known by the time requests are read, the handshake is done by then.
+  fn client_identity(&self) -> Option<ClientIdentity> {
+    self.identity.as_ref()?.get().cloned()
+  }
+}
+
+/// Answers requests one after another until the client closes the
+/// connection, stalls or the server shuts down.
+async fn serve_connection<T>(mut conn: Connection, mut stream: T)
+where
+  T: AsyncRead + AsyncWrite + Unpin,
+{
+  let _active = metrics::ActiveConnection::new();
+  let limits = conn.shared.limits.clone();
+  let connection = limit::acquire(limits.connections.as_ref()).await;
+  let per_connection =
+    limits.in_flight_per_connection.map(|max| Limit::new(max, limits.overload));
+
+  let mut read_buf = BytesMut::new();
+  let mut write_buf = BytesMut::new();
 
-        let mut server = self.server.clone();
-
-        #[cfg(feature = "tls")]
-        let acceptor = acceptor.clone();
-
-        task_tracker.spawn(async move {
-           let stream = {
-             #[cfg(feature = "tls")] { acceptor.accept(stream).await.unwrap() }
-             #[cfg(not(feature = "tls"))] { stream }
-           };
-           let mut transport = tcp::request_transport(stream);
-            let value;
-            loop {
-              value = match transport.next().await {
-                Some(value) => match value {
-                  Ok(value) => value,
-                  Err(_) => return, // Abort current request.
-                },
-                None => continue, // Not enough data, fetching more.
+  let preamble = conn.read(&mut stream, &mut read_buf, PreambleCodec).await;
+  let hello = match preamble {
+    Ok(Some(Preamble::Hello(hello))) => {
+      let answer = hello.negotiate(conn.shared.compression.features());
+      let written =
+        conn.write(&mut stream, &mut write_buf, ServerHelloCodec, answer).await;
+      if let Err(err) = written {
+        return conn.write_failed(err);
+      }
+      if answer.is_rejected() {
+        let err = io::Error::new(
+          io::ErrorKind::InvalidData,
+          format!(
+            "unsupported protocol versions {}..={}",
+            hello.min_version, hello.max_version
+          ),
+        );
+        return conn.read_failed(err);
+      }
+      answer
+    }
+    Ok(Some(Preamble::Legacy)) => ServerHello::LEGACY,
+    Ok(None) => return,
+    Err(err) => return conn.read_failed(err),
+  };
+  trace::record_protocol(&hello);
+  let payloads = conn.shared.compression.negotiated(hello.features);
+  if hello.version >= MULTIPLEXED_VERSION {
+    let limits = (connection, per_connection);
+    return serve_multiplexed(conn, stream, read_buf, payloads, limits).await;
+  }
+
+  loop {
+    let request =
+      match conn.read(&mut stream, &mut read_buf, RequestFrameCodec).await {
+        Ok(Some(request)) => request,
+        Ok(None) => return,
+        Err(err) => return conn.read_failed(err),
+      };
+    let request = match payloads.decompress(request.arguments) {
+      Ok(arguments) => RequestFrame { arguments, ..request },
+      Err(err) => return conn.read_failed(err),
+    };
+
+    let frame = match &connection {
+      Ok(_) => match request_permits(&limits, per_connection.as_ref()).await {
+        Ok(_permits) => {
+          let peer = conn.peer.clone();
+          let identity = conn.client_identity();
+          let shared = &conn.shared;
+          let dispatched =
+            shared.server.dispatch(shared.timeout, peer, identity, request);
+          let mut shutdown_rx = shared.shutdown_rx.clone();
+          let drain = shared.timeouts.drain;
+          match until_drained(&mut shutdown_rx, drain, dispatched).await {
+            Some(frame) => frame,
+            None => unavailable(),
+          }
+        }
+        Err(Rejected) => overloaded(),
+      },
+      Err(Rejected) => overloaded(),
+    };
+
+    let frame = conn.response(&payloads, frame);
+    let written =
+      conn.write(&mut stream, &mut write_buf, ResponseFrameCodec, frame).await;
+    if let Err(err) = written {
+      return conn.write_failed(err);
+    }
+
+    // Only the one response, the connection isn't served otherwise.
+    if connection.is_err() {
+      return;
+    }
+  }
+}
+
+type Dispatched = Pin<Box<dyn Future<Output = (u32, ResponseFrame)> + Send>>;
+
+/// Serves a connection of protocol version 2, answering requests as they
+/// complete and pinging the client if [ServerServe::with_keepalive] is set.
+/// On shutdown the client is sent a [Frame::GoAway], and the connection is
+/// closed once the requests it names are answered, or the drain timeout
+/// passed.
+async fn serve_multiplexed<T>(
+  conn: Connection,
+  mut stream: T,
+  mut read_buf: BytesMut,
+  payloads: Payloads,
+  (connection, per_connection): (
+    Result<Option<OwnedSemaphorePermit>, Rejected>,
+    Option<Limit>,
+  ),
+) where
+  T: AsyncRead + AsyncWrite + Unpin,
+{
+  let mut write_buf = BytesMut::new();
+  let mut in_flight = FuturesUnordered::<Dispatched>::new();
+  // Of the requests in `in_flight`, to answer them if they outlast shutdown.
+  let mut in_flight_ids = HashSet::new();
+  let mut pings = conn.shared.keepalive.map(Pings::new);
+  let mut shutdown_rx = conn.shared.shutdown_rx.clone();
+  let mut shutting_down = false;
+  let mut last_accepted = 0;
+  let timeouts = conn.shared.timeouts;
+  let mut drain_deadline = None;
+  let mut idle_deadline = Instant::now() + timeouts.idle;
+  let mut read_deadline = None;
+
+  loop {
+    loop {
+      let frame = match FrameCodec.decode(&mut read_buf) {
+        Ok(Some(frame)) => frame,
+        Ok(None) => break,
+        Err(err) => return conn.read_failed(err),
+      };
+      read_deadline = None;
+
+      let answer = match frame {
+        // Sent before the client saw the GoAway, it sends them again
+        // elsewhere.
+        Frame::Request(..) if shutting_down => continue,
+        Frame::Request(id, request) => {
+          idle_deadline = Instant::now() + timeouts.idle;
+          last_accepted = id;
+          let request = match payloads.decompress(request.arguments) {
+            Ok(arguments) => RequestFrame { arguments, ..request },
+            Err(err) => return conn.read_failed(err),
+          };
+          if connection.is_err() {
+            // Only the one response, like on earlier versions.
+            let frame = Frame::Response(id, overloaded());
+            let written =
+              conn.write(&mut stream, &mut write_buf, FrameCodec, frame).await;
+            if let Err(err) = written {
+              return conn.write_failed(err);
+            }
+            return;
+          }
+
+          let shared = conn.shared.clone();
+          let peer = conn.peer.clone();
+          let identity = conn.client_identity();
+          let per_connection = per_connection.clone();
+          in_flight_ids.insert(id);
+          in_flight.push(Box::pin(async move {
+            let limits = &shared.limits;
+            let frame =
+              match request_permits(limits, per_connection.as_ref()).await {
+                Ok(_permits) => {
+                  let server = &shared.server;
+                  let timeout = shared.timeout;
+                  server.dispatch(timeout, peer, identity, request).await
+                }
+                Err(Rejected) => overloaded(),
               };
-              break;
+            (id, frame)
+          }));
+          continue;
+        }
+        // Keeps the connection open just like requests do.
+        Frame::Ping(value) => {
+          idle_deadline = Instant::now() + timeouts.idle;
+          Frame::Pong(value)
+        }
+        Frame::Pong(value) => {
+          if let Some(pings) = &mut pings {
+            pings.pong(value);
+          }
+          continue;
+        }
+        Frame::Response(..) | Frame::GoAway(_) => {
+          let err = io::Error::new(
+            io::ErrorKind::InvalidData,
+            "client sent a frame only servers send",
+          );
+          return conn.read_failed(err);
+        }
+      };
+      let written =
+        conn.write(&mut stream, &mut write_buf, FrameCodec, answer).await;
+      if let Err(err) = written {
+        return conn.write_failed(err);
+      }
+    }
+
+    if shutting_down && in_flight.is_empty() {
+      return;
+    }
+    // The frame started with the first of its bytes.
+    if !read_buf.is_empty() && read_deadline.is_none() {
+      read_deadline = Some(Instant::now() + timeouts.read);
+    }
+    let idle = in_flight.is_empty() && read_buf.is_empty();
+    let ping_at = pings.as_ref().map(Pings::deadline);
+
+    tokio::select! {
+        read = stream.read_buf(&mut read_buf) => {
+          match read {
+            Ok(0) if read_buf.is_empty() => return, // Closed between frames.
+            Ok(0) => {
+              return conn.read_failed(io::ErrorKind::UnexpectedEof.into())
+            }
+            Ok(_) => {}
+            Err(err) => return conn.read_failed(err),
+          }
+        },
+        Some((id, frame)) = in_flight.next(), if !in_flight.is_empty() => {
+          in_flight_ids.remove(&id);
+          let frame = Frame::Response(id, conn.response(&payloads, frame));
+          let written =
+            conn.write(&mut stream, &mut write_buf, FrameCodec, frame).await;
+          if let Err(err) = written {
+            return conn.write_failed(err);
+          }
+          idle_deadline = Instant::now() + timeouts.idle;
+        },
+        _ = sleep_until(idle_deadline), if idle && !shutting_down => return,
+        _ = sleep_until(read_deadline.unwrap_or(idle_deadline)),
+          if read_deadline.is_some() =>
+        {
+          return conn.read_failed(timed_out("request didn't arrive in time"));
+        },
+        _ = sleep_until(ping_at.unwrap_or(idle_deadline)),
+          if ping_at.is_some() =>
+        {
+          let Some(Due::Ping(value)) = pings.as_mut().map(Pings::due) else {
+            let err = timed_out("client didn't answer ping in time");
+            return conn.read_failed(err);
+          };
+          let frame = Frame::Ping(value);
+          let written =
+            conn.write(&mut stream, &mut write_buf, FrameCodec, frame).await;
+          if let Err(err) = written {
+            return conn.write_failed(err);
+          }
+        },
+        _ = sleep_until(drain_deadline.unwrap_or(idle_deadline)),
+          if drain_deadline.is_some() =>
+        {
+          // Whatever didn't finish in time is given up on.
+          for id in in_flight_ids.drain() {
+            let frame = Frame::Response(id, unavailable());
+            let written =
+              conn.write(&mut stream, &mut write_buf, FrameCodec, frame).await;
+            if let Err(err) = written {
+              return conn.write_failed(err);
+            }
+          }
+          return;
+        },
+        // The guard of the value isn't `Send`, so it isn't kept.
+        Ok(()) = async { shutdown_rx.wait_for(|s| *s).await.map(drop) },
+          if !shutting_down =>
+        {
+          // Answers what was already asked, but takes no more requests.
+          shutting_down = true;
+          drain_deadline = Some(Instant::now() + timeouts.drain);
+          let frame = Frame::GoAway(last_accepted);
+          let written =
+            conn.write(&mut stream, &mut write_buf, FrameCodec, frame).await;
+          if let Err(err) = written {
+            return conn.write_failed(err);
+          }
+        },
+    }
+  }
+}
+
+impl Connection {
+  /// Reads the next frame, or `None` once the client closed the connection
+  /// or there is no frame to wait for anymore.
+  async fn read<T, D>(
+    &mut self,
+    stream: &mut T,
+    buf: &mut BytesMut,
+    mut decoder: D,
+  ) -> io::Result<Option<D::Item>>
+  where
+    T: AsyncRead + Unpin,
+    D: Decoder<Error = io::Error>,
+  {
+    let mut deadline = None;
+    loop {
+      if let Some(frame) = decoder.decode(buf)? {
+        return Ok(Some(frame));
+      }
+
+      if buf.is_empty() {
+        let idle = Instant::now() + self.shared.timeouts.idle;
+        let read = tokio::select! {
+            read = timeout_at(idle, stream.read_buf(buf)) => read,
+            Ok(_) = self.shared.shutdown_rx.wait_for(|shutdown| *shutdown) => {
+              return Ok(None)
+            },
+        };
+        match read {
+          Ok(read) => {
+            if read? == 0 {
+              return Ok(None); // Closed between requests.
             }
-            let mut transport = tcp::response_transport(transport.into_inner());
-
-            match server.query(value.command.as_str()) {
-              Some(service_ref) => {
-                  let mut service = service_ref.clone();
-                  match ServeTaskFuture::new(self.timeout, service.call(value.arguments)).await {
-                    Ok(value) => match value {
-                        Ok(bytes) => transport.send(ResponseFrame::Payload(bytes)).await.expect("webcontr internal error: failed to send ResponseFrame::Payload"),
-                        Err(err) => transport.send(ResponseFrame::Error(err)).await.expect("webcontr internal error: failed to send ResponseFrame::Error")
-                    },
-                    Err(()) => transport.send(ResponseFrame::Error(ResponseErrorKind::Timeout)).await.expect("webcontr internal error: failed to send ResponseFrame::Error(ResponseErrorKind::Timeout)"),
+          }
+          Err(_) => return Ok(None), // Idle for too long.
+        }
+        continue;
+      }
+
+      // The frame started with the first of its bytes.
+      let deadline = *deadline
+        .get_or_insert_with(|| Instant::now() + self.shared.timeouts.read);
+      match timeout_at(deadline, stream.read_buf(buf)).await {
+        Ok(read) => {
+          if read? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+          }
+        }
+        Err(_) => return Err(timed_out("request didn't arrive in time")),
+      }
+    }
+  }
+
+  async fn write<T, E, I>(
+    &self,
+    stream: &mut T,
+    buf: &mut BytesMut,
+    mut encoder: E,
+    item: I,
+  ) -> io::Result<()>
+  where
+    T: AsyncWrite + Unpin,
+    E: Encoder<I, Error = io::Error>,
+  {
+    let deadline = Instant::now() + self.shared.timeouts.write;
+    let written = async {
+      encoder.encode(item, buf)?;
+      stream.write_all(buf).await?;
+      buf.clear();
+      stream.flush().await
+    };
+    match timeout_at(deadline, written).await {
+      Ok(written) => written,
+      Err(_) => Err(timed_out("response wasn't sent in time")),
+    }
+  }
+
+  /// `frame` as it is sent. A payload that can't be, e.g. because it is too
+  /// large for one frame, fails only its own request, and the connection
+  /// stays open for the others.
+  fn response(
+    &self,
+    payloads: &Payloads,
+    frame: ResponseFrame,
+  ) -> ResponseFrame {
+    let ResponseFrame::Payload(payload) = frame else {
+      return frame;
+    };
+    let payload = match payloads.compress(payload) {
+      Ok(payload) => payload,
+      Err(err) => {
+        self.response_failed(err);
+        return ResponseFrame::Error(ResponseErrorKind::Internal(None));
+      }
+    };
+    if let Err(err) = payload_len(&payload) {
+      self.response_failed(err);
+      let status =
+        Status::new(Code::ResourceExhausted).with_message("response too large");
+      return ResponseFrame::Error(ResponseErrorKind::Status(status));
+    }
+    ResponseFrame::Payload(payload)
+  }
+
+  fn response_failed(&self, err: io::Error) {
+    trace::warn_event!(error = %err, "failed to send response");
+    metrics::record_connection_error("write");
+    let peer = self.peer.clone();
+    (self.shared.on_error)(&ServeError::Write { peer, source: err });
+  }
+
+  fn read_failed(self, err: io::Error) {
+    trace::warn_event!(error = %err, "failed to read request");
+    metrics::record_connection_error("read");
+    (self.shared.on_error)(&ServeError::Read { peer: self.peer, source: err });
+  }
+
+  fn write_failed(self, err: io::Error) {
+    trace::warn_event!(error = %err, "failed to send response");
+    metrics::record_connection_error("write");
+    (self.shared.on_error)(&ServeError::Write { peer: self.peer, source: err });
+  }
+}
+
+fn timed_out(message: &'static str) -> io::Error {
+  io::Error::new(io::ErrorKind::TimedOut, message)
+}
+
+/// Slots for a single request. The connection's own is taken first, so a busy
+/// connection doesn't hold on to a global slot while waiting for it.
+async fn request_permits(
+  limits: &Limits,
+  per_connection: Option<&Limit>,
+) -> Result<
+  (Option<OwnedSemaphorePermit>, Option<OwnedSemaphorePermit>),
+  Rejected,
+> {
+  let own = limit::acquire(per_connection).await?;
+  let global = limit::acquire(limits.in_flight.as_ref()).await?;
+  Ok((own, global))
+}
+
+/// Runs `dispatched` to completion, unless the server shuts down and it
+/// doesn't complete within `drain` after.
+async fn until_drained<F: Future>(
+  shutdown_rx: &mut watch::Receiver<bool>,
+  drain: Duration,
+  dispatched: F,
+) -> Option<F::Output> {
+  let mut dispatched = std::pin::pin!(dispatched);
+  tokio::select! {
+      output = &mut dispatched => return Some(output),
+      Ok(()) = async { shutdown_rx.wait_for(|s| *s).await.map(drop) } => {},
+  }
+  tokio::time::timeout(drain, dispatched).await.ok()
+}
+
+fn unavailable() -> ResponseFrame {
+  trace::warn_event!("gave up on request, server shut down");
+  let status = Status::new(Code::Unavailable).with_message("server shut down");
+  ResponseFrame::Error(ResponseErrorKind::Status(status))
+}
+
+fn overloaded() -> ResponseFrame {
+  trace::warn_event!("rejected request, server overloaded");
+  ResponseFrame::Error(ResponseErrorKind::Overloaded)
+}
+
+/// Serves a [FrozenServer] over a channel of frames, see
+/// [crate::Server::serve_channel]. Requests are handled concurrently, with
+/// the same limits and error reporting as [ServerServe].
+pub struct ChannelServe<C> {
+  pub(crate) server: FrozenServer,
+  pub(crate) channel: C,
+  pub(crate) timeout: Option<Duration>,
+  pub(crate) max_in_flight: Option<usize>,
+  pub(crate) overload: Overload,
+  pub(crate) on_error: Option<ErrorHook>,
+}
+
+impl<C> ChannelServe<C> {
+  pub fn with_timeout(mut self, dur: Duration) -> Self {
+    self.timeout = Some(dur);
+    self
+  }
+
+  /// Limits the number of requests handled at once, see
+  /// [ServerServe::with_max_in_flight].
+  pub fn with_max_in_flight(mut self, max: usize) -> Self {
+    self.max_in_flight = Some(max);
+    self
+  }
+
+  /// What to do once the limit is reached, [Overload::Reject] by default.
+  pub fn with_overload(mut self, overload: Overload) -> Self {
+    self.overload = overload;
+    self
+  }
+
+  /// Called when the channel fails, reported as [ServeError::Read] or
+  /// [ServeError::Write] from [PeerInfo::Local].
+  pub fn on_error<F>(mut self, hook: F) -> Self
+  where
+    F: Fn(&ServeError) + Send + Sync + 'static,
+  {
+    self.on_error = Some(Arc::new(hook));
+    self
+  }
+}
+
+impl<C, E> IntoFuture for ChannelServe<C>
+where
+  C: Stream<Item = Result<RequestFrame, E>>
+    + Sink<ResponseFrame, Error = E>
+    + Unpin
+    + Send
+    + 'static,
+  E: std::fmt::Display + Send + 'static,
+{
+  type Output = Result<(), E>;
+
+  type IntoFuture = Pin<Box<dyn Future<Output = Self::Output> + Send>>;
+
+  fn into_future(mut self) -> Self::IntoFuture {
+    let limit = self.max_in_flight.map(|max| Limit::new(max, self.overload));
+    let on_error = self.on_error.take().unwrap_or_else(|| Arc::new(|_| {}));
+    let failed = move |err: &E, write: bool| {
+      let peer = PeerInfo::Local;
+      let source = io::Error::other(err.to_string());
+      let err = if write {
+        trace::warn_event!(error = %source, "failed to send response");
+        metrics::record_connection_error("write");
+        ServeError::Write { peer, source }
+      } else {
+        trace::warn_event!(error = %source, "failed to read request");
+        metrics::record_connection_error("read");
+        ServeError::Read { peer, source }
+      };
+      on_error(&err);
+    };
+
+    Box::pin(async move {
+      // Answered in the order they arrived, the channel can't tell
+      // responses apart otherwise.
+      let mut in_flight = FuturesOrdered::new();
+      let mut receiving = true;
+      loop {
+        tokio::select! {
+            // Handlers start before more requests are taken, so they get
+            // their slot first.
+            biased;
+            Some(frame) = in_flight.next() => {
+              if let Err(err) = self.channel.send(frame).await {
+                failed(&err, true);
+                return Err(err);
+              }
+            },
+            request = self.channel.next(), if receiving => match request {
+              Some(Ok(request)) => {
+                let server = self.server.clone();
+                let timeout = self.timeout;
+                let limit = limit.clone();
+                in_flight.push_back(async move {
+                  match limit::acquire(limit.as_ref()).await {
+                    Ok(_permit) => {
+                      let peer = PeerInfo::Local;
+                      server.dispatch(timeout, peer, None, request).await
+                    }
+                    Err(Rejected) => overloaded(),
                   }
+                });
               }
-              _ => {
-                transport
-                  .send(ResponseFrame::Error(ResponseErrorKind::MethodNotFound))
-                  .await
-                  .expect("webcontr internal error: failed to send ResponseFrame::Error(ResponseErrorKind::MethodNotFound)");
+              Some(Err(err)) => {
+                failed(&err, false);
+                return Err(err);
               }
-            };
-        });
+              // Hung up, but still waits for the answers.
+              None => receiving = false,
+            },
+            else => return Ok(()),
+        }
       }
     })
   }
@@ -1,21 +1,70 @@
 #[cfg(feature = "tls")]
 use crate::tls::TLSPaths;
 use crate::{
-  serve::ServerServe, transport::frame::ResponseErrorKind,
-  utils::BoxCloneService, ServiceName,
+  accept::Accept,
+  auth::{self, Authenticate, Credentials},
+  compression::Compression,
+  context::{ClientIdentity, Context, PeerInfo},
+  metrics::{self, Side},
+  rate_limit::RateLimiter,
+  reflection::{Reflection, ReflectionService, ServiceDescriptor},
+  serve::{
+    ChannelServe, ConnectionTimeouts, ListenerOptions, Overload,
+    ServeTaskFuture, ServerServe,
+  },
+  trace,
+  transport::frame::{RequestFrame, ResponseErrorKind, ResponseFrame},
+  utils::BoxCloneService,
+  ServiceName,
 };
 use bytes::Bytes;
-use std::{collections::HashMap, future::Future, pin::Pin, sync::Arc};
+use futures_util::{FutureExt, Sink, Stream};
+use std::{
+  any::Any,
+  collections::HashMap,
+  future::Future,
+  panic::{self, AssertUnwindSafe},
+  pin::Pin,
+  sync::Arc,
+  time::{Duration, Instant},
+};
 use tokio::net::TcpListener;
+#[cfg(unix)]
+use tokio::net::UnixListener;
 use tower::Service;
 
 type ServiceFuture =
   Pin<Box<dyn Future<Output = Result<Bytes, ResponseErrorKind>> + Send>>;
 
+type PanicHook = Arc<dyn Fn(&HandlerPanic) + Send + Sync>;
+
+/// Served without credentials unless [Server::with_public_services] says
+/// otherwise, so liveness probes don't need any.
+const DEFAULT_PUBLIC_SERVICES: &[&str] = &["Health"];
+
 #[derive(Default)]
 pub struct Server {
   pub hash:
     HashMap<&'static str, BoxCloneService<Bytes, Bytes, ResponseErrorKind>>,
+  methods: HashMap<&'static str, &'static [&'static str]>,
+  timeouts: HashMap<&'static str, &'static [Option<Duration>]>,
+  /// Only described once frozen with reflection on, as that traces the
+  /// types of every method.
+  services: Vec<Box<dyn ServiceName + Send + Sync>>,
+  reflection: bool,
+  rate_limiter: Option<RateLimiter>,
+  authenticator: Option<Arc<dyn Authenticate>>,
+  public_services: Option<Vec<&'static str>>,
+  panic_hook: Option<PanicHook>,
+  expose_panic_messages: bool,
+}
+
+/// A service handler panicked while handling a request.
+#[derive(Debug)]
+pub struct HandlerPanic<'a> {
+  pub service: &'a str,
+  /// The panic message, if the payload was a string.
+  pub message: Option<&'a str>,
 }
 
 #[derive(Clone)]
@@ -24,18 +73,217 @@ pub struct FrozenServer {
 }
 
 impl From<Server> for FrozenServer {
-  fn from(value: Server) -> Self {
+  fn from(mut value: Server) -> Self {
+    let services = std::mem::take(&mut value.services);
+    if value.reflection {
+      let mut reflection = ReflectionService::new(Vec::new()).into_serve();
+      let mut services: Vec<ServiceDescriptor> =
+        services.iter().map(|service| service.descriptor()).collect();
+      services.push(reflection.descriptor());
+      reflection.service = ReflectionService::new(services);
+      value = value.add_service(reflection);
+    }
+
     FrozenServer { inner: Arc::new(value) }
   }
 }
 
 impl FrozenServer {
+  /// Like [Server::serve_with], but can be called several times to serve the
+  /// same services on more than one listener.
+  pub fn serve_with<A: Accept + 'static>(&self, acceptor: A) -> ServerServe {
+    self.serve_with_options(acceptor, ListenerOptions::default())
+  }
+
+  pub(crate) fn serve_with_options<A: Accept + 'static>(
+    &self,
+    acceptor: A,
+    options: ListenerOptions,
+  ) -> ServerServe {
+    ServerServe {
+      server: self.clone(),
+      listeners: Vec::new(),
+      listener_error: None,
+      timeout: None,
+      on_error: None,
+      max_connections: None,
+      max_in_flight: None,
+      max_in_flight_per_connection: None,
+      overload: Overload::default(),
+      timeouts: ConnectionTimeouts::default(),
+      keepalive: None,
+      shutdown: None,
+      compression: Compression::default(),
+    }
+    .with_listener(acceptor, options)
+  }
+
   pub(crate) fn query(
-    &mut self,
+    &self,
     cmd: &str,
   ) -> Option<&BoxCloneService<Bytes, Bytes, ResponseErrorKind>> {
     self.inner.hash.get(cmd)
   }
+
+  /// Index of the method a request calls, read from the enum variant index
+  /// bincode puts in front of the arguments.
+  fn method_index(request: &RequestFrame) -> Option<usize> {
+    let index = request.arguments.get(..4)?;
+    Some(u32::from_le_bytes(index.try_into().ok()?) as usize)
+  }
+
+  fn method_name(&self, request: &RequestFrame) -> Option<&'static str> {
+    let methods = self.inner.methods.get(request.command.as_str())?;
+    methods.get(Self::method_index(request)?).copied()
+  }
+
+  fn method_timeout(&self, request: &RequestFrame) -> Option<Duration> {
+    let timeouts = self.inner.timeouts.get(request.command.as_str())?;
+    timeouts.get(Self::method_index(request)?).copied().flatten()
+  }
+
+  /// Routes a single request to the service it names and produces the frame
+  /// that should be sent back, regardless of which transport it came from.
+  pub(crate) async fn dispatch(
+    &self,
+    timeout: Option<Duration>,
+    peer: PeerInfo,
+    identity: Option<ClientIdentity>,
+    mut request: RequestFrame,
+  ) -> ResponseFrame {
+    let method = self.method_name(&request);
+    // Declared on the method, so more specific than the listener's.
+    let timeout = self.method_timeout(&request).or(timeout);
+    let request_size = request.arguments.len();
+    let span =
+      trace::server_rpc_span(&request.command, method, &peer, request_size);
+    trace::extract(&span, &request.metadata);
+    let mut context = Context::new(peer, std::mem::take(&mut request.metadata))
+      .with_client_identity(identity);
+    // Only registered names are used as labels, clients choose the rest.
+    let service = self
+      .inner
+      .hash
+      .get_key_value(request.command.as_str())
+      .map_or("unknown", |(name, _)| *name);
+    let in_flight = metrics::InFlight::new(Side::Server, service);
+    let started = Instant::now();
+
+    let checked = match self.authenticate(service, &mut context).await {
+      Ok(()) => match &self.inner.rate_limiter {
+        Some(limiter) => limiter.check(&context, service, method),
+        None => Ok(()),
+      },
+      Err(err) => Err(err),
+    };
+    let response = match checked {
+      Ok(()) => {
+        let call = self.call(timeout, context, request);
+        trace::instrument(call, &span).await
+      }
+      Err(err) => ResponseFrame::Error(err),
+    };
+
+    let elapsed = started.elapsed();
+    drop(in_flight);
+    trace::record_response(&span, &response, elapsed);
+    metrics::record_request(
+      Side::Server,
+      service,
+      method.unwrap_or("unknown"),
+      request_size,
+      &response,
+      elapsed,
+    );
+    response
+  }
+
+  /// Attaches who made the request to `context`, if the server authenticates
+  /// requests to `service`.
+  async fn authenticate(
+    &self,
+    service: &str,
+    context: &mut Context,
+  ) -> Result<(), ResponseErrorKind> {
+    let Some(authenticator) = &self.inner.authenticator else {
+      return Ok(());
+    };
+    let public = self.inner.public_services.as_deref();
+    if public.unwrap_or(DEFAULT_PUBLIC_SERVICES).contains(&service) {
+      return Ok(());
+    }
+    let Some(credentials) = Credentials::from_metadata(context.metadata())
+    else {
+      return Err(auth::unauthenticated("missing credentials").into());
+    };
+
+    match authenticator.authenticate(&credentials).await {
+      Ok(principal) => {
+        context.set_principal(principal);
+        Ok(())
+      }
+      Err(status) => {
+        trace::warn_event!(%status, "request not authenticated");
+        Err(status.into())
+      }
+    }
+  }
+
+  async fn call(
+    &self,
+    timeout: Option<Duration>,
+    context: Context,
+    request: RequestFrame,
+  ) -> ResponseFrame {
+    let Some(service_ref) = self.query(request.command.as_str()) else {
+      return ResponseFrame::Error(ResponseErrorKind::MethodNotFound);
+    };
+
+    let mut service = service_ref.clone();
+    let future = panic::catch_unwind(AssertUnwindSafe(|| {
+      let future = service.call(request.arguments);
+      AssertUnwindSafe(ServeTaskFuture::new(timeout, future)).catch_unwind()
+    }));
+    let result = match future {
+      Ok(future) => context.scope(future).await,
+      Err(payload) => Err(payload),
+    };
+
+    match result {
+      Ok(Ok(Ok(bytes))) => ResponseFrame::Payload(bytes),
+      Ok(Ok(Err(err))) => ResponseFrame::Error(err),
+      Ok(Err(())) => {
+        trace::warn_event!(?timeout, "request timed out");
+        ResponseFrame::Error(ResponseErrorKind::Timeout)
+      }
+      Err(payload) => {
+        self.handle_panic(request.command.as_str(), payload.as_ref())
+      }
+    }
+  }
+
+  fn handle_panic(
+    &self,
+    service: &str,
+    payload: &(dyn Any + Send),
+  ) -> ResponseFrame {
+    let message = match payload.downcast_ref::<&'static str>() {
+      Some(message) => Some(*message),
+      None => payload.downcast_ref::<String>().map(String::as_str),
+    };
+
+    trace::error_event!(service, message, "handler panicked");
+
+    if let Some(hook) = &self.inner.panic_hook {
+      hook(&HandlerPanic { service, message });
+    }
+
+    let message = match self.inner.expose_panic_messages {
+      true => message.map(str::to_string),
+      false => None,
+    };
+    ResponseFrame::Error(ResponseErrorKind::Internal(message))
+  }
 }
 
 #[cfg(test)]
@@ -56,21 +304,116 @@ impl Server {
       + Send
       + Clone,
   {
+    self.methods.insert(service.name(), service.methods());
+    self.timeouts.insert(service.name(), service.timeouts());
+    self.services.retain(|added| added.name() != service.name());
+    self.services.push(Box::new(service.clone()));
     self.hash.insert(service.name(), BoxCloneService::new(service));
     self
   }
 
+  /// Called whenever a handler panics. The panic is caught either way and the
+  /// client receives [ResponseErrorKind::Internal].
+  pub fn on_panic<F>(mut self, hook: F) -> Self
+  where
+    F: Fn(&HandlerPanic) + Send + Sync + 'static,
+  {
+    self.panic_hook = Some(Arc::new(hook));
+    self
+  }
+
+  /// Sends the panic message of a handler to the client as part of
+  /// [ResponseErrorKind::Internal]. Off by default, as it may leak details
+  /// about the server.
+  pub fn with_panic_messages(mut self) -> Self {
+    self.expose_panic_messages = true;
+    self
+  }
+
+  /// Limits how many requests each client can make, see
+  /// [crate::rate_limit].
+  pub fn with_rate_limiter(mut self, limiter: RateLimiter) -> Self {
+    self.rate_limiter = Some(limiter);
+    self
+  }
+
+  /// Rejects requests without valid credentials before they reach a
+  /// handler, for every service but the ones of
+  /// [Server::with_public_services], see [crate::auth].
+  pub fn with_authenticator(
+    mut self,
+    authenticator: impl Authenticate + 'static,
+  ) -> Self {
+    self.authenticator = Some(Arc::new(authenticator));
+    self
+  }
+
+  /// Services anyone may call with [Server::with_authenticator] set, only
+  /// [crate::health] by default. Replaces the default, e.g. pass the name of
+  /// [crate::reflection::Reflection] along with `"Health"` to keep both
+  /// public, or nothing to authenticate every service.
+  pub fn with_public_services(
+    mut self,
+    services: impl IntoIterator<Item = &'static str>,
+  ) -> Self {
+    self.public_services = Some(services.into_iter().collect());
+    self
+  }
+
+  /// Adds a [crate::reflection::Reflection] service, listing every service
+  /// of the server with its methods.
+  pub fn with_reflection(mut self) -> Self {
+    self.reflection = true;
+    self
+  }
+
   pub fn serve(
     self,
     tcp_listener: TcpListener,
     #[cfg(feature = "tls")] tls_paths: TLSPaths,
   ) -> ServerServe {
-    ServerServe {
+    let options = ListenerOptions::new();
+    #[cfg(feature = "tls")]
+    let options = options.with_tls(tls_paths);
+
+    FrozenServer::from(self).serve_with_options(tcp_listener, options)
+  }
+
+  /// Serves plaintext on a Unix domain socket, see
+  /// [crate::transport::unix::UnixSocket] for binding one.
+  ///
+  /// Handlers can read the credentials of the calling process through
+  /// [crate::context::peer].
+  #[cfg(unix)]
+  pub fn serve_unix(self, listener: UnixListener) -> ServerServe {
+    self.serve_with(listener)
+  }
+
+  /// Serves connections from any [Accept] implementation.
+  pub fn serve_with<A: Accept + 'static>(self, acceptor: A) -> ServerServe {
+    FrozenServer::from(self).serve_with(acceptor)
+  }
+
+  /// Serves requests arriving over an in-memory transport, such as one half
+  /// of [crate::transport::channel::unbounded], without binding a socket.
+  ///
+  /// Requests are handled concurrently and answered in the order they are
+  /// received. The returned future resolves once the peer hung up and every
+  /// request was answered.
+  pub fn serve_channel<C, E>(self, channel: C) -> ChannelServe<C>
+  where
+    C: Stream<Item = Result<RequestFrame, E>>
+      + Sink<ResponseFrame, Error = E>
+      + Unpin
+      + Send,
+  {
+    ChannelServe {
       server: FrozenServer::from(self),
-      listener: tcp_listener,
+      channel,
       timeout: None,
-      #[cfg(feature = "tls")]
-      tls_paths,
+      max_in_flight: None,
+      overload: Overload::default(),
+      on_error: None,
     }
   }
 }
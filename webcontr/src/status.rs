@@ -0,0 +1,160 @@
+//! gRPC like status codes, for errors the other [ResponseErrorKind] variants
+//! don't cover.
+//!
+//! A [Status] is sent in the response frame with its [Code], an optional
+//! message for humans and optional details, e.g. a serialized struct both
+//! sides agreed on.
+//!
+//! Service methods declared to return `Result<T, webcontr::status::Status>`,
+//! or `Result<T, Status>` marked `#[status]`, send their errors this way. Only
+//! `T` goes in the response, so generated clients return
+//! `Result<T, ClientError>` and the status arrives as
+//! [ResponseErrorKind::Status] in [crate::ClientError::ServerError].
+
+use std::fmt;
+
+use bytes::Bytes;
+
+use crate::transport::frame::ResponseErrorKind;
+
+/// Numbered as in gRPC. Peers may send codes added later, they are read as
+/// [Code::Unknown].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Code {
+  Cancelled = 1,
+  Unknown = 2,
+  InvalidArgument = 3,
+  DeadlineExceeded = 4,
+  NotFound = 5,
+  AlreadyExists = 6,
+  PermissionDenied = 7,
+  ResourceExhausted = 8,
+  FailedPrecondition = 9,
+  Aborted = 10,
+  OutOfRange = 11,
+  Unimplemented = 12,
+  Internal = 13,
+  Unavailable = 14,
+  DataLoss = 15,
+  Unauthenticated = 16,
+}
+
+impl Code {
+  /// A short, stable identifier, e.g. to label logs.
+  pub fn name(self) -> &'static str {
+    match self {
+      Code::Cancelled => "cancelled",
+      Code::Unknown => "unknown",
+      Code::InvalidArgument => "invalid_argument",
+      Code::DeadlineExceeded => "deadline_exceeded",
+      Code::NotFound => "not_found",
+      Code::AlreadyExists => "already_exists",
+      Code::PermissionDenied => "permission_denied",
+      Code::ResourceExhausted => "resource_exhausted",
+      Code::FailedPrecondition => "failed_precondition",
+      Code::Aborted => "aborted",
+      Code::OutOfRange => "out_of_range",
+      Code::Unimplemented => "unimplemented",
+      Code::Internal => "internal",
+      Code::Unavailable => "unavailable",
+      Code::DataLoss => "data_loss",
+      Code::Unauthenticated => "unauthenticated",
+    }
+  }
+
+  pub(crate) fn from_u8(code: u8) -> Code {
+    match code {
+      1 => Code::Cancelled,
+      3 => Code::InvalidArgument,
+      4 => Code::DeadlineExceeded,
+      5 => Code::NotFound,
+      6 => Code::AlreadyExists,
+      7 => Code::PermissionDenied,
+      8 => Code::ResourceExhausted,
+      9 => Code::FailedPrecondition,
+      10 => Code::Aborted,
+      11 => Code::OutOfRange,
+      12 => Code::Unimplemented,
+      13 => Code::Internal,
+      14 => Code::Unavailable,
+      15 => Code::DataLoss,
+      16 => Code::Unauthenticated,
+      _ => Code::Unknown,
+    }
+  }
+}
+
+impl fmt::Display for Code {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(self.name())
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Status {
+  code: Code,
+  message: Option<String>,
+  details: Bytes,
+}
+
+impl Status {
+  pub fn new(code: Code) -> Self {
+    Self { code, message: None, details: Bytes::new() }
+  }
+
+  /// Cut short to 64 KiB when sent.
+  pub fn with_message(mut self, message: impl Into<String>) -> Self {
+    self.message = Some(message.into());
+    self
+  }
+
+  /// Attaches `details` for the client to decode, they aren't interpreted
+  /// otherwise. Details over 64 KiB aren't sent.
+  pub fn with_details(mut self, details: impl Into<Bytes>) -> Self {
+    self.details = details.into();
+    self
+  }
+
+  pub fn code(&self) -> Code {
+    self.code
+  }
+
+  pub fn message(&self) -> Option<&str> {
+    self.message.as_deref()
+  }
+
+  /// Empty if none were attached.
+  pub fn details(&self) -> &Bytes {
+    &self.details
+  }
+}
+
+impl fmt::Display for Status {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match &self.message {
+      Some(message) => write!(f, "{}: {message}", self.code),
+      None => write!(f, "{}", self.code),
+    }
+  }
+}
+
+impl From<Status> for ResponseErrorKind {
+  fn from(status: Status) -> Self {
+    ResponseErrorKind::Status(status)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn codes_roundtrip() {
+    for code in 1..=16 {
+      assert_eq!(Code::from_u8(code) as u8, code);
+    }
+    assert_eq!(Code::from_u8(0), Code::Unknown);
+    assert_eq!(Code::from_u8(42), Code::Unknown);
+  }
+}
@@ -1,14 +1,37 @@
-use std::{fs::File, io::Read};
+use std::{
+  future::Future,
+  io,
+  pin::Pin,
+  sync::Arc,
+  task::{ready, Context, Poll},
+};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use tokio::{
+  io::{AsyncRead, AsyncWrite, ReadBuf},
+  time::{sleep, Duration, Sleep},
+};
+use tokio_rustls::{
+  rustls::{
+    pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer},
+    server::WebPkiClientVerifier,
+    ClientConfig, RootCertStore, ServerConfig,
+  },
+  TlsAcceptor,
+};
 
-use tokio_rustls::rustls::{
-  pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer},
-  ServerConfig,
+use crate::{
+  accept::{Accept, IdentityCell},
+  context::{ClientIdentity, PeerInfo},
+  trace,
 };
 
 #[derive(Clone)]
 pub struct TLSPaths {
   cert_path: String,
   key_path: String,
+  client_ca_path: Option<String>,
 }
 
 impl TLSPaths {
@@ -16,28 +39,219 @@ impl TLSPaths {
     cert_path: impl Into<String>,
     key_path: impl Into<String>,
   ) -> Self {
-    Self { cert_path: cert_path.into(), key_path: key_path.into() }
+    Self {
+      cert_path: cert_path.into(),
+      key_path: key_path.into(),
+      client_ca_path: None,
+    }
+  }
+
+  /// Only accepts clients with a certificate issued by one of the CAs in the
+  /// PEM file at `path`, handlers see it as
+  /// [crate::context::Context::client_identity].
+  pub fn with_client_ca(mut self, path: impl Into<String>) -> Self {
+    self.client_ca_path = Some(path.into());
+    self
   }
 }
 
 impl TLSPaths {
-  pub fn serverconfig_from_paths(self) -> ServerConfig {
-    let mut file = File::open(self.cert_path).unwrap();
-    let mut content = Vec::default();
-    file.read_to_end(&mut content).unwrap();
+  /// Reads the certificate chain, key and client CAs, failing with
+  /// [io::ErrorKind::InvalidData] on files that don't hold what they should.
+  pub fn serverconfig_from_paths(self) -> io::Result<ServerConfig> {
+    let content = std::fs::read(self.cert_path)?;
     let cert = CertificateDer::pem_slice_iter(&content)
       .collect::<Result<Vec<_>, _>>()
-      .unwrap()
-      .clone();
-
-    let mut file = File::open(self.key_path).unwrap();
-    let mut content = Vec::default();
-    file.read_to_end(&mut content).unwrap();
-    let key = PrivateKeyDer::from_pem_slice(&content).unwrap();
-
-    ServerConfig::builder()
-      .with_no_client_auth()
-      .with_single_cert(cert, key)
-      .unwrap()
+      .map_err(invalid_data)?;
+
+    let content = std::fs::read(self.key_path)?;
+    let key = PrivateKeyDer::from_pem_slice(&content).map_err(invalid_data)?;
+
+    let builder = ServerConfig::builder();
+    let builder = match self.client_ca_path {
+      Some(path) => {
+        let content = std::fs::read(path)?;
+        let mut roots = RootCertStore::empty();
+        for cert in CertificateDer::pem_slice_iter(&content) {
+          roots.add(cert.map_err(invalid_data)?).map_err(invalid_data)?;
+        }
+        let verifier = WebPkiClientVerifier::builder(Arc::new(roots))
+          .build()
+          .map_err(invalid_data)?;
+        builder.with_client_cert_verifier(verifier)
+      }
+      None => builder.with_no_client_auth(),
+    };
+    builder.with_single_cert(cert, key).map_err(invalid_data)
+  }
+}
+
+fn invalid_data(
+  err: impl Into<Box<dyn std::error::Error + Send + Sync>>,
+) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+/// Client config trusting the certificates in the PEM file at `root_path`,
+/// for [crate::transport::tcp::client::TcpTransport::with_tls_config].
+pub fn clientconfig_from_pem(
+  root_path: impl AsRef<std::path::Path>,
+) -> io::Result<ClientConfig> {
+  let content = std::fs::read(root_path)?;
+  let mut roots = RootCertStore::empty();
+  for cert in CertificateDer::pem_slice_iter(&content) {
+    roots.add(cert.map_err(invalid_data)?).map_err(invalid_data)?;
+  }
+
+  Ok(
+    ClientConfig::builder().with_root_certificates(roots).with_no_client_auth(),
+  )
+}
+
+/// Wraps another [Accept] and serves TLS on top of its connections.
+pub struct TlsListener<A> {
+  inner: A,
+  acceptor: TlsAcceptor,
+  handshake_timeout: Duration,
+}
+
+const DEFAULT_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+impl<A> TlsListener<A> {
+  pub fn new(inner: A, config: Arc<ServerConfig>) -> Self {
+    Self {
+      inner,
+      acceptor: TlsAcceptor::from(config),
+      handshake_timeout: DEFAULT_HANDSHAKE_TIMEOUT,
+    }
+  }
+
+  /// Fails connections that haven't finished the handshake `dur` after they
+  /// were accepted, 10 seconds by default.
+  pub fn with_handshake_timeout(mut self, dur: Duration) -> Self {
+    self.handshake_timeout = dur;
+    self
+  }
+
+  /// See [TLSPaths::serverconfig_from_paths] for how it fails.
+  pub fn from_paths(inner: A, tls_paths: TLSPaths) -> io::Result<Self> {
+    Ok(Self::new(inner, Arc::new(tls_paths.serverconfig_from_paths()?)))
+  }
+}
+
+#[async_trait]
+impl<A: Accept> Accept for TlsListener<A> {
+  type Io = TlsStream<A::Io>;
+
+  async fn accept(&mut self) -> io::Result<(Self::Io, PeerInfo)> {
+    let (stream, peer) = self.inner.accept().await?;
+    let state = TlsState::Handshaking(
+      self.acceptor.accept(stream),
+      Box::pin(sleep(self.handshake_timeout)),
+    );
+    let identity = IdentityCell::default();
+    Ok((TlsStream { state, identity }, peer))
+  }
+
+  fn client_identity(io: &Self::Io) -> Option<IdentityCell> {
+    Some(io.identity.clone())
+  }
+}
+
+/// A server side TLS connection that finishes its handshake the first time
+/// it is read from or written to.
+pub struct TlsStream<T> {
+  state: TlsState<T>,
+  identity: IdentityCell,
+}
+
+enum TlsState<T> {
+  Handshaking(tokio_rustls::Accept<T>, Pin<Box<Sleep>>),
+  Streaming(tokio_rustls::server::TlsStream<T>),
+  Failed,
+}
+
+impl<T: AsyncRead + AsyncWrite + Unpin> TlsStream<T> {
+  fn poll_handshake(
+    &mut self,
+    cx: &mut Context<'_>,
+  ) -> Poll<io::Result<&mut tokio_rustls::server::TlsStream<T>>> {
+    if let TlsState::Handshaking(accept, deadline) = &mut self.state {
+      match Pin::new(accept).poll(cx) {
+        Poll::Pending if deadline.as_mut().poll(cx).is_ready() => {
+          trace::warn_event!("tls handshake timed out");
+          self.state = TlsState::Failed;
+          return Poll::Ready(Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            "tls handshake timed out",
+          )));
+        }
+        Poll::Pending => return Poll::Pending,
+        Poll::Ready(Ok(stream)) => {
+          // Only set when the config verifies client certificates.
+          let certificates = stream.get_ref().1.peer_certificates();
+          let identity = certificates.and_then(|certificates| {
+            ClientIdentity::new(
+              certificates.iter().map(|cert| Bytes::copy_from_slice(cert)),
+            )
+          });
+          if let Some(identity) = identity {
+            let _ = self.identity.set(identity);
+          }
+          self.state = TlsState::Streaming(stream);
+        }
+        Poll::Ready(Err(err)) => {
+          trace::warn_event!(error = %err, "tls handshake failed");
+          self.state = TlsState::Failed;
+          return Poll::Ready(Err(err));
+        }
+      }
+    }
+
+    match &mut self.state {
+      TlsState::Streaming(stream) => Poll::Ready(Ok(stream)),
+      _ => Poll::Ready(Err(io::Error::new(
+        io::ErrorKind::NotConnected,
+        "tls handshake failed",
+      ))),
+    }
+  }
+}
+
+impl<T: AsyncRead + AsyncWrite + Unpin> AsyncRead for TlsStream<T> {
+  fn poll_read(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    buf: &mut ReadBuf<'_>,
+  ) -> Poll<io::Result<()>> {
+    let stream = ready!(self.get_mut().poll_handshake(cx))?;
+    Pin::new(stream).poll_read(cx, buf)
+  }
+}
+
+impl<T: AsyncRead + AsyncWrite + Unpin> AsyncWrite for TlsStream<T> {
+  fn poll_write(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    buf: &[u8],
+  ) -> Poll<io::Result<usize>> {
+    let stream = ready!(self.get_mut().poll_handshake(cx))?;
+    Pin::new(stream).poll_write(cx, buf)
+  }
+
+  fn poll_flush(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+  ) -> Poll<io::Result<()>> {
+    let stream = ready!(self.get_mut().poll_handshake(cx))?;
+    Pin::new(stream).poll_flush(cx)
+  }
+
+  fn poll_shutdown(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+  ) -> Poll<io::Result<()>> {
+    let stream = ready!(self.get_mut().poll_handshake(cx))?;
+    Pin::new(stream).poll_shutdown(cx)
   }
 }
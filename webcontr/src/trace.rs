@@ -0,0 +1,293 @@
+//! Instrumentation points, compiled to nothing without the `tracing` feature
+//! so call sites don't need to be gated.
+
+use std::{future::Future, time::Duration};
+
+use crate::{
+  context::PeerInfo,
+  metadata::Metadata,
+  transport::{frame::ResponseFrame, preamble::ServerHello},
+};
+
+#[cfg(feature = "tracing")]
+pub(crate) use tracing::Span;
+
+#[cfg(not(feature = "tracing"))]
+#[derive(Clone)]
+pub(crate) struct Span;
+
+macro_rules! info_event {
+  ($($arg:tt)*) => {
+    #[cfg(feature = "tracing")]
+    tracing::info!($($arg)*);
+  };
+}
+
+macro_rules! warn_event {
+  ($($arg:tt)*) => {
+    #[cfg(feature = "tracing")]
+    tracing::warn!($($arg)*);
+  };
+}
+
+macro_rules! error_event {
+  ($($arg:tt)*) => {
+    #[cfg(feature = "tracing")]
+    tracing::error!($($arg)*);
+  };
+}
+
+pub(crate) use {error_event, info_event, warn_event};
+
+pub(crate) fn connection_span(peer: &PeerInfo) -> Span {
+  #[cfg(feature = "tracing")]
+  return tracing::info_span!(
+    "webcontr.connection",
+    peer = ?peer,
+    protocol_version = tracing::field::Empty,
+    features = tracing::field::Empty,
+  );
+  #[cfg(not(feature = "tracing"))]
+  {
+    let _ = peer;
+    Span
+  }
+}
+
+/// Adds what was negotiated to the current [connection_span].
+pub(crate) fn record_protocol(hello: &ServerHello) {
+  #[cfg(feature = "tracing")]
+  {
+    let span = tracing::Span::current();
+    span.record("protocol_version", hello.version);
+    span.record("features", tracing::field::debug(hello.features));
+  }
+  #[cfg(not(feature = "tracing"))]
+  let _ = hello;
+}
+
+pub(crate) fn server_rpc_span(
+  service: &str,
+  method: Option<&str>,
+  peer: &PeerInfo,
+  request_size: usize,
+) -> Span {
+  #[cfg(feature = "tracing")]
+  return tracing::info_span!(
+    "webcontr.rpc",
+    otel.kind = "server",
+    service,
+    method,
+    peer = ?peer,
+    request_size,
+    response_size = tracing::field::Empty,
+    status = tracing::field::Empty,
+    duration = tracing::field::Empty,
+  );
+  #[cfg(not(feature = "tracing"))]
+  {
+    let _ = (service, method, peer, request_size);
+    Span
+  }
+}
+
+pub(crate) fn client_rpc_span(
+  service: &str,
+  method: &str,
+  request_size: usize,
+) -> Span {
+  #[cfg(feature = "tracing")]
+  return tracing::info_span!(
+    "webcontr.rpc",
+    otel.kind = "client",
+    service,
+    method,
+    request_size,
+    response_size = tracing::field::Empty,
+    status = tracing::field::Empty,
+    duration = tracing::field::Empty,
+  );
+  #[cfg(not(feature = "tracing"))]
+  {
+    let _ = (service, method, request_size);
+    Span
+  }
+}
+
+/// Fills in the outcome of an rpc span.
+pub(crate) fn record_response(
+  span: &Span,
+  response: &ResponseFrame,
+  elapsed: Duration,
+) {
+  #[cfg(feature = "tracing")]
+  {
+    match response {
+      ResponseFrame::Payload(payload) => {
+        span.record("response_size", payload.len());
+        span.record("status", "ok");
+      }
+      ResponseFrame::Error(err) => {
+        span.record("status", err.name());
+      }
+    }
+    span.record("duration", tracing::field::debug(elapsed));
+  }
+  #[cfg(not(feature = "tracing"))]
+  let _ = (span, response, elapsed);
+}
+
+/// Writes the W3C trace context of `span` to `metadata`, so the server can
+/// continue the trace. Only does something with the `opentelemetry` feature.
+pub(crate) fn inject(span: &Span, metadata: &mut Metadata) {
+  #[cfg(feature = "opentelemetry")]
+  otel::inject(span, metadata);
+  #[cfg(not(feature = "opentelemetry"))]
+  let _ = (span, metadata);
+}
+
+/// Makes `span` a child of the trace context the client sent in `metadata`.
+pub(crate) fn extract(span: &Span, metadata: &Metadata) {
+  #[cfg(feature = "opentelemetry")]
+  otel::extract(span, metadata);
+  #[cfg(not(feature = "opentelemetry"))]
+  let _ = (span, metadata);
+}
+
+#[cfg(feature = "opentelemetry")]
+mod otel {
+  use opentelemetry::trace::{
+    SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId,
+  };
+  use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+  use super::Span;
+  use crate::metadata::Metadata;
+
+  const TRACEPARENT: &str = "traceparent";
+  const TRACESTATE: &str = "tracestate";
+
+  pub(super) fn inject(span: &Span, metadata: &mut Metadata) {
+    let context = span.context();
+    let span_context = context.span().span_context().clone();
+    if !span_context.is_valid() {
+      return;
+    }
+
+    metadata.insert(
+      TRACEPARENT,
+      format!(
+        "00-{}-{}-{:02x}",
+        span_context.trace_id(),
+        span_context.span_id(),
+        span_context.trace_flags().to_u8()
+      ),
+    );
+
+    let trace_state = span_context.trace_state().header();
+    if !trace_state.is_empty() {
+      metadata.insert(TRACESTATE, trace_state);
+    }
+  }
+
+  pub(super) fn extract(span: &Span, metadata: &Metadata) {
+    let Some(span_context) = metadata
+      .get(TRACEPARENT)
+      .and_then(|header| parse_traceparent(header, metadata.get(TRACESTATE)))
+    else {
+      return;
+    };
+
+    let context =
+      opentelemetry::Context::new().with_remote_span_context(span_context);
+    span.set_parent(context);
+  }
+
+  fn parse_traceparent(
+    header: &str,
+    trace_state: Option<&str>,
+  ) -> Option<SpanContext> {
+    let mut parts = header.trim().split('-');
+    let version = parts.next()?;
+    let trace_id = parts.next()?;
+    let span_id = parts.next()?;
+    let flags = parts.next()?;
+
+    // Later versions may append fields, but never change these four.
+    if version.len() != 2 || version == "ff" {
+      return None;
+    }
+    if version == "00" && parts.next().is_some() {
+      return None;
+    }
+    if trace_id.len() != 32 || span_id.len() != 16 || flags.len() != 2 {
+      return None;
+    }
+
+    let trace_id = TraceId::from_hex(trace_id).ok()?;
+    let span_id = SpanId::from_hex(span_id).ok()?;
+    let flags = u8::from_str_radix(flags, 16).ok()?;
+    let trace_state =
+      trace_state.and_then(|state| state.parse().ok()).unwrap_or_default();
+
+    let span_context = SpanContext::new(
+      trace_id,
+      span_id,
+      TraceFlags::new(flags) & TraceFlags::SAMPLED,
+      true,
+      trace_state,
+    );
+    span_context.is_valid().then_some(span_context)
+  }
+
+  #[cfg(test)]
+  mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_traceparent() {
+      let span_context = parse_traceparent(
+        "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+        Some("vendor=value"),
+      )
+      .unwrap();
+
+      assert_eq!(
+        span_context.trace_id().to_string(),
+        "4bf92f3577b34da6a3ce929d0e0e4736"
+      );
+      assert_eq!(span_context.span_id().to_string(), "00f067aa0ba902b7");
+      assert!(span_context.is_sampled());
+      assert!(span_context.is_remote());
+      assert_eq!(span_context.trace_state().get("vendor"), Some("value"));
+    }
+
+    #[test]
+    fn rejects_invalid_traceparent() {
+      for header in [
+        "",
+        "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+        "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+        "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+        "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+        "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra",
+        "00-xyz92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+      ] {
+        assert!(parse_traceparent(header, None).is_none(), "{header}");
+      }
+    }
+  }
+}
+
+#[cfg(feature = "tracing")]
+pub(crate) fn instrument<F: Future>(
+  future: F,
+  span: &Span,
+) -> impl Future<Output = F::Output> {
+  tracing::Instrument::instrument(future, span.clone())
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn instrument<F: Future>(future: F, _: &Span) -> F {
+  future
+}
@@ -2,9 +2,10 @@ use std::task::Poll;
 
 use futures_util::{Sink, Stream};
 use tokio::sync::mpsc::{self, error::SendError};
+use tokio_util::sync::{PollSendError, PollSender};
 
 pub struct UnboundedChannel<Item, SinkItem> {
-  sender: mpsc::UnboundedSender<SinkItem>,
+  sender: Option<mpsc::UnboundedSender<SinkItem>>,
   receiver: mpsc::UnboundedReceiver<Item>,
 }
 
@@ -13,8 +14,8 @@ pub fn unbounded<SinkItem, Item>(
   let (sender1, receiver2) = mpsc::unbounded_channel();
   let (sender2, receiver1) = mpsc::unbounded_channel();
   (
-    UnboundedChannel { sender: sender1, receiver: receiver1 },
-    UnboundedChannel { sender: sender2, receiver: receiver2 },
+    UnboundedChannel { sender: Some(sender1), receiver: receiver1 },
+    UnboundedChannel { sender: Some(sender2), receiver: receiver2 },
   )
 }
 
@@ -51,8 +52,10 @@ impl<Item, SinkItem> Sink<SinkItem> for UnboundedChannel<Item, SinkItem> {
     self: std::pin::Pin<&mut Self>,
     item: SinkItem,
   ) -> Result<(), Self::Error> {
-    self.sender.send(item)?;
-    Ok(())
+    match &self.sender {
+      Some(sender) => sender.send(item),
+      None => Err(SendError(item)),
+    }
   }
 
   fn poll_flush(
@@ -62,17 +65,134 @@ impl<Item, SinkItem> Sink<SinkItem> for UnboundedChannel<Item, SinkItem> {
     Poll::Ready(Ok(()))
   }
 
+  /// Stops sending, the peer sees the end of the stream once it has received
+  /// everything sent before the close.
   fn poll_close(
     mut self: std::pin::Pin<&mut Self>,
     cx: &mut std::task::Context<'_>,
   ) -> Poll<Result<(), Self::Error>> {
-    match self.as_mut().as_mut().poll_flush(cx) {
+    match self.as_mut().poll_flush(cx) {
       Poll::Pending => return Poll::Pending,
       Poll::Ready(val) => val?,
     };
 
-    self.receiver.close();
+    self.sender = None;
 
     Poll::Ready(Ok(()))
   }
 }
+
+/// Like [UnboundedChannel], but every direction holds at most `capacity`
+/// items. [Sink::poll_ready] stays pending while the peer's buffer is full.
+pub struct BoundedChannel<Item, SinkItem> {
+  sender: PollSender<SinkItem>,
+  receiver: mpsc::Receiver<Item>,
+}
+
+/// # Panics
+///
+/// Panics if `capacity` is zero.
+pub fn bounded<SinkItem: Send, Item: Send>(
+  capacity: usize,
+) -> (BoundedChannel<SinkItem, Item>, BoundedChannel<Item, SinkItem>) {
+  let (sender1, receiver2) = mpsc::channel(capacity);
+  let (sender2, receiver1) = mpsc::channel(capacity);
+  (
+    BoundedChannel { sender: PollSender::new(sender1), receiver: receiver1 },
+    BoundedChannel { sender: PollSender::new(sender2), receiver: receiver2 },
+  )
+}
+
+impl<Item, SinkItem> Stream for BoundedChannel<Item, SinkItem> {
+  type Item = Result<Item, PollSendError<SinkItem>>;
+
+  fn poll_next(
+    mut self: std::pin::Pin<&mut Self>,
+    cx: &mut std::task::Context<'_>,
+  ) -> Poll<Option<Self::Item>> {
+    match self.receiver.poll_recv(cx) {
+      Poll::Ready(Some(data)) => Poll::Ready(Some(Ok(data))),
+      Poll::Ready(None) => Poll::Ready(None),
+      Poll::Pending => Poll::Pending,
+    }
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    (self.receiver.len(), None)
+  }
+}
+
+impl<Item, SinkItem: Send> Sink<SinkItem> for BoundedChannel<Item, SinkItem> {
+  type Error = PollSendError<SinkItem>;
+
+  fn poll_ready(
+    mut self: std::pin::Pin<&mut Self>,
+    cx: &mut std::task::Context<'_>,
+  ) -> Poll<Result<(), Self::Error>> {
+    self.sender.poll_reserve(cx)
+  }
+
+  fn start_send(
+    mut self: std::pin::Pin<&mut Self>,
+    item: SinkItem,
+  ) -> Result<(), Self::Error> {
+    self.sender.send_item(item)
+  }
+
+  fn poll_flush(
+    self: std::pin::Pin<&mut Self>,
+    _: &mut std::task::Context<'_>,
+  ) -> Poll<Result<(), Self::Error>> {
+    Poll::Ready(Ok(()))
+  }
+
+  /// Stops sending, the peer sees the end of the stream once it has received
+  /// everything sent before the close.
+  fn poll_close(
+    mut self: std::pin::Pin<&mut Self>,
+    _: &mut std::task::Context<'_>,
+  ) -> Poll<Result<(), Self::Error>> {
+    self.sender.close();
+
+    Poll::Ready(Ok(()))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::task::{Context, Poll};
+
+  use futures_util::{task::noop_waker_ref, SinkExt, StreamExt};
+
+  use super::{bounded, unbounded};
+
+  #[tokio::test]
+  async fn bounded_applies_backpressure() {
+    let (mut producer, mut consumer) = bounded::<u8, u8>(1);
+    let mut cx = Context::from_waker(noop_waker_ref());
+
+    producer.send(1).await.unwrap();
+    assert!(producer.poll_ready_unpin(&mut cx).is_pending());
+
+    assert_eq!(consumer.next().await.unwrap().unwrap(), 1);
+    assert!(matches!(producer.poll_ready_unpin(&mut cx), Poll::Ready(Ok(()))));
+  }
+
+  #[tokio::test]
+  async fn close_ends_peer_stream() {
+    let (mut producer, mut consumer) = bounded::<u8, u8>(4);
+    producer.send(1).await.unwrap();
+    producer.close().await.unwrap();
+
+    assert_eq!(consumer.next().await.unwrap().unwrap(), 1);
+    assert!(consumer.next().await.is_none());
+    assert!(producer.send(2).await.is_err());
+
+    let (mut producer, mut consumer) = unbounded::<u8, u8>();
+    producer.send(1).await.unwrap();
+    producer.close().await.unwrap();
+
+    assert_eq!(consumer.next().await.unwrap().unwrap(), 1);
+    assert!(consumer.next().await.is_none());
+  }
+}
@@ -0,0 +1,594 @@
+use std::{
+  collections::{HashMap, VecDeque},
+  io,
+  sync::{Arc, OnceLock},
+  time::Duration,
+};
+
+use async_trait::async_trait;
+use bytes::BytesMut;
+use tokio::{
+  io::{AsyncReadExt, AsyncWriteExt},
+  sync::{
+    mpsc::{self, UnboundedReceiver, UnboundedSender},
+    oneshot, watch,
+  },
+  time::{sleep_until, timeout, Instant},
+};
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::{
+  accept::Io,
+  compression::{Compression, Payloads},
+  trace,
+  transport::{
+    frame::{Frame, FrameCodec, RequestFrame, ResponseFrame},
+    keepalive::{Due, Keepalive, Pings},
+    preamble::{
+      ClientHello, PreambleCodec, ServerHelloCodec, MAX_VERSION,
+      MULTIPLEXED_VERSION,
+    },
+    ClientTransport,
+  },
+  ClientError,
+};
+
+/// Opens connections to a server, e.g.
+/// [crate::transport::tcp::client::TcpTransport].
+///
+/// Implement this to use a [Connection] over a transport webcontr does not
+/// know about.
+#[async_trait]
+pub trait Connect: Send + Sync {
+  async fn connect(&self) -> io::Result<Box<dyn Io>>;
+}
+
+/// How long a [Connection] waits before trying to connect again, doubling
+/// with every failed attempt.
+#[derive(Debug, Clone, Copy)]
+pub struct Backoff {
+  pub initial: Duration,
+  pub max: Duration,
+}
+
+impl Backoff {
+  pub fn new(initial: Duration, max: Duration) -> Self {
+    Self { initial, max }
+  }
+
+  fn delay(&self, failures: u32) -> Duration {
+    let factor = 2u32.saturating_pow(failures.saturating_sub(1));
+    self.initial.saturating_mul(factor).min(self.max)
+  }
+}
+
+impl Default for Backoff {
+  fn default() -> Self {
+    Self::new(Duration::from_millis(100), Duration::from_secs(30))
+  }
+}
+
+/// What happens to calls while a [Connection] can't reach the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OnDisconnect {
+  /// Wait until the server can be reached again, or the call times out.
+  #[default]
+  Queue,
+  /// Fail right away with the error connecting failed with.
+  Fail,
+}
+
+/// Where a [Connection] is at, see [Connection::state].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+  /// Nothing was called yet.
+  Idle,
+  Connecting,
+  Connected,
+  /// The last `failures` connections failed, or were lost before the
+  /// server answered anything, the next one is made after `retry_in`.
+  Reconnecting {
+    failures: u32,
+    retry_in: Duration,
+  },
+}
+
+type Reply = oneshot::Sender<Result<ResponseFrame, ClientError>>;
+type Call = (RequestFrame, Reply);
+
+/// Keeps a connection of protocol version 2 open and sends every call over
+/// it, without waiting for earlier ones to be answered. Clones share the
+/// connection.
+///
+/// If the server closes the connection or stops answering pings, see
+/// [Connection::with_keepalive], the calls waiting on it fail and a new
+/// connection is made in the background, with [Backoff] while the server
+/// can't be reached. A server that sends a [Frame::GoAway] gets to answer the
+/// calls it took, the rest and any new ones go to a new connection.
+#[derive(Clone)]
+pub struct Connection {
+  settings: Settings,
+  calls: Arc<OnceLock<UnboundedSender<Call>>>,
+  state: Arc<watch::Sender<ConnectionState>>,
+}
+
+#[derive(Clone)]
+struct Settings {
+  connector: Arc<dyn Connect>,
+  keepalive: Option<Keepalive>,
+  compression: Compression,
+  backoff: Backoff,
+  on_disconnect: OnDisconnect,
+  connect_timeout: Duration,
+  write_timeout: Duration,
+}
+
+impl Connection {
+  /// Connects with `connector` once the first call is made.
+  pub fn new(connector: impl Connect + 'static) -> Self {
+    Self {
+      settings: Settings {
+        connector: Arc::new(connector),
+        keepalive: None,
+        compression: Compression::default(),
+        backoff: Backoff::default(),
+        on_disconnect: OnDisconnect::default(),
+        connect_timeout: Duration::from_secs(10),
+        write_timeout: Duration::from_secs(30),
+      },
+      calls: Arc::default(),
+      state: Arc::new(watch::Sender::new(ConnectionState::Idle)),
+    }
+  }
+
+  /// Pings the server while the connection is open. Off by default.
+  pub fn with_keepalive(mut self, keepalive: Keepalive) -> Self {
+    self.settings.keepalive = Some(keepalive);
+    self
+  }
+
+  /// Offers `compression` to the server, see [crate::compression].
+  pub fn with_compression(mut self, compression: Compression) -> Self {
+    self.settings.compression = compression;
+    self
+  }
+
+  /// How long to wait between attempts to connect, from 100 milliseconds up
+  /// to 30 seconds by default.
+  pub fn with_backoff(mut self, backoff: Backoff) -> Self {
+    self.settings.backoff = backoff;
+    self
+  }
+
+  /// What happens to calls while the server can't be reached,
+  /// [OnDisconnect::Queue] by default.
+  pub fn with_on_disconnect(mut self, on_disconnect: OnDisconnect) -> Self {
+    self.settings.on_disconnect = on_disconnect;
+    self
+  }
+
+  /// How long connecting and agreeing on the protocol may take, 10 seconds
+  /// by default. A server that doesn't answer in time, e.g. one from before
+  /// the [crate::transport::preamble], counts as one that can't be reached.
+  pub fn with_connect_timeout(mut self, dur: Duration) -> Self {
+    self.settings.connect_timeout = dur;
+    self
+  }
+
+  /// How long sending a frame may take, 30 seconds by default. A server that
+  /// stops reading for longer is treated like one that closed the
+  /// connection, the calls waiting on it fail and a new one is made.
+  pub fn with_write_timeout(mut self, dur: Duration) -> Self {
+    self.settings.write_timeout = dur;
+    self
+  }
+
+  /// Changes whenever the connection is made or lost.
+  pub fn state(&self) -> watch::Receiver<ConnectionState> {
+    self.state.subscribe()
+  }
+
+  /// Where to send calls to. The task keeping the connection open is
+  /// started with the first call, and stops once every clone is dropped.
+  fn calls(&self) -> &UnboundedSender<Call> {
+    self.calls.get_or_init(|| {
+      let (sender, receiver) = mpsc::unbounded_channel();
+      let settings = self.settings.clone();
+      tokio::spawn(supervise(settings, receiver, self.state.clone()));
+      sender
+    })
+  }
+}
+
+#[async_trait]
+impl ClientTransport for Connection {
+  async fn call(
+    &mut self,
+    request: RequestFrame,
+  ) -> Result<ResponseFrame, ClientError> {
+    let (reply, response) = oneshot::channel();
+    self.calls().send((request, reply)).map_err(|_| connection_lost())?;
+    response.await.map_err(|_| connection_lost())?
+  }
+}
+
+fn connection_lost() -> ClientError {
+  ClientError::IoError(io::Error::new(
+    io::ErrorKind::ConnectionAborted,
+    "connection to server lost",
+  ))
+}
+
+fn fail(reply: Reply, err: &ClientError) {
+  let _ = reply.send(Err(duplicate(err)));
+}
+
+/// `err` again, for each call it fails.
+fn duplicate(err: &ClientError) -> ClientError {
+  let copy = |err: &io::Error| io::Error::new(err.kind(), err.to_string());
+  match err {
+    ClientError::ConnectError(err) => ClientError::ConnectError(copy(err)),
+    ClientError::TlsError(err) => ClientError::TlsError(copy(err)),
+    ClientError::IoError(err) => ClientError::IoError(copy(err)),
+    ClientError::ProtocolError(message) => {
+      ClientError::ProtocolError(message.clone())
+    }
+    err => ClientError::IoError(io::Error::other(err.to_string())),
+  }
+}
+
+/// Connects, and connects again whenever the connection is lost, until
+/// every handle is dropped.
+async fn supervise(
+  settings: Settings,
+  mut calls: UnboundedReceiver<Call>,
+  state: Arc<watch::Sender<ConnectionState>>,
+) {
+  // Calls waiting for a connection, and ones a server going away didn't
+  // take.
+  let mut queued = VecDeque::new();
+  let mut failures = 0;
+
+  loop {
+    state.send_replace(ConnectionState::Connecting);
+    let err = match Driver::connect(&settings).await {
+      Ok(mut driver) => {
+        state.send_replace(ConnectionState::Connected);
+        let ended = driver.run(&mut calls, &mut queued).await;
+        // Only a server that answered counts as back, not one that takes
+        // the handshake and then hangs up.
+        if driver.answered {
+          failures = 0;
+        }
+        match ended {
+          Ended::Dropped => return,
+          Ended::GoingAway => {
+            tokio::spawn(driver.drain());
+            // Right away, another server may well take the calls.
+            continue;
+          }
+          Ended::Failed(err) => err,
+        }
+      }
+      Err(err) => {
+        trace::warn_event!(error = %err, "failed to connect to server");
+        err
+      }
+    };
+
+    failures += 1;
+    if settings.on_disconnect == OnDisconnect::Fail {
+      for (_, reply) in queued.drain(..) {
+        fail(reply, &err);
+      }
+    }
+
+    let retry_in = settings.backoff.delay(failures);
+    state.send_replace(ConnectionState::Reconnecting { failures, retry_in });
+    let retry_at = Instant::now() + retry_in;
+    let failing = settings.on_disconnect == OnDisconnect::Fail;
+    loop {
+      tokio::select! {
+          _ = sleep_until(retry_at) => break,
+          call = calls.recv() => match call {
+            Some((_, reply)) if failing => fail(reply, &err),
+            Some(call) => queued.push_back(call),
+            None => return,
+          },
+      }
+    }
+  }
+}
+
+/// Why [Driver::run] returned.
+enum Ended {
+  /// Every handle was dropped.
+  Dropped,
+  /// The server sent a [Frame::GoAway], see [Driver::drain].
+  GoingAway,
+  /// The connection broke, calls waiting on it failed.
+  Failed(ClientError),
+}
+
+/// Owns the socket of a [Connection], matching responses to calls by ID.
+struct Driver {
+  io: Box<dyn Io>,
+  read_buf: BytesMut,
+  write_buf: BytesMut,
+  payloads: Payloads,
+  pings: Option<Pings>,
+  /// What each call was before compression, in case it needs to be sent
+  /// again.
+  pending: HashMap<u32, Call>,
+  next_id: u32,
+  going_away: bool,
+  /// Whether the server answered a call or ping on this connection.
+  answered: bool,
+  write_timeout: Duration,
+}
+
+impl Driver {
+  async fn connect(settings: &Settings) -> Result<Self, ClientError> {
+    match timeout(settings.connect_timeout, Self::handshake(settings)).await {
+      Ok(driver) => driver,
+      Err(_) => Err(ClientError::ConnectError(io::Error::new(
+        io::ErrorKind::TimedOut,
+        "server didn't answer in time",
+      ))),
+    }
+  }
+
+  async fn handshake(settings: &Settings) -> Result<Self, ClientError> {
+    let mut io =
+      settings.connector.connect().await.map_err(ClientError::connecting)?;
+    let features = settings.compression.features();
+    let hello = ClientHello {
+      min_version: MULTIPLEXED_VERSION,
+      max_version: MAX_VERSION,
+      features,
+    };
+
+    let mut buf = BytesMut::new();
+    PreambleCodec.encode(hello, &mut buf)?;
+    io.write_all(&buf).await?;
+    io.flush().await?;
+    buf.clear();
+
+    let hello = loop {
+      let hello =
+        ServerHelloCodec.decode(&mut buf).map_err(ClientError::protocol)?;
+      if let Some(hello) = hello {
+        break hello;
+      }
+      if io.read_buf(&mut buf).await? == 0 {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+      }
+    };
+    if hello.is_rejected() {
+      return Err(ClientError::ProtocolError(format!(
+        "server doesn't support protocol versions \
+         {MULTIPLEXED_VERSION}..={MAX_VERSION}"
+      )));
+    }
+
+    Ok(Self {
+      io,
+      // Whatever the server sent after its hello.
+      read_buf: buf,
+      write_buf: BytesMut::new(),
+      payloads: settings.compression.negotiated(hello.features),
+      pings: settings.keepalive.map(Pings::new),
+      pending: HashMap::new(),
+      next_id: 1,
+      going_away: false,
+      answered: false,
+      write_timeout: settings.write_timeout,
+    })
+  }
+
+  /// Sends `queued` and then new calls, until the connection ends.
+  async fn run(
+    &mut self,
+    calls: &mut UnboundedReceiver<Call>,
+    queued: &mut VecDeque<Call>,
+  ) -> Ended {
+    match self.serve(Some(calls), queued).await {
+      Ok(()) if self.going_away => Ended::GoingAway,
+      Ok(()) => Ended::Dropped,
+      Err(err) => {
+        trace::warn_event!(error = %err, "connection to server lost");
+        self.fail_pending(&err);
+        Ended::Failed(err)
+      }
+    }
+  }
+
+  /// Waits for the answers to the calls the server took before going away.
+  async fn drain(mut self) {
+    if let Err(err) = self.serve(None, &mut VecDeque::new()).await {
+      self.fail_pending(&err);
+    }
+  }
+
+  fn fail_pending(&mut self, err: &ClientError) {
+    for (_, (_, reply)) in self.pending.drain() {
+      fail(reply, err);
+    }
+  }
+
+  async fn serve(
+    &mut self,
+    mut calls: Option<&mut UnboundedReceiver<Call>>,
+    queued: &mut VecDeque<Call>,
+  ) -> Result<(), ClientError> {
+    loop {
+      if !self.going_away {
+        while let Some((request, reply)) = queued.pop_front() {
+          self.send(request, reply).await?;
+        }
+      }
+      while let Some(frame) =
+        FrameCodec.decode(&mut self.read_buf).map_err(ClientError::protocol)?
+      {
+        self.received(frame, queued).await?;
+      }
+      // New calls go elsewhere, the ones taken are left to drain.
+      if self.going_away && (calls.is_some() || self.pending.is_empty()) {
+        return Ok(());
+      }
+
+      let receiving = calls.is_some();
+      let ping_at = self.pings.as_ref().map(Pings::deadline);
+      tokio::select! {
+          call = async { calls.as_deref_mut()?.recv().await }, if receiving => {
+            match call {
+              Some((request, reply)) => self.send(request, reply).await?,
+              None => return Ok(()),
+            }
+          },
+          read = self.io.read_buf(&mut self.read_buf) => {
+            if read? == 0 {
+              return Err(ClientError::IoError(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "server closed the connection",
+              )));
+            }
+          },
+          _ = sleep_until(ping_at.unwrap_or_else(Instant::now)),
+            if ping_at.is_some() =>
+          {
+            let due = self.pings.as_mut().map(Pings::due);
+            if let Some(Due::Ping(value)) = due {
+              self.write(Frame::Ping(value)).await?;
+            } else {
+              return Err(ClientError::IoError(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "server didn't answer ping in time",
+              )));
+            }
+          },
+      }
+    }
+  }
+
+  async fn send(
+    &mut self,
+    request: RequestFrame,
+    reply: Reply,
+  ) -> io::Result<()> {
+    let arguments = match self.payloads.compress(request.arguments.clone()) {
+      Ok(arguments) => arguments,
+      Err(err) => {
+        let _ = reply.send(Err(ClientError::IoError(err)));
+        return Ok(());
+      }
+    };
+
+    let id = self.next_id;
+    self.next_id = self.next_id.checked_add(1).unwrap_or(1);
+    let frame =
+      Frame::Request(id, RequestFrame { arguments, ..request.clone() });
+    // Only this call fails if it can't be framed, e.g. it is too large.
+    if let Err(err) = FrameCodec.encode(frame, &mut self.write_buf) {
+      let _ = reply.send(Err(ClientError::IoError(err)));
+      return Ok(());
+    }
+    self.pending.insert(id, (request, reply));
+    self.flush().await
+  }
+
+  /// Stops taking calls, and queues the ones the server didn't take to be
+  /// sent first on the next connection.
+  fn go_away(&mut self, last: u32, queued: &mut VecDeque<Call>) {
+    trace::warn_event!(last, "server is going away");
+    self.going_away = true;
+
+    let mut not_taken: Vec<u32> =
+      self.pending.keys().copied().filter(|id| *id > last).collect();
+    not_taken.sort_unstable();
+    for id in not_taken.into_iter().rev() {
+      if let Some(call) = self.pending.remove(&id) {
+        queued.push_front(call);
+      }
+    }
+  }
+
+  async fn received(
+    &mut self,
+    frame: Frame,
+    queued: &mut VecDeque<Call>,
+  ) -> Result<(), ClientError> {
+    match frame {
+      Frame::Response(id, response) => {
+        let Some((_, reply)) = self.pending.remove(&id) else {
+          return Err(ClientError::ProtocolError(format!(
+            "response to unknown request {id}"
+          )));
+        };
+        self.answered = true;
+        let response = match response {
+          ResponseFrame::Payload(payload) => self
+            .payloads
+            .decompress(payload)
+            .map(ResponseFrame::Payload)
+            .map_err(ClientError::protocol),
+          response => Ok(response),
+        };
+        let _ = reply.send(response);
+        Ok(())
+      }
+      Frame::Ping(value) => Ok(self.write(Frame::Pong(value)).await?),
+      Frame::Pong(value) => {
+        self.answered = true;
+        if let Some(pings) = &mut self.pings {
+          pings.pong(value);
+        }
+        Ok(())
+      }
+      Frame::GoAway(last) => {
+        self.go_away(last, queued);
+        Ok(())
+      }
+      Frame::Request(..) => {
+        Err(ClientError::ProtocolError("server sent a request".into()))
+      }
+    }
+  }
+
+  async fn write(&mut self, frame: Frame) -> io::Result<()> {
+    FrameCodec.encode(frame, &mut self.write_buf)?;
+    self.flush().await
+  }
+
+  /// Nothing is read meanwhile, so this gives up on a server that doesn't
+  /// read either.
+  async fn flush(&mut self) -> io::Result<()> {
+    let written = async {
+      self.io.write_all(&self.write_buf).await?;
+      self.write_buf.clear();
+      self.io.flush().await
+    };
+    match timeout(self.write_timeout, written).await {
+      Ok(written) => written,
+      Err(_) => Err(io::Error::new(
+        io::ErrorKind::TimedOut,
+        "server didn't take request in time",
+      )),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn backoff_doubles_up_to_max() {
+    let backoff =
+      Backoff::new(Duration::from_millis(100), Duration::from_millis(500));
+    let delays: Vec<_> =
+      (1..=5).map(|failures| backoff.delay(failures).as_millis()).collect();
+    assert_eq!(delays, [100, 200, 400, 500, 500]);
+    assert_eq!(backoff.delay(u32::MAX), backoff.max);
+  }
+}
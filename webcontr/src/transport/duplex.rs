@@ -0,0 +1,95 @@
+use std::io;
+
+use async_trait::async_trait;
+use tokio::{
+  io::DuplexStream,
+  sync::mpsc::{self, UnboundedReceiver, UnboundedSender},
+};
+
+use crate::{
+  accept::{Accept, Io},
+  compression::Compression,
+  context::PeerInfo,
+  transport::{
+    connection::Connect,
+    frame::{RequestFrame, ResponseFrame},
+    tcp::client::call_io,
+    ClientTransport,
+  },
+  ClientError,
+};
+
+/// Creates an in-memory listener and a handle that can connect to it.
+///
+/// Unlike [crate::transport::channel], every connection goes through the
+/// same byte-level codecs as a socket would. `max_buf_size` is passed to
+/// [tokio::io::duplex] for every connection.
+pub fn listener(max_buf_size: usize) -> (DuplexConnector, DuplexListener) {
+  let (sender, receiver) = mpsc::unbounded_channel();
+  let connector = DuplexConnector {
+    sender,
+    max_buf_size,
+    compression: Compression::default(),
+  };
+  (connector, DuplexListener { receiver })
+}
+
+#[derive(Clone)]
+pub struct DuplexConnector {
+  sender: UnboundedSender<DuplexStream>,
+  max_buf_size: usize,
+  compression: Compression,
+}
+
+impl DuplexConnector {
+  /// Offers `compression` to the server, see [crate::compression].
+  pub fn with_compression(mut self, compression: Compression) -> Self {
+    self.compression = compression;
+    self
+  }
+
+  pub fn connect(&self) -> io::Result<DuplexStream> {
+    let (client, server) = tokio::io::duplex(self.max_buf_size);
+    self.sender.send(server).map_err(|_| {
+      io::Error::new(io::ErrorKind::ConnectionRefused, "listener is gone")
+    })?;
+    Ok(client)
+  }
+}
+
+#[async_trait]
+impl Connect for DuplexConnector {
+  async fn connect(&self) -> io::Result<Box<dyn Io>> {
+    Ok(Box::new(DuplexConnector::connect(self)?))
+  }
+}
+
+#[async_trait]
+impl ClientTransport for DuplexConnector {
+  async fn call(
+    &mut self,
+    request: RequestFrame,
+  ) -> Result<ResponseFrame, ClientError> {
+    let stream =
+      DuplexConnector::connect(self).map_err(ClientError::connecting)?;
+    call_io(stream, request, &self.compression).await
+  }
+}
+
+pub struct DuplexListener {
+  receiver: UnboundedReceiver<DuplexStream>,
+}
+
+#[async_trait]
+impl Accept for DuplexListener {
+  type Io = DuplexStream;
+
+  /// Once every [DuplexConnector] is dropped this never resolves, same as a
+  /// socket nobody connects to anymore.
+  async fn accept(&mut self) -> io::Result<(Self::Io, PeerInfo)> {
+    match self.receiver.recv().await {
+      Some(stream) => Ok((stream, PeerInfo::Local)),
+      None => std::future::pending().await,
+    }
+  }
+}
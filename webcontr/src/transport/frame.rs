@@ -1,12 +1,20 @@
 #![allow(clippy::len_zero)]
 
-use std::io::{self, ErrorKind};
+use std::{
+  io::{self, ErrorKind},
+  time::Duration,
+};
 
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 use tokio_util::codec::{Decoder, Encoder};
 
 use thiserror::Error;
 
+use crate::{
+  metadata::Metadata,
+  status::{Code, Status},
+};
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum ResponseFrame {
   Payload(Bytes),
@@ -20,6 +28,47 @@ pub enum ResponseErrorKind {
   InvalidRequest, // 2
   #[error("timeout")]
   Timeout, // 3
+  /// The handler failed unexpectedly, e.g. it panicked. Carries a message if
+  /// the server chose to share one.
+  #[error("internal error: {}", .0.as_deref().unwrap_or("no details"))]
+  Internal(Option<String>), // 4
+  /// The server is at its concurrency limits and didn't handle the request.
+  #[error("server overloaded")]
+  Overloaded, // 5
+  /// The client made too many requests, see [crate::rate_limit].
+  #[error("rate limited, retry after {retry_after:?}")]
+  RateLimited { retry_after: Duration }, // 6
+  /// Any other error, see [crate::status].
+  #[error("{0}")]
+  Status(Status), // 7
+}
+
+impl ResponseErrorKind {
+  /// A short, stable identifier for the kind of error, e.g. to label logs.
+  pub fn name(&self) -> &'static str {
+    match self {
+      ResponseErrorKind::MethodNotFound => "method_not_found",
+      ResponseErrorKind::InvalidRequest => "invalid_request",
+      ResponseErrorKind::Timeout => "timeout",
+      ResponseErrorKind::Internal(_) => "internal",
+      ResponseErrorKind::Overloaded => "overloaded",
+      ResponseErrorKind::RateLimited { .. } => "rate_limited",
+      ResponseErrorKind::Status(status) => status.code().name(),
+    }
+  }
+
+  /// The closest [Code], so every error can be handled by its code alone.
+  pub fn code(&self) -> Code {
+    match self {
+      ResponseErrorKind::MethodNotFound => Code::Unimplemented,
+      ResponseErrorKind::InvalidRequest => Code::InvalidArgument,
+      ResponseErrorKind::Timeout => Code::DeadlineExceeded,
+      ResponseErrorKind::Internal(_) => Code::Internal,
+      ResponseErrorKind::Overloaded => Code::Unavailable,
+      ResponseErrorKind::RateLimited { .. } => Code::ResourceExhausted,
+      ResponseErrorKind::Status(status) => status.code(),
+    }
+  }
 }
 
 impl ResponseFrame {
@@ -47,27 +96,113 @@ impl Decoder for ResponseFrameCodec {
     match buf.get_u8() {
       // Scenario 0: Normal request with a payload.
       0 => {
-        if src.len() < 2 {
-          return Ok(None); // Not enough data for command length
+        if buf.len() < 2 {
+          return Ok(None); // Not enough data for payload length
         }
 
         let response_len = buf.get_u16() as usize;
 
-        if src.len() < response_len {
+        if buf.len() < response_len {
           return Ok(None);
         }
 
         let response_bytes = buf.split_to(response_len);
-        src.advance(response_len);
+        src.advance(3 + response_len);
 
         Ok(Some(ResponseFrame::with_payload(response_bytes.freeze())))
       }
       // Scenario 1: If client send invalid rpc method.
-      1 => Ok(Some(ResponseFrame::Error(ResponseErrorKind::MethodNotFound))),
+      1 => {
+        src.advance(1);
+        Ok(Some(ResponseFrame::Error(ResponseErrorKind::MethodNotFound)))
+      }
       // Scenario 2: Totally unreadable/invalid request.
-      2 => Ok(Some(ResponseFrame::Error(ResponseErrorKind::InvalidRequest))),
+      2 => {
+        src.advance(1);
+        Ok(Some(ResponseFrame::Error(ResponseErrorKind::InvalidRequest)))
+      }
       // Scenario 3: Server timeout
-      3 => Ok(Some(ResponseFrame::Error(ResponseErrorKind::Timeout))),
+      3 => {
+        src.advance(1);
+        Ok(Some(ResponseFrame::Error(ResponseErrorKind::Timeout)))
+      }
+      // Scenario 4: Handler failed, followed by an optional message.
+      4 => {
+        if buf.len() < 2 {
+          return Ok(None); // Not enough data for message length
+        }
+
+        let message_len = buf.get_u16() as usize;
+
+        if buf.len() < message_len {
+          return Ok(None);
+        }
+
+        let message = match message_len {
+          0 => None,
+          _ => Some(
+            String::from_utf8(buf.split_to(message_len).to_vec()).map_err(
+              |_| {
+                io::Error::new(ErrorKind::InvalidData, "Invalid UTF-8 message")
+              },
+            )?,
+          ),
+        };
+        src.advance(3 + message_len);
+
+        Ok(Some(ResponseFrame::Error(ResponseErrorKind::Internal(message))))
+      }
+      // Scenario 5: Server at its limits, retrying later may succeed.
+      5 => {
+        src.advance(1);
+        Ok(Some(ResponseFrame::Error(ResponseErrorKind::Overloaded)))
+      }
+      // Scenario 6: Client rate limited, followed by milliseconds to wait.
+      6 => {
+        if buf.len() < 4 {
+          return Ok(None); // Not enough data for retry delay
+        }
+
+        let retry_after = Duration::from_millis(buf.get_u32() as u64);
+        src.advance(5);
+
+        Ok(Some(ResponseFrame::Error(ResponseErrorKind::RateLimited {
+          retry_after,
+        })))
+      }
+      // Scenario 7: Status code, followed by a message and details.
+      7 => {
+        if buf.len() < 3 {
+          return Ok(None); // Not enough data for code and message length
+        }
+
+        let code = Code::from_u8(buf.get_u8());
+        let message_len = buf.get_u16() as usize;
+
+        if buf.len() < message_len + 2 {
+          return Ok(None); // Not enough data for message and details length
+        }
+
+        let message = buf.split_to(message_len);
+        let details_len = buf.get_u16() as usize;
+
+        if buf.len() < details_len {
+          return Ok(None);
+        }
+
+        let mut status =
+          Status::new(code).with_details(buf.split_to(details_len).freeze());
+        if message_len > 0 {
+          status = status.with_message(
+            String::from_utf8(message.to_vec()).map_err(|_| {
+              io::Error::new(ErrorKind::InvalidData, "Invalid UTF-8 message")
+            })?,
+          );
+        }
+        src.advance(6 + message_len + details_len);
+
+        Ok(Some(ResponseFrame::Error(ResponseErrorKind::Status(status))))
+      }
       _ => Err(io::Error::new(ErrorKind::InvalidData, "Invalid first byte")),
     }
   }
@@ -86,10 +221,41 @@ impl Encoder<ResponseFrame> for ResponseFrameCodec {
         ResponseErrorKind::MethodNotFound => dst.put_u8(1),
         ResponseErrorKind::InvalidRequest => dst.put_u8(2),
         ResponseErrorKind::Timeout => dst.put_u8(3),
+        ResponseErrorKind::Internal(message) => {
+          // Only informational, so cut short rather than failing the response.
+          let message = truncate(message.as_deref().unwrap_or_default());
+          dst.put_u8(4);
+          dst.put_u16(message.len() as u16);
+          dst.extend_from_slice(message.as_bytes());
+        }
+        ResponseErrorKind::Overloaded => dst.put_u8(5),
+        ResponseErrorKind::RateLimited { retry_after } => {
+          // Rounded up, retrying early would be rejected again.
+          let millis = retry_after.as_nanos().div_ceil(1_000_000);
+          dst.put_u8(6);
+          dst.put_u32(millis.try_into().unwrap_or(u32::MAX));
+        }
+        ResponseErrorKind::Status(status) => {
+          // The code is what matters, so the rest is cut short or left out
+          // rather than failing the response. Details cut short can't be
+          // read anymore, so they are dropped.
+          let message = truncate(status.message().unwrap_or_default());
+          let details = match status.details().len() {
+            len if len <= u16::MAX as usize => &status.details()[..],
+            _ => &[],
+          };
+          dst.put_u8(7);
+          dst.put_u8(status.code() as u8);
+          dst.put_u16(message.len() as u16);
+          dst.extend_from_slice(message.as_bytes());
+          dst.put_u16(details.len() as u16);
+          dst.extend_from_slice(details);
+        }
       },
       ResponseFrame::Payload(payload) => {
+        let payload_len = payload_len(&payload)?;
         dst.put_u8(0);
-        dst.put_u16(payload.len() as u16);
+        dst.put_u16(payload_len);
         dst.extend_from_slice(&payload);
       }
     };
@@ -98,15 +264,39 @@ impl Encoder<ResponseFrame> for ResponseFrameCodec {
   }
 }
 
+/// The length prefix of a payload. Compression may have added a byte to it,
+/// so it is checked once compressed rather than where the payload was made.
+pub(crate) fn payload_len(payload: &[u8]) -> io::Result<u16> {
+  u16::try_from(payload.len()).map_err(|_| {
+    io::Error::new(ErrorKind::InvalidInput, "payload too long for one frame")
+  })
+}
+
+/// The longest prefix of `message` whose length fits in a `u16`, cut at a
+/// char boundary.
+fn truncate(message: &str) -> &str {
+  let mut len = message.len().min(u16::MAX as usize);
+  while !message.is_char_boundary(len) {
+    len -= 1;
+  }
+  &message[..len]
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct RequestFrame {
   pub command: String,
+  pub metadata: Metadata,
   pub arguments: Bytes,
 }
 
 impl RequestFrame {
   pub fn new(cmd: String, payload: Bytes) -> Self {
-    Self { command: cmd, arguments: payload }
+    Self { command: cmd, metadata: Metadata::new(), arguments: payload }
+  }
+
+  pub fn with_metadata(mut self, metadata: Metadata) -> Self {
+    self.metadata = metadata;
+    self
   }
 
   //pub fn args<'a, R: Deserialize<'a>>(&'a mut self) -> bincode::Result<R> {
@@ -116,6 +306,19 @@ impl RequestFrame {
 
 pub struct RequestFrameCodec;
 
+/// Set in the command length of requests that carry [Metadata] between the
+/// command and the payload. Peers from before metadata never set it, and
+/// requests without metadata are framed like theirs.
+const HAS_METADATA: u16 = 1 << 15;
+
+/// Requests with more [Metadata] entries than this are rejected, so a peer
+/// can't make decoding them expensive.
+pub const MAX_METADATA_ENTRIES: usize = 64;
+
+/// Requests whose [Metadata] keys and values, with their length prefixes,
+/// take more bytes than this are rejected.
+pub const MAX_METADATA_LEN: usize = 16 * 1024;
+
 impl Decoder for RequestFrameCodec {
   type Item = RequestFrame;
   type Error = io::Error;
@@ -124,37 +327,106 @@ impl Decoder for RequestFrameCodec {
     &mut self,
     src: &mut BytesMut,
   ) -> Result<Option<RequestFrame>, Self::Error> {
-    if src.len() < 4 {
-      return Ok(None); // Not enough data for command length
+    let Some(len) = request_len(src)? else {
+      return Ok(None);
+    };
+    parse_request(src.split_to(len)).map(Some)
+  }
+}
+
+/// The length of the request at the start of `src`, once all of it arrived.
+/// Only the length prefixes are read, so this is cheap to repeat while the
+/// request comes in.
+fn request_len(src: &[u8]) -> io::Result<Option<usize>> {
+  let Some(cmd_len) = read_u16(src, 0) else {
+    return Ok(None); // Not enough data for command length
+  };
+  let mut len = 2 + (cmd_len & !HAS_METADATA) as usize;
+
+  if cmd_len & HAS_METADATA != 0 {
+    let Some(metadata_count) = read_u16(src, len) else {
+      return Ok(None); // Not enough data for metadata count
+    };
+    if metadata_count as usize > MAX_METADATA_ENTRIES {
+      return Err(io::Error::new(
+        ErrorKind::InvalidData,
+        "too many metadata entries",
+      ));
+    }
+    len += 2;
+
+    let metadata_start = len;
+    // A key and a value for each entry.
+    for _ in 0..metadata_count * 2 {
+      let Some(str_len) = read_u16(src, len) else {
+        return Ok(None); // Not enough data for metadata key or value
+      };
+      len += 2 + str_len as usize;
+      if len - metadata_start > MAX_METADATA_LEN {
+        return Err(io::Error::new(
+          ErrorKind::InvalidData,
+          "metadata too long",
+        ));
+      }
     }
+  }
 
-    let mut buf = src.clone();
-    let cmd_len = buf.get_u16() as usize;
+  let Some(payload_len) = read_u16(src, len) else {
+    return Ok(None); // Not enough data for payload length
+  };
+  len += 2 + payload_len as usize;
 
-    if buf.len() < cmd_len + 2 {
-      return Ok(None); // Not enough data for full command
-    }
+  Ok((src.len() >= len).then_some(len))
+}
 
-    let cmd_bytes = buf.split_to(cmd_len);
-    let command = String::from_utf8(cmd_bytes.to_vec()).map_err(|_| {
-      io::Error::new(ErrorKind::InvalidData, "Invalid UTF-8 command")
-    })?;
+fn read_u16(src: &[u8], at: usize) -> Option<u16> {
+  let bytes = src.get(at..at + 2)?;
+  Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
 
-    if buf.len() < 2 {
-      return Ok(None); // Not enough data for payload length
+/// Reads a whole request, its length as found by [request_len].
+fn parse_request(mut buf: BytesMut) -> io::Result<RequestFrame> {
+  let cmd_len = buf.get_u16();
+  let has_metadata = cmd_len & HAS_METADATA != 0;
+  let cmd_len = (cmd_len & !HAS_METADATA) as usize;
+
+  let cmd_bytes = buf.split_to(cmd_len);
+  let command = String::from_utf8(cmd_bytes.to_vec()).map_err(|_| {
+    io::Error::new(ErrorKind::InvalidData, "Invalid UTF-8 command")
+  })?;
+
+  let mut metadata = Metadata::new();
+  if has_metadata {
+    let metadata_count = buf.get_u16();
+    for _ in 0..metadata_count {
+      let key = decode_str(&mut buf)?;
+      let value = decode_str(&mut buf)?;
+      metadata.append(key, value);
     }
+  }
 
-    let payload_len = buf.get_u16() as usize;
+  let payload_len = buf.get_u16() as usize;
+  let payload = buf.split_to(payload_len).freeze();
 
-    if buf.len() < payload_len {
-      return Ok(None); // Not enough data for full payload
-    }
+  Ok(RequestFrame { command, metadata, arguments: payload })
+}
 
-    let payload = buf.split_to(payload_len).freeze();
-    src.advance(4 + cmd_len + payload_len);
+/// Reads a length prefixed string, all of which is in `buf`.
+fn decode_str(buf: &mut BytesMut) -> io::Result<String> {
+  let len = buf.get_u16() as usize;
+  String::from_utf8(buf.split_to(len).to_vec()).map_err(|_| {
+    io::Error::new(ErrorKind::InvalidData, "Invalid UTF-8 metadata")
+  })
+}
 
-    Ok(Some(RequestFrame { command, arguments: payload }))
-  }
+/// Writes a length prefixed string, the counterpart of [decode_str].
+fn encode_str(value: &str, dst: &mut BytesMut) -> io::Result<()> {
+  let Ok(len) = u16::try_from(value.len()) else {
+    return Err(io::Error::new(ErrorKind::InvalidInput, "metadata too long"));
+  };
+  dst.put_u16(len);
+  dst.extend_from_slice(value.as_bytes());
+  Ok(())
 }
 
 impl Encoder<RequestFrame> for RequestFrameCodec {
@@ -165,20 +437,187 @@ impl Encoder<RequestFrame> for RequestFrameCodec {
     frame: RequestFrame,
     dst: &mut BytesMut,
   ) -> Result<(), Self::Error> {
+    let payload_len = payload_len(&frame.arguments)?;
     let cmd_bytes = frame.command.as_bytes();
-    let cmd_len = cmd_bytes.len();
+    let cmd_len = match u16::try_from(cmd_bytes.len()) {
+      Ok(len) if len & HAS_METADATA == 0 => len,
+      _ => {
+        return Err(io::Error::new(ErrorKind::InvalidInput, "command too long"))
+      }
+    };
 
-    dst.put_u16(cmd_len as u16);
-    dst.extend_from_slice(cmd_bytes);
+    if frame.metadata.is_empty() {
+      dst.put_u16(cmd_len);
+      dst.extend_from_slice(cmd_bytes);
+    } else {
+      // Checked up front, the server would reject the request anyway.
+      let metadata_count = frame.metadata.len();
+      if metadata_count > MAX_METADATA_ENTRIES {
+        return Err(io::Error::new(
+          ErrorKind::InvalidInput,
+          "too many metadata entries",
+        ));
+      }
+      let metadata_len: usize =
+        frame.metadata.iter().map(|(k, v)| 4 + k.len() + v.len()).sum();
+      if metadata_len > MAX_METADATA_LEN {
+        return Err(io::Error::new(
+          ErrorKind::InvalidInput,
+          "metadata too long",
+        ));
+      }
+      dst.put_u16(cmd_len | HAS_METADATA);
+      dst.extend_from_slice(cmd_bytes);
 
-    let payload_len = frame.arguments.len();
-    dst.put_u16(payload_len as u16);
+      dst.put_u16(metadata_count as u16);
+      for (key, value) in frame.metadata.iter() {
+        encode_str(key, dst)?;
+        encode_str(value, dst)?;
+      }
+    }
+
+    dst.put_u16(payload_len);
     dst.extend_from_slice(&frame.arguments);
 
     Ok(())
   }
 }
 
+/// What is sent either way on connections of protocol version 2, see
+/// [crate::transport::preamble]. Requests and responses carry an ID, so
+/// several can be in flight on one connection.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Frame {
+  Request(u32, RequestFrame),   // 0
+  Response(u32, ResponseFrame), // 1
+  /// Asks the peer for a [Frame::Pong] with the same value, to tell whether
+  /// it is still there.
+  Ping(u64), // 2
+  Pong(u64),                    // 3
+  /// The server takes no more requests on this connection, it answers the
+  /// ones up to this ID and then closes it. Requests with higher IDs should
+  /// be sent again on a new connection. IDs start at 1, `0` means none
+  /// were taken.
+  GoAway(u32), // 4
+}
+
+pub struct FrameCodec;
+
+impl Decoder for FrameCodec {
+  type Item = Frame;
+  type Error = io::Error;
+
+  fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<Frame>> {
+    if src.len() < 1 {
+      return Ok(None); // Not enough data for frame type
+    }
+
+    match src[0] {
+      0 => {
+        if src.len() < 5 {
+          return Ok(None); // Not enough data for request ID
+        }
+
+        let Some(len) = request_len(&src[5..])? else {
+          return Ok(None);
+        };
+        src.advance(1);
+        let id = src.get_u32();
+        let request = parse_request(src.split_to(len))?;
+        Ok(Some(Frame::Request(id, request)))
+      }
+      1 => {
+        if src.len() < 5 {
+          return Ok(None); // Not enough data for request ID
+        }
+
+        let mut body = src.clone();
+        body.advance(1);
+        let id = body.get_u32();
+        let len = body.len();
+        let frame =
+          ResponseFrameCodec.decode(&mut body)?.map(|r| Frame::Response(id, r));
+        if frame.is_some() {
+          src.advance(5 + len - body.len());
+        }
+        Ok(frame)
+      }
+      kind @ (2 | 3) => {
+        if src.len() < 9 {
+          return Ok(None); // Not enough data for ping value
+        }
+
+        src.advance(1);
+        let value = src.get_u64();
+        Ok(Some(match kind {
+          2 => Frame::Ping(value),
+          _ => Frame::Pong(value),
+        }))
+      }
+      4 => {
+        if src.len() < 5 {
+          return Ok(None); // Not enough data for request ID
+        }
+
+        src.advance(1);
+        Ok(Some(Frame::GoAway(src.get_u32())))
+      }
+      _ => Err(io::Error::new(ErrorKind::InvalidData, "Invalid frame type")),
+    }
+  }
+}
+
+impl Encoder<Frame> for FrameCodec {
+  type Error = io::Error;
+
+  fn encode(&mut self, frame: Frame, dst: &mut BytesMut) -> io::Result<()> {
+    // Nothing of a frame that can't be sent is left behind, so the ones
+    // after it still can be.
+    let start = dst.len();
+    let encoded = self.encode_frame(frame, dst);
+    if encoded.is_err() {
+      dst.truncate(start);
+    }
+    encoded
+  }
+}
+
+impl FrameCodec {
+  fn encode_frame(
+    &mut self,
+    frame: Frame,
+    dst: &mut BytesMut,
+  ) -> io::Result<()> {
+    match frame {
+      Frame::Request(id, request) => {
+        dst.put_u8(0);
+        dst.put_u32(id);
+        RequestFrameCodec.encode(request, dst)
+      }
+      Frame::Response(id, response) => {
+        dst.put_u8(1);
+        dst.put_u32(id);
+        ResponseFrameCodec.encode(response, dst)
+      }
+      Frame::Ping(value) => {
+        dst.put_u8(2);
+        dst.put_u64(value);
+        Ok(())
+      }
+      Frame::Pong(value) => {
+        dst.put_u8(3);
+        dst.put_u64(value);
+        Ok(())
+      }
+      Frame::GoAway(last) => {
+        dst.put_u8(4);
+        dst.put_u32(last);
+        Ok(())
+      }
+    }
+  }
+}
+
 #[test]
 pub fn request_decoding() {
   let mut buffer_vec = Vec::default();
@@ -193,14 +632,100 @@ pub fn request_decoding() {
 
   assert_eq!(
     result.unwrap().unwrap(),
-    RequestFrame { command: "hello".into(), arguments: Bytes::from("data") }
+    RequestFrame::new("hello".into(), Bytes::from("data"))
+  );
+}
+
+#[test]
+pub fn request_decoding_with_metadata() {
+  let mut buffer_vec = Vec::default();
+
+  buffer_vec.extend((5u16 | HAS_METADATA).to_be_bytes());
+  buffer_vec.extend(b"hello");
+  buffer_vec.extend(1u16.to_be_bytes());
+  buffer_vec.extend(3u16.to_be_bytes());
+  buffer_vec.extend(b"key");
+  buffer_vec.extend(5u16.to_be_bytes());
+  buffer_vec.extend(b"value");
+  buffer_vec.extend(4u16.to_be_bytes());
+  buffer_vec.extend(b"data");
+
+  let mut metadata = Metadata::new();
+  metadata.insert("key", "value");
+
+  let mut buffer_mut = BytesMut::from(buffer_vec.as_slice());
+  let result = RequestFrameCodec.decode(&mut buffer_mut);
+
+  assert_eq!(
+    result.unwrap().unwrap(),
+    RequestFrame {
+      command: "hello".into(),
+      metadata,
+      arguments: Bytes::from("data")
+    }
   );
+  assert!(buffer_mut.is_empty());
+
+  let mut partial = BytesMut::from(&buffer_vec[..buffer_vec.len() - 9]);
+  assert_eq!(RequestFrameCodec.decode(&mut partial).unwrap(), None);
+}
+
+#[test]
+pub fn metadata_is_limited() {
+  let with_metadata = |count: u16| {
+    let mut bytes = BytesMut::new();
+    bytes.put_u16(5 | HAS_METADATA);
+    bytes.extend_from_slice(b"hello");
+    bytes.put_u16(count);
+    bytes
+  };
+
+  // Rejected from the count alone, before any entry arrived.
+  let mut bytes = with_metadata(MAX_METADATA_ENTRIES as u16 + 1);
+  assert!(RequestFrameCodec.decode(&mut bytes).is_err());
+
+  // Or from the length prefixes, before the values arrived.
+  let mut bytes = with_metadata(1);
+  bytes.put_u16(3);
+  bytes.extend_from_slice(b"key");
+  bytes.put_u16(u16::MAX);
+  assert!(RequestFrameCodec.decode(&mut bytes).is_err());
+
+  // Duplicate keys are kept as sent rather than looked for.
+  let mut bytes = with_metadata(2);
+  for value in ["first", "second"] {
+    bytes.put_u16(3);
+    bytes.extend_from_slice(b"KEY");
+    bytes.put_u16(value.len() as u16);
+    bytes.extend_from_slice(value.as_bytes());
+  }
+  bytes.put_u16(0);
+  let request = RequestFrameCodec.decode(&mut bytes).unwrap().unwrap();
+  let entries: Vec<_> = request.metadata.iter().collect();
+  assert_eq!(entries, [("key", "first"), ("key", "second")]);
+  assert_eq!(request.metadata.get("key"), Some("first"));
+  assert!(bytes.is_empty());
+
+  // Not sent in the first place.
+  let request = |metadata: Metadata| {
+    RequestFrame::new("hello".into(), Bytes::new()).with_metadata(metadata)
+  };
+  let mut many = Metadata::new();
+  for i in 0..=MAX_METADATA_ENTRIES {
+    many.insert(format!("key-{i}"), "value");
+  }
+  let mut long = Metadata::new();
+  long.insert("key", "x".repeat(MAX_METADATA_LEN));
+  for metadata in [many, long] {
+    let mut bytes = BytesMut::new();
+    assert!(RequestFrameCodec.encode(request(metadata), &mut bytes).is_err());
+    assert!(bytes.is_empty());
+  }
 }
 
 #[test]
 pub fn request_encoding() {
-  let frame =
-    RequestFrame { command: "hello".into(), arguments: Bytes::from("data") };
+  let frame = RequestFrame::new("hello".into(), Bytes::from("data"));
 
   let mut bytes = BytesMut::default();
 
@@ -208,12 +733,18 @@ pub fn request_encoding() {
 
   let mut buffer_vec = Vec::default();
 
+  // Framed like before metadata existed.
   buffer_vec.extend(5u16.to_be_bytes());
   buffer_vec.extend(b"hello");
   buffer_vec.extend(4u16.to_be_bytes());
   buffer_vec.extend(b"data");
 
   assert_eq!(bytes, BytesMut::from(buffer_vec.as_slice()));
+  assert_eq!(
+    RequestFrameCodec.decode(&mut bytes).unwrap(),
+    Some(RequestFrame::new("hello".into(), Bytes::from("data")))
+  );
+  assert!(bytes.is_empty());
 }
 
 #[test]
@@ -253,9 +784,7 @@ pub fn response_encoding() {
 
   ResponseFrameCodec.encode(frame, &mut bytes).unwrap();
 
-  let mut buffer_vec = Vec::default();
-
-  buffer_vec.push(1u8);
+  let buffer_vec = vec![1u8];
 
   assert_eq!(bytes, BytesMut::from(buffer_vec.as_slice()));
 
@@ -275,3 +804,179 @@ pub fn response_encoding() {
 
   assert_eq!(bytes, BytesMut::from(buffer_vec.as_slice()));
 }
+
+#[test]
+pub fn oversized_payloads_are_rejected() {
+  let payload = Bytes::from(vec![0; u16::MAX as usize + 1]);
+
+  let frame = ResponseFrame::with_payload(payload.clone());
+  assert!(ResponseFrameCodec.encode(frame, &mut BytesMut::new()).is_err());
+
+  let frame = RequestFrame::new("hello".into(), payload);
+  let mut bytes = BytesMut::new();
+  assert!(FrameCodec.encode(Frame::Request(1, frame), &mut bytes).is_err());
+  assert!(bytes.is_empty());
+}
+
+#[test]
+pub fn internal_error_roundtrip() {
+  for message in [None, Some("handler panicked".to_string())] {
+    let frame = ResponseFrame::Error(ResponseErrorKind::Internal(message));
+
+    let mut bytes = BytesMut::default();
+    ResponseFrameCodec.encode(frame.clone(), &mut bytes).unwrap();
+
+    let mut partial = bytes.split_to(bytes.len() - 1);
+    assert_eq!(ResponseFrameCodec.decode(&mut partial).unwrap(), None);
+    partial.unsplit(bytes);
+
+    assert_eq!(ResponseFrameCodec.decode(&mut partial).unwrap(), Some(frame));
+    assert!(partial.is_empty());
+  }
+
+  // Two bytes per char, so the limit falls inside one.
+  let long = "é".repeat(40_000);
+  let frame = ResponseFrame::Error(ResponseErrorKind::Internal(Some(long)));
+  let next = ResponseFrame::Error(ResponseErrorKind::Overloaded);
+  let mut bytes = BytesMut::default();
+  ResponseFrameCodec.encode(frame, &mut bytes).unwrap();
+  ResponseFrameCodec.encode(next.clone(), &mut bytes).unwrap();
+
+  let Some(ResponseFrame::Error(ResponseErrorKind::Internal(Some(message)))) =
+    ResponseFrameCodec.decode(&mut bytes).unwrap()
+  else {
+    panic!("expected an internal error");
+  };
+  assert_eq!(message, "é".repeat(32_767));
+  assert_eq!(ResponseFrameCodec.decode(&mut bytes).unwrap(), Some(next));
+  assert!(bytes.is_empty());
+}
+
+#[test]
+pub fn overloaded_roundtrip() {
+  let frame = ResponseFrame::Error(ResponseErrorKind::Overloaded);
+
+  let mut bytes = BytesMut::default();
+  ResponseFrameCodec.encode(frame.clone(), &mut bytes).unwrap();
+  assert_eq!(bytes, BytesMut::from(&[5u8][..]));
+
+  assert_eq!(ResponseFrameCodec.decode(&mut bytes).unwrap(), Some(frame));
+  assert!(bytes.is_empty());
+}
+
+#[test]
+pub fn rate_limited_roundtrip() {
+  let frame = ResponseFrame::Error(ResponseErrorKind::RateLimited {
+    retry_after: Duration::from_micros(1500),
+  });
+
+  let mut bytes = BytesMut::default();
+  ResponseFrameCodec.encode(frame, &mut bytes).unwrap();
+
+  let mut partial = bytes.split_to(bytes.len() - 1);
+  assert_eq!(ResponseFrameCodec.decode(&mut partial).unwrap(), None);
+  partial.unsplit(bytes);
+
+  assert_eq!(
+    ResponseFrameCodec.decode(&mut partial).unwrap(),
+    Some(ResponseFrame::Error(ResponseErrorKind::RateLimited {
+      retry_after: Duration::from_millis(2),
+    }))
+  );
+  assert!(partial.is_empty());
+}
+
+#[test]
+pub fn status_roundtrip() {
+  let statuses = [
+    Status::new(Code::NotFound),
+    Status::new(Code::PermissionDenied)
+      .with_message("not yours")
+      .with_details(Bytes::from_static(&[1, 2, 3])),
+  ];
+  for status in statuses {
+    let frame = ResponseFrame::Error(ResponseErrorKind::Status(status));
+
+    let mut bytes = BytesMut::default();
+    ResponseFrameCodec.encode(frame.clone(), &mut bytes).unwrap();
+
+    for len in 0..bytes.len() {
+      let mut partial = BytesMut::from(&bytes[..len]);
+      assert_eq!(ResponseFrameCodec.decode(&mut partial).unwrap(), None);
+    }
+    assert_eq!(ResponseFrameCodec.decode(&mut bytes).unwrap(), Some(frame));
+    assert!(bytes.is_empty());
+  }
+
+  // Two bytes per char, so the limit falls inside one.
+  let long = Status::new(Code::Internal)
+    .with_message("é".repeat(35 * 1024))
+    .with_details(Bytes::from(vec![0; 70 * 1024]));
+  let frame = ResponseFrame::Error(ResponseErrorKind::Status(long));
+  let next = ResponseFrame::Error(ResponseErrorKind::Overloaded);
+  let mut bytes = BytesMut::default();
+  ResponseFrameCodec.encode(frame, &mut bytes).unwrap();
+  ResponseFrameCodec.encode(next.clone(), &mut bytes).unwrap();
+
+  let Some(ResponseFrame::Error(ResponseErrorKind::Status(status))) =
+    ResponseFrameCodec.decode(&mut bytes).unwrap()
+  else {
+    panic!("expected a status");
+  };
+  assert_eq!(status.code(), Code::Internal);
+  assert_eq!(status.message(), Some("é".repeat(32_767).as_str()));
+  assert!(status.details().is_empty());
+  assert_eq!(ResponseFrameCodec.decode(&mut bytes).unwrap(), Some(next));
+  assert!(bytes.is_empty());
+}
+
+#[test]
+pub fn consecutive_responses_are_consumed() {
+  let mut bytes = BytesMut::from(&[1, 0, 0, 1, 42, 3][..]);
+
+  assert_eq!(
+    ResponseFrameCodec.decode(&mut bytes).unwrap(),
+    Some(ResponseFrame::Error(ResponseErrorKind::MethodNotFound))
+  );
+  assert_eq!(
+    ResponseFrameCodec.decode(&mut bytes).unwrap(),
+    Some(ResponseFrame::with_payload(Bytes::from_static(&[42])))
+  );
+  assert_eq!(
+    ResponseFrameCodec.decode(&mut bytes).unwrap(),
+    Some(ResponseFrame::Error(ResponseErrorKind::Timeout))
+  );
+  assert!(bytes.is_empty());
+}
+
+#[test]
+pub fn frame_roundtrip() {
+  let frames = [
+    Frame::Request(
+      7,
+      RequestFrame::new("Ping".into(), Bytes::from_static(&[0; 4])),
+    ),
+    Frame::Response(7, ResponseFrame::with_payload(Bytes::from_static(b"hi"))),
+    Frame::Response(8, ResponseFrame::Error(ResponseErrorKind::Overloaded)),
+    Frame::Ping(42),
+    Frame::Pong(42),
+    Frame::GoAway(7),
+  ];
+
+  let mut bytes = BytesMut::default();
+  for frame in frames.clone() {
+    FrameCodec.encode(frame, &mut bytes).unwrap();
+  }
+
+  // Byte by byte, every frame only shows up once it is complete.
+  let mut decoded = Vec::new();
+  let mut partial = BytesMut::new();
+  for byte in bytes {
+    partial.put_u8(byte);
+    if let Some(frame) = FrameCodec.decode(&mut partial).unwrap() {
+      decoded.push(frame);
+    }
+  }
+  assert_eq!(decoded, frames);
+  assert!(partial.is_empty());
+}
@@ -0,0 +1,76 @@
+//! Ping frames on connections of protocol version 2, so both sides notice
+//! peers that went away without closing the connection, e.g. behind a NAT or
+//! firewall that forgot about it.
+
+use tokio::time::{Duration, Instant};
+
+/// Pings the peer every `interval`, and gives up on the connection if a ping
+/// isn't answered within `timeout`.
+#[derive(Debug, Clone, Copy)]
+pub struct Keepalive {
+  pub interval: Duration,
+  pub timeout: Duration,
+}
+
+impl Keepalive {
+  pub fn new(interval: Duration, timeout: Duration) -> Self {
+    Self { interval, timeout }
+  }
+}
+
+impl Default for Keepalive {
+  fn default() -> Self {
+    Self::new(Duration::from_secs(30), Duration::from_secs(10))
+  }
+}
+
+/// When one side of a connection pings next, and whether the last ping got
+/// its answer.
+pub(crate) struct Pings {
+  keepalive: Keepalive,
+  next: Instant,
+  outstanding: Option<u64>,
+  sent: u64,
+}
+
+pub(crate) enum Due {
+  /// Send a ping with this value.
+  Ping(u64),
+  /// The last ping wasn't answered in time.
+  Dead,
+}
+
+impl Pings {
+  pub(crate) fn new(keepalive: Keepalive) -> Self {
+    Self {
+      keepalive,
+      next: Instant::now() + keepalive.interval,
+      outstanding: None,
+      sent: 0,
+    }
+  }
+
+  /// When [Pings::due] should be called.
+  pub(crate) fn deadline(&self) -> Instant {
+    self.next
+  }
+
+  pub(crate) fn due(&mut self) -> Due {
+    if self.outstanding.is_some() {
+      return Due::Dead;
+    }
+
+    self.sent += 1;
+    self.outstanding = Some(self.sent);
+    self.next = Instant::now() + self.keepalive.timeout;
+    Due::Ping(self.sent)
+  }
+
+  /// The peer answered with `value`.
+  pub(crate) fn pong(&mut self, value: u64) {
+    if self.outstanding == Some(value) {
+      self.outstanding = None;
+      self.next = Instant::now() + self.keepalive.interval;
+    }
+  }
+}
@@ -1,4 +1,225 @@
 pub mod channel;
+pub mod connection;
+pub mod duplex;
 pub mod tcp;
+#[cfg(unix)]
+pub mod unix;
 
 pub mod frame;
+pub mod keepalive;
+pub mod preamble;
+
+use std::{
+  io,
+  time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures_util::{Sink, SinkExt, Stream, StreamExt};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+  metadata::Metadata,
+  metrics::{self, Side},
+  trace, ClientError,
+};
+use channel::{BoundedChannel, UnboundedChannel};
+use frame::{RequestFrame, ResponseErrorKind, ResponseFrame};
+
+/// The client half of a connection: sends one [RequestFrame] and waits for
+/// the [ResponseFrame] answering it.
+#[async_trait]
+pub trait ClientTransport: Send {
+  async fn call(
+    &mut self,
+    request: RequestFrame,
+  ) -> Result<ResponseFrame, ClientError>;
+}
+
+#[cfg(test)]
+static_assertions::assert_obj_safe!(ClientTransport);
+
+#[async_trait]
+impl ClientTransport for Box<dyn ClientTransport> {
+  async fn call(
+    &mut self,
+    request: RequestFrame,
+  ) -> Result<ResponseFrame, ClientError> {
+    (**self).call(request).await
+  }
+}
+
+#[async_trait]
+impl ClientTransport for UnboundedChannel<ResponseFrame, RequestFrame> {
+  async fn call(
+    &mut self,
+    request: RequestFrame,
+  ) -> Result<ResponseFrame, ClientError> {
+    call_channel(self, request).await
+  }
+}
+
+#[async_trait]
+impl ClientTransport for BoundedChannel<ResponseFrame, RequestFrame> {
+  async fn call(
+    &mut self,
+    request: RequestFrame,
+  ) -> Result<ResponseFrame, ClientError> {
+    call_channel(self, request).await
+  }
+}
+
+async fn call_channel<C, E>(
+  channel: &mut C,
+  request: RequestFrame,
+) -> Result<ResponseFrame, ClientError>
+where
+  C: Stream<Item = Result<ResponseFrame, E>> + Sink<RequestFrame> + Unpin,
+{
+  channel.send(request).await.map_err(|_| {
+    ClientError::IoError(io::Error::new(
+      io::ErrorKind::BrokenPipe,
+      "channel server is gone",
+    ))
+  })?;
+
+  match channel.next().await {
+    Some(Ok(frame)) => Ok(frame),
+    _ => Err(ClientError::IoError(io::ErrorKind::UnexpectedEof.into())),
+  }
+}
+
+/// How a single call is made, see [send_request].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CallOptions {
+  /// Gives up on the call after this long, retries included.
+  pub timeout: Option<Duration>,
+  /// How many times a rate limited request is retried, after waiting as long
+  /// as the server asked.
+  pub rate_limit_retries: u32,
+}
+
+/// Calls `method` of `service`. `method` is only used for instrumentation,
+/// `req` already encodes which method is called.
+pub async fn send_request<Req: Serialize, Res: DeserializeOwned>(
+  transport: &mut dyn ClientTransport,
+  service: &'static str,
+  method: &'static str,
+  req: Req,
+  options: CallOptions,
+) -> Result<Res, ClientError> {
+  let body = bincode::serialize(&req).map_err(ClientError::EncodingError)?;
+
+  let request_size = body.len();
+  let span = trace::client_rpc_span(service, method, request_size);
+  let in_flight = metrics::InFlight::new(Side::Client, service);
+  let started = Instant::now();
+
+  let mut metadata = Metadata::new();
+  trace::inject(&span, &mut metadata);
+
+  let request_frame = RequestFrame::new(service.to_string(), Bytes::from(body))
+    .with_metadata(metadata);
+  let call = trace::instrument(
+    call_with_retries(transport, request_frame, options.rate_limit_retries),
+    &span,
+  );
+  let response = match options.timeout {
+    Some(timeout) => tokio::time::timeout(timeout, call).await,
+    None => Ok(call.await),
+  };
+
+  let elapsed = started.elapsed();
+  let response = match response {
+    Ok(Ok(response)) => response,
+    Ok(Err(err)) => {
+      metrics::record_failed_request(
+        service,
+        method,
+        request_size,
+        0,
+        &err,
+        elapsed,
+      );
+      return Err(err);
+    }
+    Err(_) => {
+      let frame = ResponseFrame::Error(ResponseErrorKind::Timeout);
+      trace::record_response(&span, &frame, elapsed);
+      metrics::record_request(
+        Side::Client,
+        service,
+        method,
+        request_size,
+        &frame,
+        elapsed,
+      );
+      return Err(ClientError::Timeout);
+    }
+  };
+  drop(in_flight);
+  trace::record_response(&span, &response, elapsed);
+
+  // Payloads are recorded once decoded, one that can't be isn't `ok`.
+  if let ResponseFrame::Error(_) = &response {
+    metrics::record_request(
+      Side::Client,
+      service,
+      method,
+      request_size,
+      &response,
+      elapsed,
+    );
+  }
+  let data = match response {
+    ResponseFrame::Payload(data) => data,
+    ResponseFrame::Error(err) => return Err(ClientError::ServerError(err)),
+  };
+  match bincode::deserialize(&data) {
+    Ok(decoded) => {
+      let response = ResponseFrame::Payload(data);
+      metrics::record_request(
+        Side::Client,
+        service,
+        method,
+        request_size,
+        &response,
+        elapsed,
+      );
+      Ok(decoded)
+    }
+    Err(err) => {
+      trace::warn_event!(parent: &span, error = %err, "failed to decode response");
+      let err = ClientError::DecodingError(err);
+      metrics::record_failed_request(
+        service,
+        method,
+        request_size,
+        data.len(),
+        &err,
+        elapsed,
+      );
+      Err(err)
+    }
+  }
+}
+
+async fn call_with_retries(
+  transport: &mut dyn ClientTransport,
+  request: RequestFrame,
+  mut retries: u32,
+) -> Result<ResponseFrame, ClientError> {
+  loop {
+    match transport.call(request.clone()).await? {
+      ResponseFrame::Error(ResponseErrorKind::RateLimited { retry_after })
+        if retries > 0 =>
+      {
+        retries -= 1;
+        trace::warn_event!(?retry_after, "rate limited, retrying");
+        tokio::time::sleep(retry_after).await;
+      }
+      response => return Ok(response),
+    }
+  }
+}
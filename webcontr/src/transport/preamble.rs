@@ -0,0 +1,303 @@
+//! The first bytes of every connection, telling both sides which protocol
+//! version and optional features the rest of it uses.
+//!
+//! The client opens with a [ClientHello], the server answers with a
+//! [ServerHello] picking the highest version both support and the features
+//! both enabled. Clients that only offer version 1 and features which don't
+//! change its framing, or none at all, may send requests right after their
+//! hello. Others need to wait for the answer to know how to frame them.
+//!
+//! In version 1 a connection carries one request at a time, each answered
+//! before the next is sent. From version 2 on, every message is a
+//! [crate::transport::frame::Frame], so requests are answered as they
+//! complete and either side may ping the other.
+//!
+//! Servers still accept clients from before the preamble existed, their
+//! first bytes are a request instead of [MAGIC]. They get version 1 without
+//! any features, and since they never send request metadata their requests
+//! are framed just like those of version 1.
+//!
+//! The other way around doesn't work: a server from before the preamble
+//! reads [MAGIC] as the length of a request and waits for the rest of it.
+//! So upgrade servers first, then clients. Until then clients only send a
+//! preamble when they need what it negotiates:
+//!
+//! - Clients making one call per connection, e.g.
+//!   [crate::transport::tcp::client::TcpTransport], frame requests like
+//!   version 1 without one, unless they offer compression.
+//! - A [crate::transport::connection::Connection] always needs version 2.
+//!   Against an old server it gives up after its connect timeout and backs
+//!   off like for a server that can't be reached.
+
+use std::{
+  fmt, io,
+  ops::{BitAnd, BitOr},
+};
+
+use bytes::{Buf, BufMut, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+
+/// Opens every [ClientHello] and [ServerHello].
+pub const MAGIC: [u8; 4] = *b"WCTR";
+
+/// Versions of the framing this crate speaks.
+pub const MIN_VERSION: u16 = 1;
+pub const MAX_VERSION: u16 = 2;
+
+/// The first version with [crate::transport::frame::Frame]s.
+pub const MULTIPLEXED_VERSION: u16 = 2;
+
+/// Optional protocol extensions. Bits neither side knows about are never
+/// negotiated, so new features can be rolled out one peer at a time.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Features(u32);
+
+impl Features {
+  /// Payloads may be compressed with zstd, see [crate::compression].
+  pub const ZSTD: Features = Features(1 << 0);
+  /// Payloads may be compressed with lz4.
+  pub const LZ4: Features = Features(1 << 1);
+  /// Payloads may be compressed with gzip.
+  pub const GZIP: Features = Features(1 << 2);
+  /// Any of the compression algorithms.
+  pub const COMPRESSION: Features =
+    Features(Self::ZSTD.0 | Self::LZ4.0 | Self::GZIP.0);
+
+  pub const fn empty() -> Self {
+    Self(0)
+  }
+
+  pub const fn from_bits(bits: u32) -> Self {
+    Self(bits)
+  }
+
+  pub const fn bits(self) -> u32 {
+    self.0
+  }
+
+  pub const fn contains(self, other: Features) -> bool {
+    self.0 & other.0 == other.0
+  }
+
+  pub const fn is_empty(self) -> bool {
+    self.0 == 0
+  }
+}
+
+impl BitOr for Features {
+  type Output = Self;
+
+  fn bitor(self, rhs: Self) -> Self {
+    Self(self.0 | rhs.0)
+  }
+}
+
+impl BitAnd for Features {
+  type Output = Self;
+
+  fn bitand(self, rhs: Self) -> Self {
+    Self(self.0 & rhs.0)
+  }
+}
+
+impl fmt::Debug for Features {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "Features({:#x})", self.0)
+  }
+}
+
+/// What a client supports, sent before its first request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientHello {
+  pub min_version: u16,
+  pub max_version: u16,
+  pub features: Features,
+}
+
+impl ClientHello {
+  pub fn new(features: Features) -> Self {
+    Self { min_version: MIN_VERSION, max_version: MAX_VERSION, features }
+  }
+
+  /// The server's answer, given the features it enabled.
+  pub fn negotiate(&self, features: Features) -> ServerHello {
+    let version = self.max_version.min(MAX_VERSION);
+    if version < self.min_version.max(MIN_VERSION) {
+      return ServerHello::REJECTED;
+    }
+
+    ServerHello { version, features: self.features & features }
+  }
+}
+
+/// What the connection uses from here on, in answer to a [ClientHello].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ServerHello {
+  /// `0` if the client's versions aren't supported, the server closes the
+  /// connection after saying so.
+  pub version: u16,
+  pub features: Features,
+}
+
+impl ServerHello {
+  pub const REJECTED: ServerHello =
+    ServerHello { version: 0, features: Features::empty() };
+
+  /// The settings of connections from clients without a preamble.
+  pub const LEGACY: ServerHello =
+    ServerHello { version: 1, features: Features::empty() };
+
+  pub fn is_rejected(&self) -> bool {
+    self.version == 0
+  }
+}
+
+/// What a server reads first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Preamble {
+  Hello(ClientHello),
+  /// The connection started with a request, which is left in the buffer.
+  Legacy,
+}
+
+pub struct PreambleCodec;
+
+impl Decoder for PreambleCodec {
+  type Item = Preamble;
+  type Error = io::Error;
+
+  fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<Preamble>> {
+    if src.len() < MAGIC.len() {
+      return Ok(None); // Not enough data for magic
+    }
+    if src[..MAGIC.len()] != MAGIC {
+      return Ok(Some(Preamble::Legacy));
+    }
+    if src.len() < 12 {
+      return Ok(None); // Not enough data for versions and features
+    }
+
+    src.advance(MAGIC.len());
+    Ok(Some(Preamble::Hello(ClientHello {
+      min_version: src.get_u16(),
+      max_version: src.get_u16(),
+      features: Features(src.get_u32()),
+    })))
+  }
+}
+
+impl Encoder<ClientHello> for PreambleCodec {
+  type Error = io::Error;
+
+  fn encode(
+    &mut self,
+    hello: ClientHello,
+    dst: &mut BytesMut,
+  ) -> io::Result<()> {
+    dst.put_slice(&MAGIC);
+    dst.put_u16(hello.min_version);
+    dst.put_u16(hello.max_version);
+    dst.put_u32(hello.features.0);
+    Ok(())
+  }
+}
+
+pub struct ServerHelloCodec;
+
+impl Decoder for ServerHelloCodec {
+  type Item = ServerHello;
+  type Error = io::Error;
+
+  fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<ServerHello>> {
+    if src.len() < MAGIC.len() {
+      return Ok(None); // Not enough data for magic
+    }
+    if src[..MAGIC.len()] != MAGIC {
+      return Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "peer doesn't speak the webcontr protocol",
+      ));
+    }
+    if src.len() < 10 {
+      return Ok(None); // Not enough data for version and features
+    }
+
+    src.advance(MAGIC.len());
+    Ok(Some(ServerHello {
+      version: src.get_u16(),
+      features: Features(src.get_u32()),
+    }))
+  }
+}
+
+impl Encoder<ServerHello> for ServerHelloCodec {
+  type Error = io::Error;
+
+  fn encode(
+    &mut self,
+    hello: ServerHello,
+    dst: &mut BytesMut,
+  ) -> io::Result<()> {
+    dst.put_slice(&MAGIC);
+    dst.put_u16(hello.version);
+    dst.put_u32(hello.features.0);
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn negotiates_common_version_and_features() {
+    let hello = ClientHello {
+      min_version: 1,
+      max_version: 7,
+      features: Features(0b0111),
+    };
+    assert_eq!(
+      hello.negotiate(Features(0b1101)),
+      ServerHello { version: MAX_VERSION, features: Features(0b0101) }
+    );
+
+    let newer = ClientHello { min_version: 5, ..hello };
+    assert!(newer.negotiate(Features(0b1101)).is_rejected());
+  }
+
+  #[test]
+  fn hello_roundtrip() {
+    let hello = ClientHello::new(Features(3));
+    let mut bytes = BytesMut::new();
+    PreambleCodec.encode(hello, &mut bytes).unwrap();
+    bytes.put_u8(42); // The first request.
+
+    let mut partial = bytes.split_to(6);
+    assert_eq!(PreambleCodec.decode(&mut partial).unwrap(), None);
+    partial.unsplit(bytes);
+    assert_eq!(
+      PreambleCodec.decode(&mut partial).unwrap(),
+      Some(Preamble::Hello(hello))
+    );
+    assert_eq!(&partial[..], &[42]);
+
+    let mut bytes = BytesMut::new();
+    ServerHelloCodec.encode(ServerHello::LEGACY, &mut bytes).unwrap();
+    assert_eq!(
+      ServerHelloCodec.decode(&mut bytes).unwrap(),
+      Some(ServerHello::LEGACY)
+    );
+    assert!(bytes.is_empty());
+  }
+
+  #[test]
+  fn requests_without_preamble_are_legacy() {
+    // Command length of a request for `Ping`.
+    let mut bytes = BytesMut::from(&[0, 4, b'P', b'i', b'n', b'g'][..]);
+    assert_eq!(
+      PreambleCodec.decode(&mut bytes).unwrap(),
+      Some(Preamble::Legacy)
+    );
+    assert_eq!(bytes.len(), 6);
+  }
+}
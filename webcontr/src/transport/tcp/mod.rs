@@ -18,91 +18,226 @@ where
 }
 
 pub mod client {
-  use super::{request_transport, response_transport};
-  use bytes::Bytes;
-  use futures_util::{SinkExt, StreamExt};
+  use std::io;
+
+  use async_trait::async_trait;
+  use bytes::BytesMut;
+  use futures_util::StreamExt;
   use serde::{de::DeserializeOwned, Serialize};
-  use tokio::net::TcpStream;
+  use tokio::{
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt},
+    net::TcpStream,
+  };
+  use tokio_util::codec::{Encoder, FramedRead};
 
   use crate::{
-    transport::frame::{RequestFrame, ResponseFrame},
+    accept::Io,
+    compression::Compression,
+    transport::{
+      connection::Connect,
+      frame::{
+        RequestFrame, RequestFrameCodec, ResponseFrame, ResponseFrameCodec,
+      },
+      preamble::{ClientHello, PreambleCodec, ServerHelloCodec, MIN_VERSION},
+      send_request, CallOptions, ClientTransport,
+    },
     ClientError,
   };
 
-  pub async fn send_client_req<Req: Serialize, Res: DeserializeOwned>(
-    cmd: &'static str,
-    req: Req,
-    addr: &str,
-  ) -> Result<Res, ClientError> {
-    let stream =
-      TcpStream::connect(addr).await.map_err(ClientError::IoError)?;
-    let stream = {
+  /// Dials `addr` for every call. See
+  /// [crate::transport::connection::Connection] to keep a connection open
+  /// instead.
+  pub struct TcpTransport {
+    addr: String,
+    compression: Compression,
+    #[cfg(feature = "tls")]
+    tls_config: Option<std::sync::Arc<tokio_rustls::rustls::ClientConfig>>,
+  }
+
+  impl TcpTransport {
+    pub fn new(addr: impl Into<String>) -> Self {
+      Self {
+        addr: addr.into(),
+        compression: Compression::default(),
+        #[cfg(feature = "tls")]
+        tls_config: None,
+      }
+    }
+
+    /// Offers `compression` to the server, see [crate::compression].
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+      self.compression = compression;
+      self
+    }
+
+    /// Verifies the server with `config`, see
+    /// [crate::tls::clientconfig_from_pem]. Calls fail with
+    /// [ClientError::TlsError] until one is set.
+    #[cfg(feature = "tls")]
+    pub fn with_tls_config(
+      mut self,
+      config: std::sync::Arc<tokio_rustls::rustls::ClientConfig>,
+    ) -> Self {
+      self.tls_config = Some(config);
+      self
+    }
+  }
+
+  #[async_trait]
+  impl Connect for TcpTransport {
+    async fn connect(&self) -> io::Result<Box<dyn Io>> {
+      let addr = self.addr.as_str();
       #[cfg(not(feature = "tls"))]
       {
-        stream
+        Ok(Box::new(TcpStream::connect(addr).await?))
       }
       #[cfg(feature = "tls")]
       {
-        use std::sync::Arc;
         use tokio_rustls::{
-          rustls::{
-            pki_types::{pem::PemObject, CertificateDer, ServerName},
-            ClientConfig, RootCertStore,
-          },
+          rustls::{pki_types::ServerName, Error as TlsError},
           TlsConnector,
         };
 
-        const ROOT: &str = include_str!("../../../tests/certs/root.pem");
-        let mut client_root_cert_store = RootCertStore::empty();
-        for root in CertificateDer::pem_slice_iter(ROOT.as_bytes()) {
-          client_root_cert_store.add(root.unwrap()).unwrap();
-        }
-
-        let cconfig = ClientConfig::builder()
-          .with_root_certificates(client_root_cert_store)
-          .with_no_client_auth();
-        let connector = TlsConnector::from(Arc::new(cconfig));
-        connector
-          .connect(
-            ServerName::try_from(addr.split(":").collect::<Vec<&str>>()[0])
-              .unwrap()
-              .to_owned(),
-            stream,
-          )
-          .await
-          .unwrap()
+        let stream = TcpStream::connect(addr).await?;
+        let Some(cconfig) = self.tls_config.clone() else {
+          return Err(tls_error(TlsError::General(
+            "no root certificates configured, see with_tls_config".into(),
+          )));
+        };
+        let host = addr.split(':').next().unwrap_or_default();
+        let server_name = ServerName::try_from(host)
+          .map_err(|err| tls_error(TlsError::General(err.to_string())))?
+          .to_owned();
+        let connector = TlsConnector::from(cconfig);
+        Ok(Box::new(connector.connect(server_name, stream).await?))
       }
-    };
-
-    let body = bincode::serialize(&req).map_err(ClientError::EncodingError)?;
+    }
+  }
 
-    let request_frame = RequestFrame::new(cmd.to_string(), Bytes::from(body));
-    let mut transport = request_transport(stream);
-    SinkExt::send(&mut transport, request_frame).await.unwrap();
+  /// TLS set up failures, as a `rustls::Error` like handshake failures are,
+  /// see [ClientError::TlsError].
+  #[cfg(feature = "tls")]
+  fn tls_error(err: tokio_rustls::rustls::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, err)
+  }
 
-    let stream = transport.into_inner();
+  #[async_trait]
+  impl ClientTransport for TcpTransport {
+    async fn call(
+      &mut self,
+      request: RequestFrame,
+    ) -> Result<ResponseFrame, ClientError> {
+      let stream = self.connect().await.map_err(ClientError::connecting)?;
+      call_io(stream, request, &self.compression).await
+    }
+  }
 
-    let mut transport = response_transport(stream);
+  /// Sends `request` over an already established connection and reads the
+  /// response. The [crate::transport::preamble] is only sent if `compression`
+  /// needs it, so servers from before it can still be called otherwise.
+  pub(crate) async fn call_io<T>(
+    mut io: T,
+    request: RequestFrame,
+    compression: &Compression,
+  ) -> Result<ResponseFrame, ClientError>
+  where
+    T: AsyncRead + AsyncWrite + Unpin,
+  {
+    let features = compression.features();
+    if features.is_empty() {
+      // Framed like version 1, which is all a single call needs.
+      let mut buf = BytesMut::new();
+      RequestFrameCodec.encode(request, &mut buf)?;
+      write(&mut io, &mut buf).await?;
+      let mut transport = FramedRead::new(io, ResponseFrameCodec);
+      return match transport.next().await {
+        Some(frame) => frame.map_err(read_error),
+        None => Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+      };
+    }
 
-    let response_frame_outer;
+    // A single call doesn't need anything newer than the first version.
+    let hello = ClientHello {
+      min_version: MIN_VERSION,
+      max_version: MIN_VERSION,
+      features,
+    };
+    let mut buf = BytesMut::new();
+    PreambleCodec.encode(hello, &mut buf)?;
+    write(&mut io, &mut buf).await?;
+
+    let mut transport = FramedRead::new(io, ServerHelloCodec);
+    let hello = match transport.next().await {
+      Some(hello) => hello.map_err(read_error)?,
+      None => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+    };
+    if hello.is_rejected() {
+      return Err(ClientError::ProtocolError(format!(
+        "server doesn't support protocol version {MIN_VERSION}"
+      )));
+    }
+    let payloads = compression.negotiated(hello.features);
+
+    // Compression changes the framing, so the request waits for the answer.
+    let arguments = payloads.compress(request.arguments.clone())?;
+    RequestFrameCodec
+      .encode(RequestFrame { arguments, ..request }, &mut buf)?;
+    write(transport.get_mut(), &mut buf).await?;
+
+    let mut transport = transport.map_decoder(|_| ResponseFrameCodec);
+    match transport.next().await {
+      Some(Ok(ResponseFrame::Payload(payload))) => payloads
+        .decompress(payload)
+        .map(ResponseFrame::Payload)
+        .map_err(ClientError::protocol),
+      Some(frame) => frame.map_err(read_error),
+      None => Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+    }
+  }
 
-    loop {
-      let response_frame_in = StreamExt::next(&mut transport).await;
-      match response_frame_in {
-        None => continue,
-        Some(v) => {
-          response_frame_outer = v.map_err(ClientError::IoError)?;
-          break;
-        }
-      }
+  /// Frames that don't decode are the server's fault, anything else is the
+  /// connection's.
+  fn read_error(err: io::Error) -> ClientError {
+    match err.kind() {
+      io::ErrorKind::InvalidData => ClientError::protocol(err),
+      _ => ClientError::IoError(err),
     }
+  }
 
-    let thing: Res = match response_frame_outer {
-      ResponseFrame::Error(err) => return Err(ClientError::ServerError(err)),
-      ResponseFrame::Payload(data) => bincode::deserialize(&data).unwrap(),
-    };
+  async fn write<T>(io: &mut T, buf: &mut BytesMut) -> Result<(), ClientError>
+  where
+    T: AsyncWrite + Unpin,
+  {
+    io.write_all(buf).await?;
+    buf.clear();
+    Ok(io.flush().await?)
+  }
+
+  /// Its spans and metrics name the method `unknown`, see
+  /// [send_client_call] to name it.
+  pub async fn send_client_req<Req: Serialize, Res: DeserializeOwned>(
+    cmd: &'static str,
+    req: Req,
+    addr: &str,
+  ) -> Result<Res, ClientError> {
+    send_client_call(cmd, "unknown", req, addr).await
+  }
 
-    Ok(thing)
+  /// Like [send_client_req], `method` is only used for instrumentation.
+  pub async fn send_client_call<Req: Serialize, Res: DeserializeOwned>(
+    cmd: &'static str,
+    method: &'static str,
+    req: Req,
+    addr: &str,
+  ) -> Result<Res, ClientError> {
+    send_request(
+      &mut TcpTransport::new(addr),
+      cmd,
+      method,
+      req,
+      CallOptions::default(),
+    )
+    .await
   }
 }
 
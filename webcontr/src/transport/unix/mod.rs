@@ -0,0 +1,154 @@
+use std::{
+  fs, io,
+  os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt},
+  path::{Path, PathBuf},
+};
+
+use tokio::net::UnixListener;
+
+/// Binds a [UnixListener], optionally restricting who can connect and
+/// taking over socket files left behind by a previous process.
+pub struct UnixSocket {
+  path: PathBuf,
+  mode: Option<u32>,
+  remove_stale: bool,
+}
+
+impl UnixSocket {
+  pub fn new(path: impl Into<PathBuf>) -> Self {
+    Self { path: path.into(), mode: None, remove_stale: false }
+  }
+
+  /// Sets the permission bits of the socket file, e.g. `0o660` to only allow
+  /// the owning user and group. The socket is bound in a directory only the
+  /// current user can enter and linked into place once it has them, so it is
+  /// never reachable with looser permissions.
+  pub fn with_mode(mut self, mode: u32) -> Self {
+    self.mode = Some(mode);
+    self
+  }
+
+  /// Removes an existing socket file at the path if nothing is listening on
+  /// it anymore. A socket that still accepts connections is left alone and
+  /// binding fails with [io::ErrorKind::AddrInUse].
+  pub fn with_stale_cleanup(mut self) -> Self {
+    self.remove_stale = true;
+    self
+  }
+
+  pub fn bind(self) -> io::Result<UnixListener> {
+    if self.remove_stale {
+      remove_stale_socket(&self.path)?;
+    }
+
+    match self.mode {
+      Some(mode) => bind_with_mode(&self.path, mode),
+      None => UnixListener::bind(&self.path),
+    }
+  }
+}
+
+fn bind_with_mode(path: &Path, mode: u32) -> io::Result<UnixListener> {
+  let Some(name) = path.file_name() else {
+    return Err(io::Error::new(
+      io::ErrorKind::InvalidInput,
+      "socket path has no file name",
+    ));
+  };
+  let mut private = name.to_os_string();
+  private.push(format!(".{}.tmp", std::process::id()));
+  let private = path.with_file_name(private);
+  fs::DirBuilder::new().mode(0o700).create(&private)?;
+
+  let bound = private.join("socket");
+  let listener = UnixListener::bind(&bound).and_then(|listener| {
+    fs::set_permissions(&bound, fs::Permissions::from_mode(mode))?;
+    // Unlike a rename, linking fails instead of replacing an existing file.
+    fs::hard_link(&bound, path).map_err(|err| match err.kind() {
+      io::ErrorKind::AlreadyExists => io::ErrorKind::AddrInUse.into(),
+      _ => err,
+    })?;
+    Ok(listener)
+  });
+  let _ = fs::remove_dir_all(&private);
+
+  listener
+}
+
+fn remove_stale_socket(path: &Path) -> io::Result<()> {
+  let metadata = match fs::symlink_metadata(path) {
+    Ok(metadata) => metadata,
+    Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+    Err(err) => return Err(err),
+  };
+
+  if !metadata.file_type().is_socket() {
+    return Ok(()); // Not ours to delete, let bind report it.
+  }
+
+  match std::os::unix::net::UnixStream::connect(path) {
+    Ok(_) => Err(io::Error::new(
+      io::ErrorKind::AddrInUse,
+      "another process is listening on the socket",
+    )),
+    Err(err) if err.kind() == io::ErrorKind::ConnectionRefused => {
+      fs::remove_file(path)
+    }
+    Err(err) => Err(err),
+  }
+}
+
+pub mod client {
+  use std::{io, path::PathBuf};
+
+  use async_trait::async_trait;
+  use tokio::net::UnixStream;
+
+  use crate::{
+    accept::Io,
+    compression::Compression,
+    transport::{
+      connection::Connect,
+      frame::{RequestFrame, ResponseFrame},
+      tcp::client::call_io,
+      ClientTransport,
+    },
+    ClientError,
+  };
+
+  /// Connects to the socket at `path` for every call.
+  pub struct UnixTransport {
+    path: PathBuf,
+    compression: Compression,
+  }
+
+  impl UnixTransport {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+      Self { path: path.into(), compression: Compression::default() }
+    }
+
+    /// Offers `compression` to the server, see [crate::compression].
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+      self.compression = compression;
+      self
+    }
+  }
+
+  #[async_trait]
+  impl Connect for UnixTransport {
+    async fn connect(&self) -> io::Result<Box<dyn Io>> {
+      Ok(Box::new(UnixStream::connect(&self.path).await?))
+    }
+  }
+
+  #[async_trait]
+  impl ClientTransport for UnixTransport {
+    async fn call(
+      &mut self,
+      request: RequestFrame,
+    ) -> Result<ResponseFrame, ClientError> {
+      let stream = self.connect().await.map_err(ClientError::connecting)?;
+      call_io(stream, request, &self.compression).await
+    }
+  }
+}
@@ -0,0 +1,286 @@
+use std::{future::IntoFuture, io, time::Duration};
+
+use tokio::io::DuplexStream;
+use webcontr::{
+  accept::Accept,
+  context::{self, PeerInfo},
+  prelude::*,
+  serve::ListenerOptions,
+  transport::{duplex, frame::ResponseErrorKind},
+  ClientError, FrozenServer, Server,
+};
+
+#[webcontr::service]
+pub trait Echo {
+  async fn echo(value: String) -> String;
+  async fn is_local() -> bool;
+  async fn sleep(millis: u64);
+  async fn client_certificate() -> Option<Vec<u8>>;
+}
+
+#[derive(Clone)]
+struct EchoServer;
+
+#[webcontr::async_trait]
+impl Echo for EchoServer {
+  async fn echo(&self, value: String) -> String {
+    value
+  }
+
+  async fn is_local(&self) -> bool {
+    context::peer() == Some(PeerInfo::Local)
+  }
+
+  async fn sleep(&self, millis: u64) {
+    tokio::time::sleep(Duration::from_millis(millis)).await
+  }
+
+  async fn client_certificate(&self) -> Option<Vec<u8>> {
+    let context = context::current()?;
+    Some(context.client_identity()?.certificate().to_vec())
+  }
+}
+
+/// Accepts a single, already established connection.
+struct Once(Option<DuplexStream>);
+
+#[webcontr::async_trait]
+impl Accept for Once {
+  type Io = DuplexStream;
+
+  async fn accept(&mut self) -> io::Result<(Self::Io, PeerInfo)> {
+    match self.0.take() {
+      Some(stream) => Ok((stream, PeerInfo::Local)),
+      None => std::future::pending().await,
+    }
+  }
+}
+
+#[tokio::test]
+async fn serve_over_duplex() {
+  let (connector, listener) = duplex::listener(1024);
+  let server = Server::default().add_service(EchoServer.into_serve());
+  tokio::spawn(server.serve_with(listener).into_future());
+
+  let mut client = EchoClient::with_transport(connector);
+  assert_eq!(client.echo("hi".into()).await.unwrap(), "hi");
+  assert!(client.is_local().await.unwrap());
+}
+
+#[tokio::test]
+async fn one_server_many_listeners() {
+  let server: FrozenServer =
+    Server::default().add_service(EchoServer.into_serve()).into();
+
+  let (connector, listener) = duplex::listener(1024);
+  let (client_io, server_io) = tokio::io::duplex(1024);
+  tokio::spawn(server.serve_with(listener).into_future());
+  tokio::spawn(server.serve_with(Once(Some(server_io))).into_future());
+
+  let mut client = EchoClient::with_transport(connector);
+  assert_eq!(client.echo("first".into()).await.unwrap(), "first");
+
+  let mut transport = webcontr::transport::tcp::request_transport(client_io);
+  let request =
+    bincode::serialize(&EchoRequest::echo { value: "second".into() }).unwrap();
+  transport
+    .send(webcontr::transport::frame::RequestFrame::new(
+      "Echo".into(),
+      Bytes::from(request),
+    ))
+    .await
+    .unwrap();
+
+  let mut transport =
+    webcontr::transport::tcp::response_transport(transport.into_inner());
+  let frame = transport.next().await.unwrap().unwrap();
+  assert_eq!(
+    frame,
+    webcontr::transport::frame::ResponseFrame::Payload(Bytes::from(
+      bincode::serialize(&EchoResponse::echo("second".into())).unwrap()
+    ))
+  );
+}
+
+#[tokio::test]
+async fn per_listener_options() {
+  let (fast, fast_listener) = duplex::listener(1024);
+  let (slow, slow_listener) = duplex::listener(1024);
+  let (limited, limited_listener) = duplex::listener(1024);
+
+  let serve = Server::default()
+    .add_service(EchoServer.into_serve())
+    .serve_with(fast_listener)
+    .with_timeout(Duration::from_millis(20))
+    .with_listener(
+      slow_listener,
+      ListenerOptions::new().with_timeout(Duration::from_secs(5)),
+    )
+    .with_listener(
+      limited_listener,
+      ListenerOptions::new().with_max_connections(1),
+    );
+  tokio::spawn(serve.into_future());
+
+  let err = EchoClient::with_transport(fast).sleep(200).await.unwrap_err();
+  assert!(matches!(err, ClientError::ServerError(ResponseErrorKind::Timeout)));
+  EchoClient::with_transport(slow).sleep(50).await.unwrap();
+
+  // Holds the only slot of the limited listener open.
+  let _held = limited.connect().unwrap();
+  let mut client = EchoClient::with_transport(limited);
+  let call =
+    tokio::time::timeout(Duration::from_millis(50), client.echo("".into()));
+  assert!(call.await.is_err());
+}
+
+#[cfg(feature = "tls")]
+#[tokio::test]
+async fn serve_tls_over_duplex() {
+  use std::sync::Arc;
+  use tokio_rustls::{
+    rustls::{
+      pki_types::{pem::PemObject, CertificateDer, ServerName},
+      ClientConfig, RootCertStore,
+    },
+    TlsConnector,
+  };
+  use webcontr::{
+    tls::{TLSPaths, TlsListener},
+    transport::{
+      frame::{RequestFrame, ResponseFrame},
+      tcp::{request_transport, response_transport},
+    },
+  };
+
+  let (connector, listener) = duplex::listener(4096);
+  let listener = TlsListener::from_paths(
+    listener,
+    TLSPaths::from_paths("tests/certs/chain.pem", "tests/certs/end.key"),
+  )
+  .unwrap();
+  let server = Server::default().add_service(EchoServer.into_serve());
+  tokio::spawn(server.serve_with(listener).into_future());
+
+  // A peer that never finishes its handshake must not block others.
+  let _stalled = connector.connect().unwrap();
+
+  let mut roots = RootCertStore::empty();
+  for cert in CertificateDer::pem_file_iter("tests/certs/root.pem").unwrap() {
+    roots.add(cert.unwrap()).unwrap();
+  }
+  let config =
+    ClientConfig::builder().with_root_certificates(roots).with_no_client_auth();
+  let stream = TlsConnector::from(Arc::new(config))
+    .connect(
+      ServerName::try_from("localhost").unwrap(),
+      connector.connect().unwrap(),
+    )
+    .await
+    .unwrap();
+
+  let request =
+    bincode::serialize(&EchoRequest::echo { value: "secret".into() }).unwrap();
+  let mut transport = request_transport(stream);
+  transport
+    .send(RequestFrame::new("Echo".into(), Bytes::from(request)))
+    .await
+    .unwrap();
+
+  let mut transport = response_transport(transport.into_inner());
+  let ResponseFrame::Payload(payload) =
+    transport.next().await.unwrap().unwrap()
+  else {
+    panic!("expected a payload");
+  };
+  let EchoResponse::echo(value) = bincode::deserialize(&payload).unwrap()
+  else {
+    panic!("expected an echo response");
+  };
+  assert_eq!(value, "secret");
+}
+
+#[cfg(feature = "tls")]
+#[tokio::test]
+async fn tls_client_certificates_reach_handlers() {
+  use std::sync::Arc;
+  use tokio_rustls::{
+    rustls::{
+      pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer, ServerName},
+      ClientConfig, RootCertStore,
+    },
+    TlsConnector,
+  };
+  use webcontr::{
+    tls::{TLSPaths, TlsListener},
+    transport::{
+      frame::{RequestFrame, ResponseFrame},
+      tcp::{request_transport, response_transport},
+    },
+  };
+
+  let (connector, listener) = duplex::listener(4096);
+  let listener = TlsListener::from_paths(
+    listener,
+    TLSPaths::from_paths("tests/certs/chain.pem", "tests/certs/end.key")
+      .with_client_ca("tests/certs/root.pem"),
+  )
+  .unwrap();
+  let server = Server::default().add_service(EchoServer.into_serve());
+  tokio::spawn(server.serve_with(listener).into_future());
+
+  let mut roots = RootCertStore::empty();
+  for cert in CertificateDer::pem_file_iter("tests/certs/root.pem").unwrap() {
+    roots.add(cert.unwrap()).unwrap();
+  }
+  // The server's certificate is good for client auth too.
+  let chain = CertificateDer::pem_file_iter("tests/certs/chain.pem")
+    .unwrap()
+    .collect::<Result<Vec<_>, _>>()
+    .unwrap();
+  let key = PrivateKeyDer::from_pem_file("tests/certs/end.key").unwrap();
+  let config = ClientConfig::builder()
+    .with_root_certificates(roots.clone())
+    .with_client_auth_cert(chain.clone(), key)
+    .unwrap();
+  let connect = |config: ClientConfig| {
+    TlsConnector::from(Arc::new(config)).connect(
+      ServerName::try_from("localhost").unwrap(),
+      connector.connect().unwrap(),
+    )
+  };
+  let stream = connect(config).await.unwrap();
+
+  let request =
+    bincode::serialize(&EchoRequest::client_certificate {}).unwrap();
+  let mut transport = request_transport(stream);
+  transport
+    .send(RequestFrame::new("Echo".into(), Bytes::from(request)))
+    .await
+    .unwrap();
+
+  let mut transport = response_transport(transport.into_inner());
+  let ResponseFrame::Payload(payload) =
+    transport.next().await.unwrap().unwrap()
+  else {
+    panic!("expected a payload");
+  };
+  let EchoResponse::client_certificate(certificate) =
+    bincode::deserialize(&payload).unwrap()
+  else {
+    panic!("expected a client_certificate response");
+  };
+  assert_eq!(certificate.as_deref(), Some(chain[0].as_ref()));
+
+  // Clients without a certificate are turned away.
+  let config =
+    ClientConfig::builder().with_root_certificates(roots).with_no_client_auth();
+  let stream = connect(config).await.unwrap();
+  let mut transport = request_transport(stream);
+  let request = bincode::serialize(&EchoRequest::is_local {}).unwrap();
+  let _ = transport
+    .send(RequestFrame::new("Echo".into(), Bytes::from(request)))
+    .await;
+  let mut transport = response_transport(transport.into_inner());
+  assert!(!matches!(transport.next().await, Some(Ok(_))));
+}
@@ -0,0 +1,224 @@
+mod common;
+
+use std::{
+  sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+  },
+  time::Duration,
+};
+
+use webcontr::{
+  auth::{ApiKeys, Authenticate, Credentials, Principal, RefreshingToken},
+  status::{Code, Status},
+  transport::{duplex::DuplexConnector, frame::ResponseErrorKind},
+  ClientError,
+};
+
+use common::TestClient;
+
+fn serve(authenticator: impl Authenticate + 'static) -> DuplexConnector {
+  let served = common::serve()
+    .with_server(|server| server.with_authenticator(authenticator))
+    .spawn();
+  served.connector
+}
+
+fn unauthenticated(err: &ClientError) -> bool {
+  matches!(
+    err,
+    ClientError::ServerError(ResponseErrorKind::Status(status))
+      if status.code() == Code::Unauthenticated
+  )
+}
+
+fn api_keys() -> ApiKeys {
+  ApiKeys::new()
+    .with_key("s3cr3t", Principal::new("backup").with_claim("role", "reader"))
+}
+
+#[tokio::test]
+async fn handlers_see_the_principal() {
+  let connector = serve(api_keys());
+  let mut client = TestClient::with_transport(connector)
+    .with_credentials(Credentials::ApiKey("s3cr3t".into()));
+
+  assert_eq!(client.whoami().await.unwrap(), "backup (reader)");
+}
+
+#[tokio::test]
+async fn requests_without_valid_credentials_are_rejected() {
+  let connector = serve(api_keys());
+
+  let mut client = TestClient::with_transport(connector.clone());
+  let err = client.whoami().await.unwrap_err();
+  assert!(unauthenticated(&err), "{err:?}");
+
+  let mut client = TestClient::with_transport(connector.clone())
+    .with_credentials(Credentials::ApiKey("guess".into()));
+  assert!(unauthenticated(&client.whoami().await.unwrap_err()));
+
+  let mut client = TestClient::with_transport(connector)
+    .with_credentials(Credentials::Bearer("s3cr3t".into()));
+  assert!(unauthenticated(&client.whoami().await.unwrap_err()));
+}
+
+#[tokio::test]
+async fn health_is_public_by_default() {
+  use webcontr::{
+    health::{self, HealthClient, ServingStatus},
+    reflection::ReflectionClient,
+  };
+
+  let serve = |public: Option<&'static [&'static str]>| {
+    let served = common::serve()
+      .with_server(move |server| {
+        let server = server
+          .add_service(health::reporter().1)
+          .with_reflection()
+          .with_authenticator(api_keys());
+        match public {
+          Some(public) => server.with_public_services(public.iter().copied()),
+          None => server,
+        }
+      })
+      .spawn();
+    served.connector
+  };
+
+  // Probes don't need credentials, but everything else does.
+  let connector = serve(None);
+  let mut health = HealthClient::with_transport(connector.clone());
+  assert_eq!(health.check("".into()).await.unwrap(), ServingStatus::Serving);
+  let mut reflection = ReflectionClient::with_transport(connector.clone());
+  assert!(unauthenticated(&reflection.list_services().await.unwrap_err()));
+  let mut client = TestClient::with_transport(connector);
+  assert!(unauthenticated(&client.whoami().await.unwrap_err()));
+
+  let connector = serve(Some(&["Reflection"]));
+  let mut reflection = ReflectionClient::with_transport(connector.clone());
+  assert_eq!(reflection.list_services().await.unwrap().len(), 3);
+  let mut health = HealthClient::with_transport(connector);
+  assert!(unauthenticated(&health.check("".into()).await.unwrap_err()));
+}
+
+/// Accepts only the bearer token `fresh`.
+struct Fresh;
+
+#[webcontr::async_trait]
+impl Authenticate for Fresh {
+  async fn authenticate(
+    &self,
+    credentials: &Credentials,
+  ) -> Result<Principal, Status> {
+    match credentials {
+      Credentials::Bearer(token) if token == "fresh" => {
+        Ok(Principal::new("service"))
+      }
+      _ => Err(Status::new(Code::Unauthenticated).with_message("revoked")),
+    }
+  }
+}
+
+#[tokio::test]
+async fn tokens_are_cached_and_refreshed_when_rejected() {
+  let fetches = Arc::new(AtomicUsize::new(0));
+  let fetched = fetches.clone();
+  let token = RefreshingToken::new(move || {
+    let token = match fetched.fetch_add(1, Ordering::SeqCst) {
+      0 => "stale",
+      _ => "fresh",
+    };
+    async move { Ok((token.to_string(), Duration::from_secs(60))) }
+  });
+  let mut client =
+    TestClient::with_transport(serve(Fresh)).with_credentials(token);
+
+  assert_eq!(client.whoami().await.unwrap(), "service");
+  assert_eq!(client.whoami().await.unwrap(), "service");
+  assert_eq!(fetches.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn tokens_are_refreshed_before_they_expire() {
+  let fetches = Arc::new(AtomicUsize::new(0));
+  let fetched = fetches.clone();
+  let token = RefreshingToken::new(move || {
+    fetched.fetch_add(1, Ordering::SeqCst);
+    async { Ok(("fresh".to_string(), Duration::from_millis(50))) }
+  });
+  let mut client =
+    TestClient::with_transport(serve(Fresh)).with_credentials(token);
+
+  client.whoami().await.unwrap();
+  client.whoami().await.unwrap();
+  assert_eq!(fetches.load(Ordering::SeqCst), 1);
+
+  tokio::time::sleep(Duration::from_millis(50)).await;
+  client.whoami().await.unwrap();
+  assert_eq!(fetches.load(Ordering::SeqCst), 2);
+}
+
+#[cfg(feature = "jwt")]
+mod jwt {
+  use std::time::{SystemTime, UNIX_EPOCH};
+
+  use jsonwebtoken::{Algorithm, EncodingKey, Header};
+  use serde_json::{json, Value};
+  use webcontr::auth::jwt::JwtVerifier;
+
+  use super::*;
+
+  const SECRET: &[u8] = b"webcontr-test-secret-0123456789ab";
+
+  fn verifier() -> JwtVerifier {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/certs/jwks.json");
+    JwtVerifier::from_jwks_file(path).unwrap().with_issuer("https://issuer")
+  }
+
+  fn sign(claims: Value) -> Credentials {
+    let mut header = Header::new(Algorithm::HS256);
+    header.kid = Some("test".into());
+    let key = EncodingKey::from_secret(SECRET);
+    Credentials::Bearer(jsonwebtoken::encode(&header, &claims, &key).unwrap())
+  }
+
+  fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+  }
+
+  #[tokio::test]
+  async fn valid_tokens_are_accepted() {
+    let token = sign(json!({
+      "sub": "alice",
+      "iss": "https://issuer",
+      "exp": now() + 60,
+      "role": "admin",
+    }));
+    let mut client =
+      TestClient::with_transport(serve(verifier())).with_credentials(token);
+
+    assert_eq!(client.whoami().await.unwrap(), "alice (admin)");
+  }
+
+  #[tokio::test]
+  async fn invalid_tokens_are_rejected() {
+    let connector = serve(verifier());
+    let tokens = [
+      // Expired.
+      sign(json!({ "sub": "alice", "iss": "https://issuer", "exp": 1 })),
+      // Someone else's.
+      sign(
+        json!({ "sub": "alice", "iss": "https://other", "exp": now() + 60 }),
+      ),
+      Credentials::Bearer("not a jwt".into()),
+    ];
+
+    for token in tokens {
+      let mut client =
+        TestClient::with_transport(connector.clone()).with_credentials(token);
+      let err = client.whoami().await.unwrap_err();
+      assert!(unauthenticated(&err), "{err:?}");
+    }
+  }
+}
@@ -0,0 +1,170 @@
+mod common;
+
+use std::{
+  future::IntoFuture,
+  sync::{Arc, Mutex},
+};
+
+use common::{Test, TestRequest, TestServer};
+use webcontr::{
+  prelude::*,
+  serve::{Overload, ServeError},
+  transport::{
+    channel,
+    frame::{RequestFrame, ResponseErrorKind, ResponseFrame},
+  },
+  ClientError, Server,
+};
+
+#[webcontr::service]
+pub trait Greeter {
+  async fn greet(name: String) -> String;
+}
+
+#[derive(Clone)]
+struct GreeterServer;
+
+#[webcontr::async_trait]
+impl Greeter for GreeterServer {
+  async fn greet(&self, name: String) -> String {
+    format!("hello {name}")
+  }
+}
+
+#[tokio::test]
+async fn client_over_channel() {
+  let (client, server) = channel::unbounded();
+  let server = Server::default()
+    .add_service(GreeterServer.into_serve())
+    .serve_channel(server);
+  let handle = tokio::spawn(server.into_future());
+
+  let mut client = GreeterClient::with_transport(client);
+  assert_eq!(client.greet("world".into()).await.unwrap(), "hello world");
+  assert_eq!(client.greet("again".into()).await.unwrap(), "hello again");
+
+  drop(client);
+  handle.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn unknown_service_over_channel() {
+  let (mut client, server) = channel::unbounded();
+  let server = Server::default()
+    .add_service(GreeterServer.into_serve())
+    .serve_channel(server);
+  tokio::spawn(server.into_future());
+
+  client.send(RequestFrame::new("Missing".into(), Bytes::new())).await.unwrap();
+  let frame: ResponseFrame = client.next().await.unwrap().unwrap();
+  assert_eq!(frame, ResponseFrame::Error(ResponseErrorKind::MethodNotFound));
+
+  let err = webcontr::transport::send_request::<_, GreeterResponse>(
+    &mut client,
+    "Greeter",
+    "greet",
+    (),
+    Default::default(),
+  )
+  .await
+  .unwrap_err();
+  assert!(matches!(
+    err,
+    ClientError::ServerError(ResponseErrorKind::InvalidRequest)
+  ));
+}
+
+#[tokio::test]
+async fn generated_serve_impl() {
+  let service = GreeterServer.into_serve();
+  let req = bincode::serialize(&GreeterRequest::greet { name: "serve".into() })
+    .unwrap();
+
+  let res = Serve::serve(&service, Bytes::from(req)).await.unwrap();
+  let GreeterResponse::greet(out) = bincode::deserialize(&res).unwrap();
+  assert_eq!(out, "hello serve");
+}
+
+#[tokio::test]
+async fn client_over_bounded_channel() {
+  let (client, server) = channel::bounded(1);
+  let server = Server::default()
+    .add_service(GreeterServer.into_serve())
+    .serve_channel(server);
+  let handle = tokio::spawn(server.into_future());
+
+  let mut client = GreeterClient::with_transport(client);
+  for name in ["a", "b", "c"] {
+    let res = client.greet(name.into()).await.unwrap();
+    assert_eq!(res, format!("hello {name}"));
+  }
+
+  drop(client);
+  handle.await.unwrap().unwrap();
+}
+
+fn request(request: TestRequest) -> RequestFrame {
+  let arguments = bincode::serialize(&request).unwrap();
+  RequestFrame::new("Test".into(), arguments.into())
+}
+
+#[tokio::test]
+async fn slow_requests_dont_hold_up_the_channel() {
+  let (mut client, server) = channel::unbounded();
+  let server = Server::default()
+    .add_service(TestServer::default().into_serve())
+    .serve_channel(server);
+  tokio::spawn(server.into_future());
+
+  // Only returns once the second request was handled.
+  client.send(request(TestRequest::wait {})).await.unwrap();
+  client.send(request(TestRequest::release {})).await.unwrap();
+  for _ in 0..2 {
+    let frame: ResponseFrame = client.next().await.unwrap().unwrap();
+    assert!(matches!(frame, ResponseFrame::Payload(_)), "{frame:?}");
+  }
+}
+
+#[tokio::test]
+async fn channel_requests_are_limited() {
+  let (mut client, server) = channel::unbounded();
+  let server = Server::default()
+    .add_service(TestServer::default().into_serve())
+    .serve_channel(server)
+    .with_max_in_flight(1)
+    .with_overload(Overload::Reject);
+  tokio::spawn(server.into_future());
+
+  client.send(request(TestRequest::slow { millis: 50 })).await.unwrap();
+  client.send(request(TestRequest::ping {})).await.unwrap();
+
+  let frame: ResponseFrame = client.next().await.unwrap().unwrap();
+  assert!(matches!(frame, ResponseFrame::Payload(_)), "{frame:?}");
+  let frame: ResponseFrame = client.next().await.unwrap().unwrap();
+  assert_eq!(frame, ResponseFrame::Error(ResponseErrorKind::Overloaded));
+}
+
+#[tokio::test]
+async fn channel_errors_are_reported() {
+  let service = TestServer::default();
+  let errors = Arc::new(Mutex::new(Vec::new()));
+  let hook_errors = errors.clone();
+  let (mut client, server) = channel::unbounded();
+  let server = Server::default()
+    .add_service(service.clone().into_serve())
+    .serve_channel(server)
+    .on_error(move |err: &ServeError| {
+      hook_errors.lock().unwrap().push(err.to_string())
+    });
+  let handle = tokio::spawn(server.into_future());
+
+  // Hangs up before the answer.
+  client.send(request(TestRequest::wait {})).await.unwrap();
+  drop(client);
+  service.release().await;
+
+  assert!(handle.await.unwrap().is_err());
+  let errors = errors.lock().unwrap();
+  assert_eq!(errors.len(), 1);
+  assert!(errors[0].starts_with("failed to send response"), "{errors:?}");
+}
@@ -0,0 +1,162 @@
+use std::{io, time::Duration};
+
+use bytes::Bytes;
+use futures_util::{SinkExt, StreamExt};
+use tokio::{
+  io::{AsyncReadExt, AsyncWriteExt},
+  net::TcpListener,
+};
+use tokio_util::codec::{FramedRead, FramedWrite};
+use webcontr::{
+  accept::Io,
+  prelude::*,
+  transport::{
+    connection::{Backoff, Connect, Connection, OnDisconnect},
+    frame::{RequestFrame, ResponseErrorKind, ResponseFrame},
+    preamble::{Features, PreambleCodec, ServerHello, ServerHelloCodec},
+    ClientTransport,
+  },
+  ClientError,
+};
+
+#[webcontr::service]
+pub trait Info {
+  async fn name() -> String;
+  async fn version() -> u32;
+}
+
+/// Answers every call with `payload`.
+struct Answering(Vec<u8>);
+
+#[webcontr::async_trait]
+impl ClientTransport for Answering {
+  async fn call(
+    &mut self,
+    _: RequestFrame,
+  ) -> Result<ResponseFrame, ClientError> {
+    Ok(ResponseFrame::Payload(Bytes::from(self.0.clone())))
+  }
+}
+
+#[tokio::test]
+async fn refused_connections_are_connect_errors() {
+  let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+  let addr = listener.local_addr().unwrap();
+  drop(listener);
+
+  let mut client = InfoClient::new(addr.to_string());
+  let err = client.name().await.unwrap_err();
+  let ClientError::ConnectError(io) = &err else {
+    panic!("unexpected {err:?}");
+  };
+  assert_eq!(io.kind(), io::ErrorKind::ConnectionRefused);
+  assert!(err.is_retryable());
+}
+
+#[tokio::test]
+async fn responses_of_other_methods_are_rejected() {
+  let response = bincode::serialize(&InfoResponse::version(2)).unwrap();
+  let mut client = InfoClient::with_transport(Answering(response));
+
+  let err = client.name().await.unwrap_err();
+  assert!(matches!(err, ClientError::MismatchedResponse("name")), "{err:?}");
+  assert!(!err.is_retryable());
+}
+
+#[tokio::test]
+async fn undecodable_responses_are_decoding_errors() {
+  let mut client = InfoClient::with_transport(Answering(vec![0xff; 3]));
+
+  let err = client.version().await.unwrap_err();
+  assert!(matches!(err, ClientError::DecodingError(_)), "{err:?}");
+  assert!(!err.is_retryable());
+}
+
+/// Agrees on version 2, then answers with a frame of an unknown type.
+struct Garbled;
+
+#[webcontr::async_trait]
+impl Connect for Garbled {
+  async fn connect(&self) -> io::Result<Box<dyn Io>> {
+    let (client, mut server) = tokio::io::duplex(1024);
+    tokio::spawn(async move {
+      let mut transport = FramedRead::new(&mut server, PreambleCodec);
+      transport.next().await.unwrap().unwrap();
+      let hello = ServerHello { version: 2, features: Features::empty() };
+      FramedWrite::new(&mut server, ServerHelloCodec)
+        .send(hello)
+        .await
+        .unwrap();
+      // Once the request arrived.
+      server.read_u8().await.unwrap();
+      server.write_all(&[9, 0, 0, 0, 1]).await.unwrap();
+      let _ = tokio::io::copy(&mut server, &mut tokio::io::sink()).await;
+    });
+    Ok(Box::new(client))
+  }
+}
+
+#[tokio::test]
+async fn unknown_frames_are_protocol_errors() {
+  let connection = Connection::new(Garbled)
+    .with_backoff(Backoff::new(
+      Duration::from_secs(60),
+      Duration::from_secs(60),
+    ))
+    .with_on_disconnect(OnDisconnect::Fail);
+  let mut client = InfoClient::with_transport(connection);
+
+  let err = client.version().await.unwrap_err();
+  assert!(matches!(err, ClientError::ProtocolError(_)), "{err:?}");
+  assert!(!err.is_retryable());
+}
+
+#[test]
+fn server_errors_tell_whether_to_retry() {
+  let overloaded = ClientError::ServerError(ResponseErrorKind::Overloaded);
+  assert!(overloaded.is_retryable());
+  let invalid = ClientError::ServerError(ResponseErrorKind::InvalidRequest);
+  assert!(!invalid.is_retryable());
+
+  let timeout = ClientError::ServerError(ResponseErrorKind::Timeout);
+  assert!(timeout.is_timeout() && timeout.is_retryable());
+  assert!(ClientError::Timeout.is_timeout());
+  assert!(!invalid.is_timeout());
+}
+
+#[cfg(feature = "tls")]
+#[tokio::test]
+async fn handshakes_with_plain_servers_are_tls_errors() {
+  use std::sync::Arc;
+
+  use webcontr::transport::tcp::client::TcpTransport;
+
+  let listener = TcpListener::bind("localhost:0").await.unwrap();
+  let addr = listener.local_addr().unwrap();
+  tokio::spawn(async move {
+    let (mut stream, _) = listener.accept().await.unwrap();
+    stream.write_all(b"HTTP/1.1 400 Bad Request\r\n\r\n").await.unwrap();
+  });
+
+  let config = webcontr::tls::clientconfig_from_pem("tests/certs/root.pem");
+  let transport = TcpTransport::new(format!("localhost:{}", addr.port()))
+    .with_tls_config(Arc::new(config.unwrap()));
+  let mut client = InfoClient::with_transport(transport);
+  let err = client.name().await.unwrap_err();
+  assert!(matches!(err, ClientError::TlsError(_)), "{err:?}");
+  assert!(!err.is_retryable());
+}
+
+#[cfg(feature = "tls")]
+#[tokio::test]
+async fn tls_needs_root_certificates() {
+  let listener = TcpListener::bind("localhost:0").await.unwrap();
+  let addr = listener.local_addr().unwrap();
+
+  let mut client = InfoClient::new(format!("localhost:{}", addr.port()));
+  let err = client.name().await.unwrap_err();
+  let ClientError::TlsError(err) = &err else {
+    panic!("unexpected {err:?}");
+  };
+  assert!(err.to_string().contains("no root certificates"), "{err}");
+}
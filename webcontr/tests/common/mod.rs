@@ -0,0 +1,224 @@
+//! A toy service and the fixtures the integration tests serve it with.
+
+// Every test file uses only some of it.
+#![allow(dead_code)]
+
+use std::{
+  future::IntoFuture,
+  io,
+  sync::{Arc, Mutex},
+  time::Duration,
+};
+
+use futures_util::{SinkExt, StreamExt};
+use tokio::{
+  io::DuplexStream,
+  sync::{mpsc, Notify, Semaphore},
+  task::JoinHandle,
+};
+use tokio_util::codec::{FramedRead, FramedWrite};
+use webcontr::{
+  context,
+  prelude::*,
+  serve::{ServeError, ServerServe},
+  transport::{
+    duplex::{self, DuplexConnector},
+    // Used by the code `webcontr::service` generates.
+    frame::ResponseErrorKind,
+    preamble::{
+      ClientHello, Features, PreambleCodec, ServerHello, ServerHelloCodec,
+    },
+  },
+  Server,
+};
+
+#[webcontr::service]
+pub trait Test {
+  async fn ping() -> bool;
+  async fn echo(value: String) -> String;
+  async fn slow(millis: u64) -> bool;
+  /// Returns once [Test::release] or [Served::release] lets it.
+  async fn wait() -> bool;
+  async fn release() -> bool;
+  /// `len` bytes, e.g. more than fit in one frame.
+  async fn fill(len: u32) -> Vec<u8>;
+  /// The subject of the authenticated principal, with its role if it has
+  /// one.
+  async fn whoami() -> String;
+}
+
+#[derive(Clone)]
+pub struct TestServer {
+  entered: mpsc::UnboundedSender<()>,
+  gate: Arc<Semaphore>,
+}
+
+impl Default for TestServer {
+  /// Without [Served] to wait for or release calls to [Test::wait].
+  fn default() -> Self {
+    let (entered, _) = mpsc::unbounded_channel();
+    Self { entered, gate: Arc::new(Semaphore::new(0)) }
+  }
+}
+
+#[webcontr::async_trait]
+impl Test for TestServer {
+  async fn ping(&self) -> bool {
+    true
+  }
+
+  async fn echo(&self, value: String) -> String {
+    value
+  }
+
+  async fn slow(&self, millis: u64) -> bool {
+    tokio::time::sleep(Duration::from_millis(millis)).await;
+    true
+  }
+
+  async fn wait(&self) -> bool {
+    let _ = self.entered.send(());
+    self.gate.acquire().await.unwrap().forget();
+    true
+  }
+
+  async fn release(&self) -> bool {
+    self.gate.add_permits(1);
+    true
+  }
+
+  async fn fill(&self, len: u32) -> Vec<u8> {
+    vec![0; len as usize]
+  }
+
+  async fn whoami(&self) -> String {
+    let principal = context::principal().unwrap();
+    match principal.claim("role") {
+      Some(role) => format!("{} ({role})", principal.subject()),
+      None => principal.subject().to_string(),
+    }
+  }
+}
+
+type Errors = Arc<Mutex<Vec<String>>>;
+
+type Configure<T> = Box<dyn FnOnce(T) -> T>;
+
+/// Serves [TestServer] over an in-memory listener, see [serve].
+pub struct Serve {
+  max_buf_size: usize,
+  server: Configure<Server>,
+  serve: Configure<ServerServe>,
+}
+
+/// Starts building a server, with a buffer of 1024 bytes per connection.
+pub fn serve() -> Serve {
+  Serve {
+    max_buf_size: 1024,
+    server: Box::new(|server| server),
+    serve: Box::new(|serve| serve),
+  }
+}
+
+impl Serve {
+  pub fn with_buf_size(mut self, max_buf_size: usize) -> Self {
+    self.max_buf_size = max_buf_size;
+    self
+  }
+
+  /// Changes the [Server] after [TestServer] was added to it.
+  pub fn with_server(
+    mut self,
+    configure: impl FnOnce(Server) -> Server + 'static,
+  ) -> Self {
+    let previous = self.server;
+    self.server = Box::new(move |server| configure(previous(server)));
+    self
+  }
+
+  /// Changes how the server is served, e.g. its limits and timeouts.
+  pub fn with_serve(
+    mut self,
+    configure: impl FnOnce(ServerServe) -> ServerServe + 'static,
+  ) -> Self {
+    let previous = self.serve;
+    self.serve = Box::new(move |serve| configure(previous(serve)));
+    self
+  }
+
+  pub fn spawn(self) -> Served {
+    let (entered_tx, entered) = mpsc::unbounded_channel();
+    let gate = Arc::new(Semaphore::new(0));
+    let service = TestServer { entered: entered_tx, gate: gate.clone() };
+
+    let errors = Errors::default();
+    let hook_errors = errors.clone();
+    let shutdown = Arc::new(Notify::new());
+    let signal = shutdown.clone();
+
+    let (connector, listener) = duplex::listener(self.max_buf_size);
+    let server =
+      (self.server)(Server::default().add_service(service.into_serve()));
+    let serve = server
+      .serve_with(listener)
+      .on_error(move |err: &ServeError| {
+        hook_errors.lock().unwrap().push(err.to_string())
+      })
+      .with_shutdown(async move { signal.notified().await });
+    let server = tokio::spawn((self.serve)(serve).into_future());
+
+    Served { connector, errors, entered, gate, shutdown, server }
+  }
+}
+
+/// A running server, it keeps running when this is dropped.
+pub struct Served {
+  pub connector: DuplexConnector,
+  errors: Errors,
+  entered: mpsc::UnboundedReceiver<()>,
+  gate: Arc<Semaphore>,
+  shutdown: Arc<Notify>,
+  pub server: JoinHandle<io::Result<()>>,
+}
+
+impl Served {
+  pub fn client(&self) -> TestClient {
+    TestClient::with_transport(self.connector.clone())
+  }
+
+  /// Waits until a call to [Test::wait] is being handled.
+  pub async fn entered(&mut self) {
+    self.entered.recv().await.unwrap();
+  }
+
+  /// Lets `calls` calls to [Test::wait] return.
+  pub fn release(&self, calls: usize) {
+    self.gate.add_permits(calls);
+  }
+
+  pub fn shutdown(&self) {
+    self.shutdown.notify_one();
+  }
+
+  /// Everything reported through [ServerServe::on_error] so far.
+  pub fn errors(&self) -> Vec<String> {
+    self.errors.lock().unwrap().clone()
+  }
+}
+
+/// Sends `hello` over `stream` and reads the server's answer.
+pub async fn hello(
+  stream: &mut DuplexStream,
+  hello: ClientHello,
+) -> ServerHello {
+  FramedWrite::new(&mut *stream, PreambleCodec).send(hello).await.unwrap();
+  FramedRead::new(stream, ServerHelloCodec).next().await.unwrap().unwrap()
+}
+
+/// Plays a server that reads the client's hello and agrees on version 2.
+pub async fn answer_hello(stream: &mut DuplexStream) {
+  let mut transport = FramedRead::new(&mut *stream, PreambleCodec);
+  transport.next().await.unwrap().unwrap();
+  let hello = ServerHello { version: 2, features: Features::empty() };
+  FramedWrite::new(stream, ServerHelloCodec).send(hello).await.unwrap();
+}
@@ -0,0 +1,89 @@
+#![cfg(all(feature = "zstd", feature = "lz4", feature = "gzip"))]
+
+mod common;
+
+use bytes::{BufMut, Bytes, BytesMut};
+use futures_util::{SinkExt, StreamExt};
+use tokio::io::DuplexStream;
+use webcontr::{
+  compression::{Algorithm, Compression},
+  transport::{
+    frame::{RequestFrame, ResponseFrame},
+    preamble::{ClientHello, Features},
+    tcp,
+  },
+};
+
+use common::{Served, TestClient, TestRequest};
+
+fn serve(compression: Compression) -> Served {
+  common::serve()
+    .with_buf_size(64 * 1024)
+    .with_serve(|serve| serve.with_compression(compression))
+    .spawn()
+}
+
+fn all() -> Compression {
+  Compression::new([Algorithm::Zstd, Algorithm::Lz4, Algorithm::Gzip])
+}
+
+#[tokio::test]
+async fn calls_roundtrip_with_each_algorithm() {
+  let served = serve(all());
+  let value = "webcontr ".repeat(500);
+
+  for algorithm in [Algorithm::Zstd, Algorithm::Lz4, Algorithm::Gzip] {
+    let connector =
+      served.connector.clone().with_compression(Compression::new([algorithm]));
+    let mut client = TestClient::with_transport(connector);
+    assert_eq!(client.echo(value.clone()).await.unwrap(), value);
+  }
+}
+
+#[tokio::test]
+async fn peers_without_compression_still_talk() {
+  let value = "webcontr ".repeat(500);
+
+  let mut client = serve(all()).client();
+  assert_eq!(client.echo(value.clone()).await.unwrap(), value);
+
+  let connector =
+    serve(Compression::default()).connector.with_compression(all());
+  let mut client = TestClient::with_transport(connector);
+  assert_eq!(client.echo(value.clone()).await.unwrap(), value);
+}
+
+/// Sends `value` to `echo`, uncompressed but with a flag, and returns the
+/// raw response payload.
+async fn echo_raw(stream: &mut DuplexStream, value: &str) -> Bytes {
+  let mut arguments = BytesMut::new();
+  arguments.put_u8(0);
+  arguments.put_slice(
+    &bincode::serialize(&TestRequest::echo { value: value.to_string() })
+      .unwrap(),
+  );
+  let request = RequestFrame::new("Test".into(), arguments.freeze());
+
+  let mut transport = tcp::request_transport(stream);
+  transport.send(request).await.unwrap();
+  let mut transport = tcp::response_transport(transport.into_inner());
+  match transport.next().await.unwrap().unwrap() {
+    ResponseFrame::Payload(payload) => payload,
+    frame => panic!("unexpected {frame:?}"),
+  }
+}
+
+#[tokio::test]
+async fn small_payloads_are_not_compressed() {
+  let served = serve(all().with_threshold(512));
+  let mut stream = served.connector.connect().unwrap();
+
+  // Version 1, `echo_raw` doesn't frame requests as later versions do.
+  let hello =
+    ClientHello { min_version: 1, max_version: 1, features: Features::ZSTD };
+  assert_eq!(common::hello(&mut stream, hello).await.features, Features::ZSTD);
+
+  assert_eq!(echo_raw(&mut stream, "small").await[0], 0);
+  // The zstd flag.
+  assert_eq!(echo_raw(&mut stream, &"large ".repeat(200)).await[0], 1);
+}
@@ -0,0 +1,259 @@
+mod common;
+
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures_util::{SinkExt, StreamExt};
+use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+use tokio_util::codec::Framed;
+use webcontr::{
+  accept::Accept,
+  serve::ServerServe,
+  status::Code,
+  transport::{
+    connection::Connection,
+    duplex,
+    frame::{Frame, FrameCodec, RequestFrame, ResponseFrame},
+    preamble::{ClientHello, Features, ServerHello, MAX_VERSION},
+    tcp,
+  },
+  ClientError,
+};
+
+use common::{Served, TestClient};
+
+fn serve(
+  configure: impl FnOnce(ServerServe) -> ServerServe + 'static,
+) -> Served {
+  common::serve().with_serve(configure).spawn()
+}
+
+/// Pings over `stream` without closing it afterwards. Doesn't send a
+/// preamble, like clients from before it existed.
+async fn ping(stream: &mut DuplexStream) -> ResponseFrame {
+  let mut transport = tcp::request_transport(stream);
+  transport.send(ping_request()).await.unwrap();
+  let mut transport = tcp::response_transport(transport.into_inner());
+  transport.next().await.unwrap().unwrap()
+}
+
+fn ping_request() -> RequestFrame {
+  // `ping` is the first variant of the request enum, without fields.
+  RequestFrame::new("Test".into(), Bytes::from_static(&[0; 4]))
+}
+
+/// Waits until the server closes `stream`.
+async fn closed(stream: &mut DuplexStream) {
+  let mut buf = Vec::new();
+  let read =
+    tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut buf));
+  assert_eq!(read.await.unwrap().unwrap(), 0);
+}
+
+#[tokio::test]
+async fn serves_several_requests_per_connection() {
+  let served = serve(|serve| serve);
+
+  let mut stream = served.connector.connect().unwrap();
+  for _ in 0..3 {
+    assert!(matches!(ping(&mut stream).await, ResponseFrame::Payload(_)));
+  }
+  drop(stream);
+
+  assert!(served.errors().is_empty());
+}
+
+#[tokio::test]
+async fn serves_clients_from_before_the_preamble() {
+  let served = serve(|serve| serve);
+
+  // Written out by hand, as a client from before the preamble and request
+  // metadata would: command length, command, payload length, payload.
+  let mut stream = served.connector.connect().unwrap();
+  let request = [0, 4, b'T', b'e', b's', b't', 0, 4, 0, 0, 0, 0];
+  stream.write_all(&request).await.unwrap();
+
+  // A payload of the first response variant, `true`.
+  let mut response = [0; 8];
+  stream.read_exact(&mut response).await.unwrap();
+  assert_eq!(response, [0, 0, 5, 0, 0, 0, 0, 1]);
+  drop(stream);
+
+  assert!(served.errors().is_empty());
+}
+
+#[tokio::test]
+async fn calls_servers_from_before_the_preamble() {
+  let (connector, mut listener) = duplex::listener(1024);
+  tokio::spawn(async move {
+    // Reads the command length first, as a server from before the preamble
+    // would, so a preamble would never make a whole request.
+    let (mut stream, _) = listener.accept().await.unwrap();
+    let mut request = [0; 12];
+    stream.read_exact(&mut request).await.unwrap();
+    assert_eq!(request, [0, 4, b'T', b'e', b's', b't', 0, 4, 0, 0, 0, 0]);
+    stream.write_all(&[0, 0, 5, 0, 0, 0, 0, 1]).await.unwrap();
+  });
+
+  let mut client = TestClient::with_transport(connector);
+  let called = tokio::time::timeout(Duration::from_secs(5), client.ping());
+  assert!(called.await.unwrap().unwrap());
+}
+
+#[tokio::test]
+async fn negotiates_protocol() {
+  let served = serve(|serve| serve);
+
+  let mut stream = served.connector.connect().unwrap();
+  let client = ClientHello {
+    min_version: 1,
+    max_version: 3,
+    features: Features::from_bits(0xff),
+  };
+  // Unknown versions and features are left out.
+  assert_eq!(
+    common::hello(&mut stream, client).await,
+    ServerHello { version: MAX_VERSION, features: Features::empty() }
+  );
+
+  // From version 2 on, requests are framed with an ID.
+  let mut transport = Framed::new(stream, FrameCodec);
+  transport.send(Frame::Ping(42)).await.unwrap();
+  assert_eq!(transport.next().await.unwrap().unwrap(), Frame::Pong(42));
+  transport.send(Frame::Request(7, ping_request())).await.unwrap();
+  assert!(matches!(
+    transport.next().await.unwrap().unwrap(),
+    Frame::Response(7, ResponseFrame::Payload(_))
+  ));
+  drop(transport);
+
+  assert!(served.errors().is_empty());
+}
+
+#[tokio::test]
+async fn unsupported_versions_are_rejected() {
+  let served = serve(|serve| serve);
+
+  let mut stream = served.connector.connect().unwrap();
+  let client = ClientHello {
+    min_version: MAX_VERSION + 1,
+    max_version: MAX_VERSION + 2,
+    features: Features::empty(),
+  };
+  assert!(common::hello(&mut stream, client).await.is_rejected());
+  closed(&mut stream).await;
+
+  let errors = served.errors();
+  assert_eq!(errors.len(), 1);
+  assert!(errors[0].contains("unsupported protocol versions"));
+}
+
+#[tokio::test]
+async fn oversized_responses_only_fail_their_request() {
+  let mut served = serve(|serve| serve);
+  let connection = Connection::new(served.connector.clone());
+
+  let mut waiting = TestClient::with_transport(connection.clone());
+  let waiting = tokio::spawn(async move { waiting.wait().await });
+  served.entered().await;
+
+  // Sent on the same connection while `wait` is still in flight.
+  let mut client = TestClient::with_transport(connection);
+  let err = client.fill(70_000).await.unwrap_err();
+  assert!(matches!(
+    err,
+    ClientError::ServerError(kind) if kind.code() == Code::ResourceExhausted
+  ));
+  served.release(1);
+  assert!(waiting.await.unwrap().unwrap());
+  assert_eq!(client.fill(3).await.unwrap(), [0; 3]);
+
+  // On version 1 the connection stays open for the next request as well.
+  let mut stream = served.connector.connect().unwrap();
+  let arguments =
+    bincode::serialize(&common::TestRequest::fill { len: 70_000 }).unwrap();
+  let request = RequestFrame::new("Test".into(), Bytes::from(arguments));
+  let mut transport = tcp::request_transport(&mut stream);
+  transport.send(request).await.unwrap();
+  let mut transport = tcp::response_transport(transport.into_inner());
+  assert!(matches!(
+    transport.next().await.unwrap().unwrap(),
+    ResponseFrame::Error(kind) if kind.code() == Code::ResourceExhausted
+  ));
+  assert!(matches!(ping(&mut stream).await, ResponseFrame::Payload(_)));
+
+  let errors = served.errors();
+  assert_eq!(errors.len(), 2);
+  assert!(errors[0].contains("payload too long"), "{}", errors[0]);
+}
+
+#[tokio::test]
+async fn idle_connections_are_closed() {
+  let served =
+    serve(|serve| serve.with_idle_timeout(Duration::from_millis(20)));
+
+  let mut stream = served.connector.connect().unwrap();
+  assert!(matches!(ping(&mut stream).await, ResponseFrame::Payload(_)));
+  closed(&mut stream).await;
+
+  // Not an error, the client just had nothing left to ask.
+  assert!(served.errors().is_empty());
+}
+
+#[tokio::test]
+async fn slow_requests_are_dropped() {
+  let served = serve(|serve| {
+    serve
+      .with_idle_timeout(Duration::from_secs(5))
+      .with_read_timeout(Duration::from_millis(20))
+  });
+
+  // Only the start of a command length.
+  let mut stream = served.connector.connect().unwrap();
+  stream.write_all(&[0]).await.unwrap();
+  closed(&mut stream).await;
+
+  let errors = served.errors();
+  assert_eq!(errors.len(), 1);
+  assert!(errors[0].contains("didn't arrive in time"), "{}", errors[0]);
+}
+
+#[tokio::test]
+async fn eof_inside_request_is_reported() {
+  let served = serve(|serve| serve);
+
+  let mut stream = served.connector.connect().unwrap();
+  stream.write_all(&[0, 4, b'P']).await.unwrap();
+  stream.shutdown().await.unwrap();
+  closed(&mut stream).await;
+
+  let errors = served.errors();
+  assert_eq!(errors.len(), 1);
+  assert!(errors[0].starts_with("failed to read request"));
+}
+
+#[cfg(feature = "tls")]
+#[tokio::test]
+async fn stalled_tls_handshakes_time_out() {
+  use webcontr::{serve::ListenerOptions, tls::TLSPaths};
+
+  let (tls, tls_listener) = duplex::listener(1024);
+  let served = serve(move |serve| {
+    serve.with_listener(
+      tls_listener,
+      ListenerOptions::new()
+        .with_tls(TLSPaths::from_paths(
+          "tests/certs/chain.pem",
+          "tests/certs/end.key",
+        ))
+        .with_handshake_timeout(Duration::from_millis(20)),
+    )
+  });
+
+  let mut stream = tls.connect().unwrap();
+  closed(&mut stream).await;
+
+  let errors = served.errors();
+  assert_eq!(errors.len(), 1);
+  assert!(errors[0].contains("tls handshake timed out"), "{}", errors[0]);
+}
@@ -0,0 +1,170 @@
+use std::{
+  collections::VecDeque,
+  future::IntoFuture,
+  io,
+  sync::{Arc, Mutex},
+};
+
+use tokio::io::{AsyncWriteExt, DuplexStream};
+use webcontr::{
+  accept::Accept,
+  context::PeerInfo,
+  prelude::*,
+  serve::ServeError,
+  transport::{duplex, frame::ResponseErrorKind},
+  Server,
+};
+
+#[webcontr::service]
+pub trait Ping {
+  async fn ping() -> bool;
+}
+
+#[derive(Clone)]
+struct PingServer;
+
+#[webcontr::async_trait]
+impl Ping for PingServer {
+  async fn ping(&self) -> bool {
+    true
+  }
+}
+
+/// Fails a few times before handing out connections from `inner`.
+struct Flaky {
+  errors: VecDeque<io::Error>,
+  inner: duplex::DuplexListener,
+}
+
+#[webcontr::async_trait]
+impl Accept for Flaky {
+  type Io = DuplexStream;
+
+  async fn accept(&mut self) -> io::Result<(Self::Io, PeerInfo)> {
+    match self.errors.pop_front() {
+      Some(err) => Err(err),
+      None => self.inner.accept().await,
+    }
+  }
+}
+
+fn recorder() -> (Arc<Mutex<Vec<String>>>, impl Fn(&ServeError)) {
+  let errors = Arc::new(Mutex::new(Vec::new()));
+  let hook_errors = errors.clone();
+  (errors, move |err: &ServeError| {
+    hook_errors.lock().unwrap().push(err.to_string())
+  })
+}
+
+#[tokio::test]
+async fn accept_errors_are_retried() {
+  let (connector, listener) = duplex::listener(1024);
+  let listener = Flaky {
+    errors: VecDeque::from([
+      io::Error::other("too many open files"),
+      io::ErrorKind::ConnectionAborted.into(),
+      io::Error::other("too many open files"),
+    ]),
+    inner: listener,
+  };
+
+  let (errors, hook) = recorder();
+  let server = Server::default()
+    .add_service(PingServer.into_serve())
+    .serve_with(listener)
+    .on_error(hook);
+  tokio::spawn(server.into_future());
+
+  assert!(PingClient::with_transport(connector).ping().await.unwrap());
+  assert_eq!(errors.lock().unwrap().len(), 3);
+}
+
+#[tokio::test]
+async fn bad_connection_does_not_stop_server() {
+  let (connector, listener) = duplex::listener(1024);
+  let (errors, hook) = recorder();
+  let server = Server::default()
+    .add_service(PingServer.into_serve())
+    .serve_with(listener)
+    .on_error(hook);
+  tokio::spawn(server.into_future());
+
+  // Command length 1, followed by a byte that is not valid UTF-8.
+  let mut garbage = connector.connect().unwrap();
+  garbage.write_all(&[0, 1, 0xff, 0, 0]).await.unwrap();
+
+  let mut client = PingClient::with_transport(connector);
+  assert!(client.ping().await.unwrap());
+
+  let errors = errors.lock().unwrap();
+  assert_eq!(errors.len(), 1);
+  assert!(errors[0].starts_with("failed to read request"));
+}
+
+#[cfg(feature = "tls")]
+#[tokio::test]
+async fn failed_tls_handshake_is_reported() {
+  use webcontr::{serve::ListenerOptions, tls::TLSPaths};
+
+  let (plain, plain_listener) = duplex::listener(1024);
+  let (tls, tls_listener) = duplex::listener(1024);
+  let (errors, hook) = recorder();
+  let server = Server::default()
+    .add_service(PingServer.into_serve())
+    .serve_with(plain_listener)
+    .with_listener(
+      tls_listener,
+      ListenerOptions::new().with_tls(TLSPaths::from_paths(
+        "tests/certs/chain.pem",
+        "tests/certs/end.key",
+      )),
+    )
+    .on_error(hook);
+  tokio::spawn(server.into_future());
+
+  let mut not_tls = tls.connect().unwrap();
+  not_tls.write_all(b"definitely not a client hello").await.unwrap();
+
+  assert!(PingClient::with_transport(plain).ping().await.unwrap());
+  tokio::task::yield_now().await;
+
+  let errors = errors.lock().unwrap();
+  assert_eq!(errors.len(), 1);
+  assert!(errors[0].starts_with("failed to read request"));
+}
+
+#[cfg(feature = "tls")]
+#[tokio::test]
+async fn unusable_tls_files_fail_serving() {
+  use webcontr::{serve::ListenerOptions, tls::TLSPaths};
+
+  let paths =
+    TLSPaths::from_paths("tests/certs/chain.pem", "tests/certs/end.key");
+  for (paths, kind) in [
+    (
+      paths.clone().with_client_ca("tests/certs/missing.pem"),
+      io::ErrorKind::NotFound,
+    ),
+    // Holds no certificates, so no client could ever be verified.
+    (
+      paths.clone().with_client_ca("tests/certs/end.key"),
+      io::ErrorKind::InvalidData,
+    ),
+    (
+      TLSPaths::from_paths("tests/certs/chain.pem", "tests/certs/root.pem"),
+      io::ErrorKind::InvalidData,
+    ),
+  ] {
+    let (_, listener) = duplex::listener(1024);
+    let err = Server::default()
+      .add_service(PingServer.into_serve())
+      .serve_with(listener)
+      .with_listener(
+        duplex::listener(1024).1,
+        ListenerOptions::new().with_tls(paths),
+      )
+      .await
+      .unwrap_err();
+    assert_eq!(err.kind(), kind);
+  }
+}
@@ -0,0 +1,172 @@
+mod common;
+
+use std::{
+  io,
+  sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+  },
+  time::Duration,
+};
+
+use bytes::Bytes;
+use futures_util::{SinkExt, StreamExt};
+use tokio_util::codec::Framed;
+use webcontr::{
+  accept::Io,
+  status::Code,
+  transport::{
+    connection::{Connect, Connection},
+    duplex::DuplexConnector,
+    frame::{
+      Frame, FrameCodec, RequestFrame, ResponseErrorKind, ResponseFrame,
+    },
+    preamble::{ClientHello, Features},
+  },
+  ClientError,
+};
+
+use common::{TestClient, TestRequest};
+
+fn slow_request(millis: u64) -> RequestFrame {
+  let arguments = bincode::serialize(&TestRequest::slow { millis }).unwrap();
+  RequestFrame::new("Test".into(), Bytes::from(arguments))
+}
+
+/// Connects to `first` once, and to `then` after that.
+struct Failover<F> {
+  first: F,
+  then: DuplexConnector,
+  connects: Arc<AtomicUsize>,
+}
+
+#[webcontr::async_trait]
+impl<F: Connect> Connect for Failover<F> {
+  async fn connect(&self) -> io::Result<Box<dyn Io>> {
+    if self.connects.fetch_add(1, Ordering::SeqCst) == 0 {
+      return self.first.connect().await;
+    }
+    Ok(Box::new(self.then.connect()?))
+  }
+}
+
+#[tokio::test]
+async fn server_sends_goaway_on_shutdown() {
+  let served = common::serve().spawn();
+
+  let mut stream = served.connector.connect().unwrap();
+  let hello = ClientHello::new(Features::empty());
+  assert_eq!(common::hello(&mut stream, hello).await.version, 2);
+
+  let mut transport = Framed::new(stream, FrameCodec);
+  transport.send(Frame::Request(1, slow_request(100))).await.unwrap();
+  tokio::time::sleep(Duration::from_millis(20)).await;
+  served.shutdown();
+  assert_eq!(transport.next().await.unwrap().unwrap(), Frame::GoAway(1));
+
+  // Not taken anymore, but the one before is still answered.
+  transport.send(Frame::Request(2, slow_request(0))).await.unwrap();
+  assert!(matches!(
+    transport.next().await.unwrap().unwrap(),
+    Frame::Response(1, ResponseFrame::Payload(_))
+  ));
+  assert!(transport.next().await.is_none());
+}
+
+#[tokio::test]
+async fn client_moves_to_new_connection() {
+  let draining = common::serve().spawn();
+  let served = common::serve().spawn();
+  let connects = Arc::new(AtomicUsize::new(0));
+  let connection = Connection::new(Failover {
+    first: draining.connector.clone(),
+    then: served.connector.clone(),
+    connects: connects.clone(),
+  });
+
+  let mut client = TestClient::with_transport(connection.clone());
+  let running = tokio::spawn(async move { client.slow(100).await });
+  tokio::time::sleep(Duration::from_millis(20)).await;
+  draining.shutdown();
+  tokio::time::sleep(Duration::from_millis(20)).await;
+
+  // Goes elsewhere while the first call is still being answered.
+  let mut client = TestClient::with_transport(connection);
+  assert!(client.slow(0).await.unwrap());
+  assert_eq!(connects.load(Ordering::SeqCst), 2);
+  assert!(running.await.unwrap().unwrap());
+}
+
+/// A server that takes no request at all.
+struct GoingAway;
+
+#[webcontr::async_trait]
+impl Connect for GoingAway {
+  async fn connect(&self) -> io::Result<Box<dyn Io>> {
+    let (client, mut server) = tokio::io::duplex(1024);
+    tokio::spawn(async move {
+      common::answer_hello(&mut server).await;
+
+      let mut transport = Framed::new(server, FrameCodec);
+      let request = transport.next().await.unwrap().unwrap();
+      assert!(matches!(request, Frame::Request(1, _)));
+      transport.send(Frame::GoAway(0)).await.unwrap();
+    });
+    Ok(Box::new(client))
+  }
+}
+
+#[tokio::test]
+async fn calls_not_taken_are_sent_again() {
+  let served = common::serve().spawn();
+  let connects = Arc::new(AtomicUsize::new(0));
+  let connection = Connection::new(Failover {
+    first: GoingAway,
+    then: served.connector.clone(),
+    connects: connects.clone(),
+  });
+
+  let mut client = TestClient::with_transport(connection);
+  assert!(client.slow(0).await.unwrap());
+  assert_eq!(connects.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn requests_outlasting_the_drain_timeout_are_given_up() {
+  let served = common::serve()
+    .with_serve(|serve| serve.with_drain_timeout(Duration::from_millis(50)))
+    .spawn();
+
+  let mut stream = served.connector.connect().unwrap();
+  common::hello(&mut stream, ClientHello::new(Features::empty())).await;
+  let mut transport = Framed::new(stream, FrameCodec);
+  transport.send(Frame::Request(1, slow_request(60_000))).await.unwrap();
+
+  // On protocol version 1, one request at a time.
+  let mut legacy = served.client();
+  let legacy = tokio::spawn(async move { legacy.slow(60_000).await });
+
+  tokio::time::sleep(Duration::from_millis(20)).await;
+  served.shutdown();
+  assert_eq!(transport.next().await.unwrap().unwrap(), Frame::GoAway(1));
+  let Frame::Response(
+    1,
+    ResponseFrame::Error(ResponseErrorKind::Status(status)),
+  ) = transport.next().await.unwrap().unwrap()
+  else {
+    panic!("expected the request to be given up on");
+  };
+  assert_eq!(status.code(), Code::Unavailable);
+  assert!(transport.next().await.is_none());
+
+  let err = legacy.await.unwrap().unwrap_err();
+  assert!(matches!(
+    err,
+    ClientError::ServerError(kind) if kind.code() == Code::Unavailable
+  ));
+  tokio::time::timeout(Duration::from_secs(5), served.server)
+    .await
+    .unwrap()
+    .unwrap()
+    .unwrap();
+}
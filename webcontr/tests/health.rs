@@ -0,0 +1,83 @@
+use std::future::IntoFuture;
+
+use webcontr::{
+  health::{self, HealthClient, ServingStatus},
+  prelude::*,
+  transport::{channel, duplex, frame::ResponseErrorKind},
+  Server,
+};
+
+#[webcontr::service]
+pub trait Greeter {
+  async fn greet(name: String) -> String;
+}
+
+#[derive(Clone)]
+struct GreeterServer;
+
+#[webcontr::async_trait]
+impl Greeter for GreeterServer {
+  async fn greet(&self, name: String) -> String {
+    format!("hello {name}")
+  }
+}
+
+#[tokio::test]
+async fn check_reports_statuses() {
+  let (reporter, health) = health::reporter();
+  let greeter = GreeterServer.into_serve();
+  reporter.set_service_serving(&greeter);
+
+  let (client, server) = channel::unbounded();
+  let server = Server::default()
+    .add_service(greeter)
+    .add_service(health)
+    .serve_channel(server);
+  tokio::spawn(server.into_future());
+
+  let mut client = HealthClient::with_transport(client);
+  assert_eq!(client.check("".into()).await.unwrap(), ServingStatus::Serving);
+  assert!(client.is_serving("Greeter").await.unwrap());
+  assert_eq!(
+    client.check("Missing".into()).await.unwrap(),
+    ServingStatus::Unknown
+  );
+
+  reporter.set_not_serving("Greeter");
+  assert!(!client.is_serving("Greeter").await.unwrap());
+
+  reporter.clear("Greeter");
+  assert_eq!(
+    client.check("Greeter".into()).await.unwrap(),
+    ServingStatus::Unknown
+  );
+}
+
+#[tokio::test]
+async fn watch_yields_changes() {
+  let (reporter, health) = health::reporter();
+  let (connector, listener) = duplex::listener(1024);
+  let server = Server::default()
+    .add_service(health)
+    .serve_with(listener)
+    .with_timeout(std::time::Duration::from_millis(20));
+  tokio::spawn(server.into_future());
+
+  let mut client = HealthClient::with_transport(connector);
+  let mut statuses = Box::pin(client.watch_status("Greeter"));
+  assert_eq!(statuses.next().await.unwrap().unwrap(), ServingStatus::Unknown);
+
+  // Outlive the server timeout to make sure the watch is retried.
+  let updater = reporter.clone();
+  tokio::spawn(async move {
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    updater.set_serving("Greeter");
+  });
+  assert_eq!(statuses.next().await.unwrap().unwrap(), ServingStatus::Serving);
+
+  reporter.set_not_serving("Greeter");
+  assert_eq!(
+    statuses.next().await.unwrap().unwrap(),
+    ServingStatus::NotServing
+  );
+}
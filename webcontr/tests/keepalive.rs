@@ -0,0 +1,146 @@
+mod common;
+
+use std::{
+  io,
+  sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+  },
+  time::Duration,
+};
+
+use futures_util::StreamExt;
+use tokio::io::AsyncReadExt;
+use tokio_util::codec::Framed;
+use webcontr::{
+  accept::Io,
+  transport::{
+    connection::{Connect, Connection},
+    duplex::DuplexConnector,
+    frame::{Frame, FrameCodec},
+    keepalive::Keepalive,
+    preamble::{ClientHello, Features},
+  },
+  ClientError,
+};
+
+use common::{Served, TestClient};
+
+fn serve(keepalive: Option<Keepalive>) -> Served {
+  common::serve()
+    .with_serve(move |serve| match keepalive {
+      Some(keepalive) => serve.with_keepalive(keepalive),
+      None => serve,
+    })
+    .spawn()
+}
+
+fn keepalive() -> Keepalive {
+  Keepalive::new(Duration::from_millis(20), Duration::from_millis(50))
+}
+
+/// Counts connections, and hands out `silent` ones first.
+struct Counting {
+  connector: DuplexConnector,
+  silent: usize,
+  connects: Arc<AtomicUsize>,
+}
+
+#[webcontr::async_trait]
+impl Connect for Counting {
+  async fn connect(&self) -> io::Result<Box<dyn Io>> {
+    if self.connects.fetch_add(1, Ordering::SeqCst) < self.silent {
+      return Ok(Box::new(silent_server()));
+    }
+    Ok(Box::new(self.connector.connect()?))
+  }
+}
+
+/// A server that agrees on version 2 and then never answers again.
+fn silent_server() -> tokio::io::DuplexStream {
+  let (client, mut server) = tokio::io::duplex(1024);
+  tokio::spawn(async move {
+    common::answer_hello(&mut server).await;
+    // Keeps the connection open.
+    let _ = server.read_to_end(&mut Vec::new()).await;
+  });
+  client
+}
+
+#[tokio::test]
+async fn calls_share_one_connection() {
+  let mut served = serve(None);
+  let connects = Arc::new(AtomicUsize::new(0));
+  let connection = Connection::new(Counting {
+    connector: served.connector.clone(),
+    silent: 0,
+    connects: connects.clone(),
+  });
+
+  // `wait` only returns once `release` is handled, so both are in flight
+  // at once.
+  let mut waiting = TestClient::with_transport(connection.clone());
+  let waiting = tokio::spawn(async move { waiting.wait().await });
+  served.entered().await;
+
+  let mut client = TestClient::with_transport(connection);
+  assert!(client.release().await.unwrap());
+  assert!(waiting.await.unwrap().unwrap());
+  assert_eq!(connects.load(Ordering::SeqCst), 1);
+  assert!(served.errors().is_empty());
+}
+
+#[tokio::test]
+async fn both_sides_answer_pings() {
+  let served = serve(Some(keepalive()));
+  let connection =
+    Connection::new(served.connector.clone()).with_keepalive(keepalive());
+  let mut client = TestClient::with_transport(connection);
+
+  // Several pings either way while the call is running.
+  assert!(client.slow(200).await.unwrap());
+  assert!(served.errors().is_empty());
+}
+
+#[tokio::test]
+async fn server_closes_connections_of_dead_clients() {
+  let served = serve(Some(keepalive()));
+
+  let mut stream = served.connector.connect().unwrap();
+  let hello = ClientHello::new(Features::empty());
+  assert_eq!(common::hello(&mut stream, hello).await.version, 2);
+  // Reads the pings, but never answers.
+  let mut transport = Framed::new(stream, FrameCodec);
+  let read = tokio::time::timeout(Duration::from_secs(5), async {
+    while let Some(frame) = transport.next().await {
+      assert!(matches!(frame.unwrap(), Frame::Ping(_)));
+    }
+  });
+  read.await.unwrap();
+
+  let errors = served.errors();
+  assert_eq!(errors.len(), 1);
+  assert!(errors[0].contains("didn't answer ping"), "{}", errors[0]);
+}
+
+#[tokio::test]
+async fn client_reconnects_after_dead_server() {
+  let served = serve(None);
+  let connects = Arc::new(AtomicUsize::new(0));
+  let connection = Connection::new(Counting {
+    connector: served.connector.clone(),
+    silent: 1,
+    connects: connects.clone(),
+  })
+  .with_keepalive(keepalive());
+  let mut client = TestClient::with_transport(connection);
+
+  let err = client.slow(0).await.unwrap_err();
+  let ClientError::IoError(err) = err else {
+    panic!("unexpected {err:?}");
+  };
+  assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+  assert!(client.slow(0).await.unwrap());
+  assert_eq!(connects.load(Ordering::SeqCst), 2);
+}
@@ -0,0 +1,69 @@
+mod common;
+
+use std::time::Duration;
+
+use webcontr::{
+  serve::Overload, transport::frame::ResponseErrorKind, ClientError,
+};
+
+use common::Served;
+
+fn call(served: &Served) -> tokio::task::JoinHandle<Result<bool, ClientError>> {
+  let mut client = served.client();
+  tokio::spawn(async move { client.wait().await })
+}
+
+fn is_overloaded(result: Result<bool, ClientError>) -> bool {
+  matches!(result, Err(ClientError::ServerError(ResponseErrorKind::Overloaded)))
+}
+
+#[tokio::test]
+async fn rejects_beyond_max_in_flight() {
+  let mut served =
+    common::serve().with_serve(|serve| serve.with_max_in_flight(1)).spawn();
+
+  let first = call(&served);
+  served.entered().await;
+
+  assert!(is_overloaded(call(&served).await.unwrap()));
+
+  served.release(1);
+  assert!(first.await.unwrap().unwrap());
+}
+
+#[tokio::test]
+async fn queues_up_to_bound() {
+  let mut served = common::serve()
+    .with_serve(|serve| {
+      serve.with_max_in_flight(1).with_overload(Overload::Queue(1))
+    })
+    .spawn();
+
+  let first = call(&served);
+  served.entered().await;
+  let queued = call(&served);
+
+  // Give the second request time to reach the queue.
+  tokio::time::sleep(Duration::from_millis(20)).await;
+  assert!(is_overloaded(call(&served).await.unwrap()));
+
+  served.release(2);
+  assert!(first.await.unwrap().unwrap());
+  assert!(queued.await.unwrap().unwrap());
+}
+
+#[tokio::test]
+async fn rejects_beyond_max_connections() {
+  let mut served =
+    common::serve().with_serve(|serve| serve.with_max_connections(1)).spawn();
+
+  let first = call(&served);
+  served.entered().await;
+
+  assert!(is_overloaded(call(&served).await.unwrap()));
+
+  served.release(1);
+  assert!(first.await.unwrap().unwrap());
+  served.release(1);
+  assert!(call(&served).await.unwrap().unwrap());
+}
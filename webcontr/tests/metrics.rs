@@ -0,0 +1,120 @@
+#![cfg(feature = "prometheus")]
+
+use std::future::IntoFuture;
+
+use metrics_exporter_prometheus::PrometheusBuilder;
+use tokio::{
+  io::{AsyncReadExt, AsyncWriteExt},
+  net::{TcpListener, TcpStream},
+};
+use webcontr::{
+  prelude::*,
+  transport::{duplex, frame::ResponseErrorKind},
+  Server,
+};
+
+#[webcontr::service]
+pub trait Calc {
+  async fn add(a: u32, b: u32) -> u32;
+}
+
+#[derive(Clone)]
+struct CalcServer;
+
+#[webcontr::async_trait]
+impl Calc for CalcServer {
+  async fn add(&self, a: u32, b: u32) -> u32 {
+    a + b
+  }
+}
+
+#[tokio::test]
+async fn records_and_serves_metrics() {
+  let recorder = PrometheusBuilder::new().build_recorder();
+  let handle = recorder.handle();
+  // The current thread runtime polls everything on this thread.
+  let _guard = metrics::set_default_local_recorder(&recorder);
+
+  let (connector, listener) = duplex::listener(1024);
+  let server =
+    Server::default().add_service(CalcServer.into_serve()).serve_with(listener);
+  tokio::spawn(server.into_future());
+
+  let mut client = CalcClient::with_transport(connector.clone());
+  assert_eq!(client.add(1, 2).await.unwrap(), 3);
+  webcontr::transport::send_request::<_, CalcResponse>(
+    &mut connector.clone(),
+    "Nope",
+    "add",
+    (),
+    Default::default(),
+  )
+  .await
+  .unwrap_err();
+
+  let rendered = handle.render();
+  for expected in [
+    r#"webcontr_server_requests_total{service="Calc",method="add",status="ok"} 1"#,
+    r#"webcontr_client_requests_total{service="Calc",method="add",status="ok"} 1"#,
+    r#"webcontr_server_errors_total{service="unknown",method="unknown",kind="method_not_found"} 1"#,
+    r#"webcontr_server_requests_in_flight{service="Calc"} 0"#,
+    "webcontr_server_received_bytes_total 12",
+    "webcontr_server_sent_bytes_total 8",
+  ] {
+    assert!(rendered.contains(expected), "missing {expected} in\n{rendered}");
+  }
+
+  let http = TcpListener::bind("127.0.0.1:0").await.unwrap();
+  let addr = http.local_addr().unwrap();
+  tokio::spawn(webcontr::metrics::serve_prometheus(http, handle));
+
+  let mut stream = TcpStream::connect(addr).await.unwrap();
+  stream.write_all(b"GET /metrics HTTP/1.1\r\nHost: x\r\n\r\n").await.unwrap();
+  let mut response = String::new();
+  stream.read_to_string(&mut response).await.unwrap();
+
+  assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+  assert!(response.contains("webcontr_server_request_duration_seconds"));
+}
+
+#[tokio::test]
+async fn records_failures_without_error_responses() {
+  let recorder = PrometheusBuilder::new().build_recorder();
+  let handle = recorder.handle();
+  let _guard = metrics::set_default_local_recorder(&recorder);
+
+  let (connector, listener) = duplex::listener(1024);
+  let server =
+    Server::default().add_service(CalcServer.into_serve()).serve_with(listener);
+  tokio::spawn(server.into_future());
+
+  // Command length 1, followed by a byte that is not valid UTF-8.
+  let mut garbage = connector.connect().unwrap();
+  garbage.write_all(&[0, 1, 0xff, 0, 0]).await.unwrap();
+
+  // Answered, but not with what the client expects.
+  webcontr::transport::send_request::<_, String>(
+    &mut connector.clone(),
+    "Calc",
+    "add",
+    CalcRequest::add { a: 1, b: 2 },
+    Default::default(),
+  )
+  .await
+  .unwrap_err();
+
+  let (gone, listener) = duplex::listener(1024);
+  drop(listener);
+  let mut client = CalcClient::with_transport(gone);
+  client.add(1, 2).await.unwrap_err();
+
+  let rendered = handle.render();
+  for expected in [
+    r#"webcontr_server_errors_total{service="unknown",method="unknown",kind="read"} 1"#,
+    r#"webcontr_client_requests_total{service="Calc",method="add",status="decoding"} 1"#,
+    r#"webcontr_client_errors_total{service="Calc",method="add",kind="decoding"} 1"#,
+    r#"webcontr_client_requests_total{service="Calc",method="add",status="connect"} 1"#,
+  ] {
+    assert!(rendered.contains(expected), "missing {expected} in\n{rendered}");
+  }
+}
@@ -0,0 +1,76 @@
+#![cfg(feature = "opentelemetry")]
+
+use std::future::IntoFuture;
+
+use opentelemetry::trace::{TraceContextExt, TracerProvider as _};
+use opentelemetry_sdk::trace::TracerProvider;
+use tracing::{Instrument, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::layer::SubscriberExt;
+use webcontr::{
+  prelude::*,
+  transport::{duplex, frame::ResponseErrorKind},
+  Server,
+};
+
+#[webcontr::service]
+pub trait Traced {
+  async fn trace_id() -> String;
+  async fn traceparent() -> Option<String>;
+}
+
+#[derive(Clone)]
+struct TracedServer;
+
+#[webcontr::async_trait]
+impl Traced for TracedServer {
+  async fn trace_id(&self) -> String {
+    Span::current().context().span().span_context().trace_id().to_string()
+  }
+
+  async fn traceparent(&self) -> Option<String> {
+    let context = webcontr::context::current()?;
+    context.metadata().get("traceparent").map(str::to_string)
+  }
+}
+
+#[tokio::test]
+async fn handler_continues_client_trace() {
+  let provider = TracerProvider::builder().build();
+  let subscriber = tracing_subscriber::registry()
+    .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+  let _guard = tracing::subscriber::set_default(subscriber);
+
+  let (connector, listener) = duplex::listener(1024);
+  let server = Server::default()
+    .add_service(TracedServer.into_serve())
+    .serve_with(listener);
+  tokio::spawn(server.into_future());
+
+  let mut client = TracedClient::with_transport(connector);
+  let outer = tracing::info_span!("outer");
+  let trace_id = outer.context().span().span_context().trace_id().to_string();
+
+  let (handler_trace_id, traceparent) = async {
+    (client.trace_id().await.unwrap(), client.traceparent().await.unwrap())
+  }
+  .instrument(outer)
+  .await;
+
+  assert_eq!(handler_trace_id, trace_id);
+  let traceparent = traceparent.unwrap();
+  assert!(traceparent.starts_with(&format!("00-{trace_id}-")));
+  assert!(traceparent.ends_with("-01"));
+}
+
+#[tokio::test]
+async fn no_trace_context_without_span() {
+  let (connector, listener) = duplex::listener(1024);
+  let server = Server::default()
+    .add_service(TracedServer.into_serve())
+    .serve_with(listener);
+  tokio::spawn(server.into_future());
+
+  let mut client = TracedClient::with_transport(connector);
+  assert_eq!(client.traceparent().await.unwrap(), None);
+}
@@ -0,0 +1,72 @@
+use std::{
+  future::IntoFuture,
+  sync::{Arc, Mutex},
+};
+
+use webcontr::{
+  prelude::*,
+  transport::{channel, frame::ResponseErrorKind},
+  ClientError, Server,
+};
+
+#[webcontr::service]
+pub trait Fragile {
+  async fn explode(message: String) -> u8;
+}
+
+#[derive(Clone)]
+struct FragileServer;
+
+#[webcontr::async_trait]
+impl Fragile for FragileServer {
+  async fn explode(&self, message: String) -> u8 {
+    panic!("{message}")
+  }
+}
+
+#[tokio::test]
+async fn panic_becomes_internal_error() {
+  let reported = Arc::new(Mutex::new(Vec::new()));
+  let hook_reported = reported.clone();
+
+  let (client, server) = channel::unbounded();
+  let server = Server::default()
+    .add_service(FragileServer.into_serve())
+    .on_panic(move |panic| {
+      let entry = (panic.service.to_string(), panic.message.map(String::from));
+      hook_reported.lock().unwrap().push(entry);
+    })
+    .serve_channel(server);
+  tokio::spawn(server.into_future());
+
+  let mut client = FragileClient::with_transport(client);
+  for _ in 0..2 {
+    let err = client.explode("boom".into()).await.unwrap_err();
+    assert!(matches!(
+      err,
+      ClientError::ServerError(ResponseErrorKind::Internal(None))
+    ));
+  }
+
+  let reported = reported.lock().unwrap();
+  assert_eq!(reported.len(), 2);
+  assert_eq!(reported[0], ("Fragile".to_string(), Some("boom".to_string())));
+}
+
+#[tokio::test]
+async fn panic_message_is_opt_in() {
+  let (client, server) = channel::unbounded();
+  let server = Server::default()
+    .add_service(FragileServer.into_serve())
+    .with_panic_messages()
+    .serve_channel(server);
+  tokio::spawn(server.into_future());
+
+  let mut client = FragileClient::with_transport(client);
+  let err = client.explode("boom".into()).await.unwrap_err();
+  assert!(matches!(
+    err,
+    ClientError::ServerError(ResponseErrorKind::Internal(Some(message)))
+      if message == "boom"
+  ));
+}
@@ -0,0 +1,67 @@
+mod common;
+
+use std::time::Duration;
+
+use webcontr::{
+  rate_limit::{Rate, RateLimitKey, RateLimiter},
+  transport::frame::ResponseErrorKind,
+  ClientError,
+};
+
+use common::{Served, TestClient};
+
+fn serve(limiter: RateLimiter) -> Served {
+  common::serve()
+    .with_server(|server| server.with_rate_limiter(limiter))
+    .spawn()
+}
+
+#[tokio::test]
+async fn rejects_with_retry_after() {
+  let served = serve(
+    RateLimiter::new(RateLimitKey::Peer)
+      .with_default(Rate::per_second(10).with_burst(1)),
+  );
+  let mut client = served.client();
+
+  assert!(client.ping().await.unwrap());
+  let err = client.ping().await.unwrap_err();
+  assert!(matches!(
+    err,
+    ClientError::ServerError(ResponseErrorKind::RateLimited { .. })
+  ));
+  let retry_after = err.retry_after().unwrap();
+  assert!(retry_after > Duration::ZERO);
+  assert!(retry_after <= Duration::from_millis(100));
+
+  tokio::time::sleep(retry_after).await;
+  assert!(client.ping().await.unwrap());
+}
+
+#[tokio::test]
+async fn methods_are_limited_separately() {
+  let served = serve(
+    RateLimiter::new(RateLimitKey::Peer)
+      .with_default(Rate::per_second(1000))
+      .with_method("Test", "echo", Rate::per_minute(1)),
+  );
+  let mut client = served.client();
+
+  assert_eq!(client.echo("a".into()).await.unwrap(), "a");
+  assert!(client.echo("a".into()).await.unwrap_err().retry_after().is_some());
+  assert!(client.ping().await.unwrap());
+}
+
+#[tokio::test]
+async fn client_retries_after_waiting() {
+  let served = serve(
+    RateLimiter::new(RateLimitKey::Peer)
+      .with_default(Rate::per_second(50).with_burst(1)),
+  );
+  let mut client = TestClient::with_transport(served.connector.clone())
+    .with_rate_limit_retries(3);
+
+  for _ in 0..3 {
+    assert!(client.ping().await.unwrap());
+  }
+}
@@ -0,0 +1,306 @@
+mod common;
+
+use std::{
+  io,
+  sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex,
+  },
+  time::{Duration, Instant},
+};
+
+use webcontr::{
+  accept::Io,
+  transport::{
+    connection::{Backoff, Connect, Connection, ConnectionState, OnDisconnect},
+    duplex::DuplexConnector,
+  },
+  ClientError,
+};
+
+use common::TestClient;
+
+fn serve() -> DuplexConnector {
+  common::serve().spawn().connector
+}
+
+fn backoff() -> Backoff {
+  Backoff::new(Duration::from_millis(10), Duration::from_millis(40))
+}
+
+/// Refuses the first `refused` attempts, then closes the first connection
+/// right after the handshake if `restart` is set, like a server restarting.
+struct Flaky {
+  connector: DuplexConnector,
+  refused: usize,
+  restart: bool,
+  connects: Arc<AtomicUsize>,
+}
+
+#[webcontr::async_trait]
+impl Connect for Flaky {
+  async fn connect(&self) -> io::Result<Box<dyn Io>> {
+    let attempt = self.connects.fetch_add(1, Ordering::SeqCst);
+    if attempt < self.refused {
+      return Err(io::ErrorKind::ConnectionRefused.into());
+    }
+    if self.restart && attempt == self.refused {
+      return Ok(Box::new(closed_after_handshake()));
+    }
+    Ok(Box::new(self.connector.connect()?))
+  }
+}
+
+fn closed_after_handshake() -> tokio::io::DuplexStream {
+  let (client, mut server) = tokio::io::duplex(1024);
+  tokio::spawn(async move {
+    common::answer_hello(&mut server).await;
+  });
+  client
+}
+
+#[tokio::test]
+async fn queued_calls_wait_for_server() {
+  let connects = Arc::new(AtomicUsize::new(0));
+  let connection = Connection::new(Flaky {
+    connector: serve(),
+    refused: 3,
+    restart: false,
+    connects: connects.clone(),
+  })
+  .with_backoff(backoff());
+  let mut state = connection.state();
+  assert_eq!(*state.borrow(), ConnectionState::Idle);
+
+  let mut client = TestClient::with_transport(connection);
+  assert!(client.ping().await.unwrap());
+  assert_eq!(connects.load(Ordering::SeqCst), 4);
+  assert_eq!(*state.borrow_and_update(), ConnectionState::Connected);
+}
+
+#[tokio::test]
+async fn calls_fail_while_disconnected() {
+  let connects = Arc::new(AtomicUsize::new(0));
+  let connection = Connection::new(Flaky {
+    connector: serve(),
+    refused: usize::MAX,
+    restart: false,
+    connects,
+  })
+  .with_backoff(backoff())
+  .with_on_disconnect(OnDisconnect::Fail);
+  let mut state = connection.state();
+
+  let mut client = TestClient::with_transport(connection);
+  let err = client.ping().await.unwrap_err();
+  let ClientError::ConnectError(err) = err else {
+    panic!("unexpected {err:?}");
+  };
+  assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+
+  let reconnecting =
+    state.wait_for(|state| matches!(state, ConnectionState::Reconnecting { failures, .. } if *failures >= 3));
+  let reconnecting =
+    tokio::time::timeout(Duration::from_secs(5), reconnecting).await;
+  let ConnectionState::Reconnecting { retry_in, .. } =
+    *reconnecting.unwrap().unwrap()
+  else {
+    unreachable!()
+  };
+  assert_eq!(retry_in, Duration::from_millis(40));
+  assert!(client.ping().await.is_err());
+}
+
+#[tokio::test]
+async fn reconnects_after_server_restart() {
+  let connects = Arc::new(AtomicUsize::new(0));
+  let connection = Connection::new(Flaky {
+    connector: serve(),
+    refused: 0,
+    restart: true,
+    connects: connects.clone(),
+  })
+  .with_backoff(backoff());
+  let mut state = connection.state();
+
+  // The first call goes out just as the connection breaks, or waits for
+  // the next one.
+  let mut client = TestClient::with_transport(connection);
+  let _ = client.ping().await;
+
+  // Reconnected in the background, without another call.
+  let connected = async {
+    while connects.load(Ordering::SeqCst) < 2 {
+      state.changed().await.unwrap();
+    }
+    state.wait_for(|state| *state == ConnectionState::Connected).await.map(drop)
+  };
+  tokio::time::timeout(Duration::from_secs(5), connected)
+    .await
+    .unwrap()
+    .unwrap();
+  assert!(client.ping().await.unwrap());
+  assert_eq!(connects.load(Ordering::SeqCst), 2);
+}
+
+/// Answers every handshake and hangs up right after, noting when each
+/// connection was made.
+struct HangsUp {
+  connects: Arc<Mutex<Vec<Instant>>>,
+}
+
+#[webcontr::async_trait]
+impl Connect for HangsUp {
+  async fn connect(&self) -> io::Result<Box<dyn Io>> {
+    self.connects.lock().unwrap().push(Instant::now());
+    Ok(Box::new(closed_after_handshake()))
+  }
+}
+
+#[tokio::test]
+async fn backs_off_from_servers_that_hang_up() {
+  let connects = Arc::new(Mutex::new(Vec::new()));
+  let connection = Connection::new(HangsUp { connects: connects.clone() })
+    .with_backoff(backoff());
+  let mut state = connection.state();
+
+  let mut client = TestClient::with_transport(connection);
+  let _ = tokio::time::timeout(Duration::from_millis(10), client.ping()).await;
+  let reconnecting = state.wait_for(|state| {
+    matches!(state, ConnectionState::Reconnecting { failures, .. } if *failures >= 4)
+  });
+  tokio::time::timeout(Duration::from_secs(5), reconnecting)
+    .await
+    .unwrap()
+    .unwrap();
+
+  let connects = connects.lock().unwrap();
+  let gaps: Vec<_> =
+    connects.windows(2).map(|pair| pair[1] - pair[0]).take(3).collect();
+  assert_eq!(gaps.len(), 3);
+  for (gap, min) in gaps.into_iter().zip([10, 20, 40]) {
+    assert!(gap >= Duration::from_millis(min), "{gap:?} < {min}ms");
+  }
+}
+
+/// Answers the handshake of the first connection but never reads from it,
+/// later ones go to `connector`.
+struct Stalled {
+  connector: DuplexConnector,
+  connects: Arc<AtomicUsize>,
+}
+
+#[webcontr::async_trait]
+impl Connect for Stalled {
+  async fn connect(&self) -> io::Result<Box<dyn Io>> {
+    if self.connects.fetch_add(1, Ordering::SeqCst) > 0 {
+      return Ok(Box::new(self.connector.connect()?));
+    }
+
+    let (client, mut server) = tokio::io::duplex(64);
+    tokio::spawn(async move {
+      common::answer_hello(&mut server).await;
+      // Keeps the connection open without taking anything.
+      std::future::pending::<()>().await;
+    });
+    Ok(Box::new(client))
+  }
+}
+
+#[tokio::test]
+async fn reconnects_when_server_stops_reading() {
+  let connects = Arc::new(AtomicUsize::new(0));
+  let connection =
+    Connection::new(Stalled { connector: serve(), connects: connects.clone() })
+      .with_backoff(backoff())
+      .with_write_timeout(Duration::from_millis(50));
+
+  // More than fits in the buffer of the first connection.
+  let pings = (0..10).map(|_| {
+    let mut client = TestClient::with_transport(connection.clone());
+    tokio::spawn(async move { client.ping().await })
+  });
+  let results = tokio::time::timeout(
+    Duration::from_secs(5),
+    futures_util::future::join_all(pings),
+  )
+  .await
+  .unwrap();
+  assert!(results.into_iter().any(|result| result.unwrap().is_err()));
+
+  let mut client = TestClient::with_transport(connection);
+  assert!(client.ping().await.unwrap());
+  assert_eq!(connects.load(Ordering::SeqCst), 2);
+}
+
+/// Accepts the first connection but never answers its hello, like a server
+/// from before the preamble. Later ones go to `connector`.
+struct Unresponsive {
+  connector: DuplexConnector,
+  connects: Arc<AtomicUsize>,
+}
+
+#[webcontr::async_trait]
+impl Connect for Unresponsive {
+  async fn connect(&self) -> io::Result<Box<dyn Io>> {
+    if self.connects.fetch_add(1, Ordering::SeqCst) > 0 {
+      return Ok(Box::new(self.connector.connect()?));
+    }
+
+    let (client, server) = tokio::io::duplex(1024);
+    tokio::spawn(async move {
+      let _server = server;
+      std::future::pending::<()>().await;
+    });
+    Ok(Box::new(client))
+  }
+}
+
+#[tokio::test]
+async fn gives_up_on_servers_that_never_answer() {
+  let connects = Arc::new(AtomicUsize::new(0));
+  let connection = Connection::new(Unresponsive {
+    connector: serve(),
+    connects: connects.clone(),
+  })
+  .with_backoff(backoff())
+  .with_connect_timeout(Duration::from_millis(50))
+  .with_on_disconnect(OnDisconnect::Fail);
+  let mut state = connection.state();
+
+  let mut client = TestClient::with_transport(connection);
+  let err = client.ping().await.unwrap_err();
+  let ClientError::ConnectError(err) = err else {
+    panic!("unexpected {err:?}");
+  };
+  assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+  // Backs off like for any other server that can't be reached.
+  let connected = state.wait_for(|state| *state == ConnectionState::Connected);
+  tokio::time::timeout(Duration::from_secs(5), connected)
+    .await
+    .unwrap()
+    .unwrap();
+  assert!(client.ping().await.unwrap());
+  assert_eq!(connects.load(Ordering::SeqCst), 2);
+}
+
+// With tls the client expects a tls server.
+#[cfg(not(feature = "tls"))]
+#[tokio::test]
+async fn generated_client_connects() {
+  use std::future::IntoFuture;
+
+  use common::{Test, TestServer};
+  use webcontr::Server;
+
+  let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+  let addr = listener.local_addr().unwrap();
+  let server =
+    Server::default().add_service(TestServer::default().into_serve());
+  tokio::spawn(server.serve_with(listener).into_future());
+
+  let mut client = TestClient::connect(addr.to_string());
+  assert!(client.ping().await.unwrap());
+  assert!(client.ping().await.unwrap());
+}
@@ -0,0 +1,200 @@
+use std::{
+  future::IntoFuture,
+  sync::atomic::{AtomicUsize, Ordering},
+};
+
+use serde::{Deserialize, Serialize};
+use webcontr::{
+  prelude::*,
+  reflection::{
+    Field, Fields, ReflectionClient, Schema, ServiceDescriptor, TypeKind,
+    Variant,
+  },
+  transport::{channel, frame::ResponseErrorKind},
+  FrozenServer, Server, ServiceName,
+};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Entry {
+  key: String,
+  value: Vec<u8>,
+  expiry: Expiry,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Expiry {
+  Never,
+  After(u64),
+}
+
+#[webcontr::service]
+pub trait Store {
+  async fn get(key: String) -> Option<Vec<u8>>;
+  async fn set(key: String, value: Vec<u8>);
+  async fn scan(range: (u32, u32), limit: Option<usize>) -> Vec<String>;
+  async fn put(entry: Entry) -> Option<Entry>;
+}
+
+#[derive(Clone)]
+struct StoreServer;
+
+#[webcontr::async_trait]
+impl Store for StoreServer {
+  async fn get(&self, _key: String) -> Option<Vec<u8>> {
+    None
+  }
+
+  async fn set(&self, _key: String, _value: Vec<u8>) {}
+
+  async fn scan(
+    &self,
+    _range: (u32, u32),
+    _limit: Option<usize>,
+  ) -> Vec<String> {
+    Vec::new()
+  }
+
+  async fn put(&self, _entry: Entry) -> Option<Entry> {
+    None
+  }
+}
+
+fn signatures(desc: &ServiceDescriptor) -> Vec<String> {
+  desc
+    .methods
+    .iter()
+    .map(|method| {
+      let args: Vec<_> = method
+        .arguments
+        .iter()
+        .map(|arg| format!("{}: {}", arg.name, arg.ty))
+        .collect();
+      format!("{}({}) -> {}", method.name, args.join(", "), method.output)
+    })
+    .collect()
+}
+
+#[tokio::test]
+async fn lists_services_and_methods() {
+  let (client, server) = channel::unbounded();
+  let server = Server::default()
+    .add_service(StoreServer.into_serve())
+    .with_reflection()
+    .serve_channel(server);
+  tokio::spawn(server.into_future());
+
+  let mut client = ReflectionClient::with_transport(client);
+  let services = client.list_services().await.unwrap();
+  let names: Vec<_> = services.iter().map(|desc| &*desc.name).collect();
+  assert_eq!(names, ["Store", "Reflection"]);
+
+  assert_eq!(
+    signatures(&services[0]),
+    [
+      "get(key: String) -> Option<Vec<u8>>",
+      "set(key: String, value: Vec<u8>) -> ()",
+      "scan(range: (u32, u32), limit: Option<usize>) -> Vec<String>",
+      "put(entry: Entry) -> Option<Entry>",
+    ]
+  );
+
+  let store = client.describe("Store".into()).await.unwrap().unwrap();
+  assert_eq!(store, services[0]);
+  assert_eq!(store.method("set").unwrap().arguments.len(), 2);
+  assert_eq!(client.describe("Missing".into()).await.unwrap(), None);
+}
+
+#[test]
+fn describes_user_defined_types() {
+  let store = StoreServer.into_serve().descriptor();
+  let get = store.method("get").unwrap();
+  assert_eq!(
+    get.output_schema,
+    Some(Schema::Option(Box::new(Schema::Seq(Box::new(Schema::Int {
+      bits: 8,
+      signed: false
+    })))))
+  );
+
+  let put = store.method("put").unwrap();
+  let Some(Schema::Named(entry)) = put.arguments[0].schema else {
+    panic!("expected a struct, found {:?}", put.arguments[0].schema);
+  };
+  assert_eq!(
+    put.output_schema,
+    Some(Schema::Option(Box::new(Schema::Named(entry))))
+  );
+
+  let entry = &store.types[entry as usize];
+  assert_eq!(entry.name, "Entry");
+  let TypeKind::Struct(Fields::Named(fields)) = &entry.kind else {
+    panic!("expected named fields, found {:?}", entry.kind);
+  };
+  let names: Vec<_> = fields.iter().map(|field| &*field.name).collect();
+  assert_eq!(names, ["key", "value", "expiry"]);
+  let Field { schema: Schema::Named(expiry), .. } = fields[2] else {
+    panic!("expected an enum, found {:?}", fields[2]);
+  };
+  assert_eq!(
+    store.types[expiry as usize].kind,
+    TypeKind::Enum(vec![
+      Variant { name: "Never".into(), fields: Fields::Unit },
+      Variant {
+        name: "After".into(),
+        fields: Fields::Newtype(Schema::Int { bits: 64, signed: false }),
+      },
+    ])
+  );
+}
+
+#[tokio::test]
+async fn reflection_is_opt_in() {
+  let (client, server) = channel::unbounded();
+  let server = Server::default()
+    .add_service(StoreServer.into_serve())
+    .serve_channel(server);
+  tokio::spawn(server.into_future());
+
+  let mut client = ReflectionClient::with_transport(client);
+  assert!(client.list_services().await.is_err());
+}
+
+static TRACED: AtomicUsize = AtomicUsize::new(0);
+
+/// Counts how often it is deserialized, e.g. while being traced.
+#[derive(Debug, Serialize)]
+pub struct Traced(u32);
+
+impl<'de> Deserialize<'de> for Traced {
+  fn deserialize<D: serde::Deserializer<'de>>(
+    deserializer: D,
+  ) -> Result<Self, D::Error> {
+    TRACED.fetch_add(1, Ordering::SeqCst);
+    u32::deserialize(deserializer).map(Traced)
+  }
+}
+
+#[webcontr::service]
+pub trait Tracing {
+  async fn take(value: Traced);
+}
+
+#[derive(Clone)]
+struct TracingServer;
+
+#[webcontr::async_trait]
+impl Tracing for TracingServer {
+  async fn take(&self, _value: Traced) {}
+}
+
+#[test]
+fn types_are_only_traced_with_reflection() {
+  let server = Server::default().add_service(TracingServer.into_serve());
+  let _ = FrozenServer::from(server);
+  assert_eq!(TRACED.load(Ordering::SeqCst), 0);
+
+  let server =
+    Server::default().add_service(TracingServer.into_serve()).with_reflection();
+  let _ = FrozenServer::from(server);
+  assert!(TRACED.load(Ordering::SeqCst) > 0);
+}
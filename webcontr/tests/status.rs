@@ -0,0 +1,122 @@
+use std::future::IntoFuture;
+
+use bytes::Bytes;
+use webcontr::{
+  prelude::*,
+  status::{Code, Status},
+  transport::{duplex, frame::ResponseErrorKind},
+  ClientError, Server,
+};
+
+#[webcontr::service]
+pub trait Files {
+  async fn open(path: String) -> Result<u32, webcontr::status::Status>;
+  #[status]
+  async fn stat(path: String) -> Result<u64, Status>;
+}
+
+/// Opens `/` only, failing with its status for anything else.
+#[derive(Clone)]
+struct FilesServer(Status);
+
+#[webcontr::async_trait]
+impl Files for FilesServer {
+  async fn open(&self, path: String) -> Result<u32, Status> {
+    match path.as_str() {
+      "/" => Ok(3),
+      _ => Err(self.0.clone()),
+    }
+  }
+
+  async fn stat(&self, _path: String) -> Result<u64, Status> {
+    Err(self.0.clone())
+  }
+}
+
+fn client(status: Status) -> FilesClient {
+  let (connector, listener) = duplex::listener(1024);
+  let server = Server::default().add_service(FilesServer(status).into_serve());
+  tokio::spawn(server.serve_with(listener).into_future());
+  FilesClient::with_transport(connector)
+}
+
+#[tokio::test]
+async fn statuses_reach_the_client() {
+  let status = Status::new(Code::NotFound)
+    .with_message("no such file")
+    .with_details(Bytes::from_static(b"/etc/missing"));
+  let mut client = client(status.clone());
+
+  let err = client.open("/etc/missing".into()).await.unwrap_err();
+  let ClientError::ServerError(ResponseErrorKind::Status(received)) = &err
+  else {
+    panic!("unexpected {err:?}");
+  };
+  assert_eq!(received, &status);
+  assert_eq!(err.to_string(), "server error: not_found: no such file");
+  assert!(!err.is_retryable());
+
+  // Only the errors are statuses, results are sent as usual.
+  assert_eq!(client.open("/".into()).await.unwrap(), 3);
+}
+
+#[tokio::test]
+async fn unavailable_is_retryable() {
+  let mut client = client(Status::new(Code::Unavailable));
+
+  let err = client.open("/tmp".into()).await.unwrap_err();
+  assert!(err.is_retryable());
+}
+
+#[test]
+fn every_error_has_a_code() {
+  assert_eq!(ResponseErrorKind::MethodNotFound.code(), Code::Unimplemented);
+  assert_eq!(ResponseErrorKind::Overloaded.code(), Code::Unavailable);
+  let status = ResponseErrorKind::from(Status::new(Code::Unauthenticated));
+  assert_eq!(status.code(), Code::Unauthenticated);
+  assert_eq!(status.name(), "unauthenticated");
+}
+
+#[tokio::test]
+async fn imported_statuses_need_opting_in() {
+  let mut client = client(Status::new(Code::NotFound));
+  let err = client.stat("/".into()).await.unwrap_err();
+  assert!(matches!(
+    err,
+    ClientError::ServerError(ResponseErrorKind::Status(_))
+  ));
+}
+
+mod api {
+  use serde::{Deserialize, Serialize};
+
+  /// Not a [webcontr::status::Status], sent like any other type.
+  #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+  pub struct Status(pub String);
+}
+
+#[webcontr::service]
+pub trait Lookup {
+  async fn find(key: String) -> Result<u32, api::Status>;
+}
+
+#[derive(Clone)]
+struct LookupServer;
+
+#[webcontr::async_trait]
+impl Lookup for LookupServer {
+  async fn find(&self, key: String) -> Result<u32, api::Status> {
+    Err(api::Status(key))
+  }
+}
+
+#[tokio::test]
+async fn other_status_types_are_plain_results() {
+  let (connector, listener) = duplex::listener(1024);
+  let server = Server::default().add_service(LookupServer.into_serve());
+  tokio::spawn(server.serve_with(listener).into_future());
+
+  let mut client = LookupClient::with_transport(connector);
+  let found = client.find("key".into()).await.unwrap();
+  assert_eq!(found, Err(api::Status("key".into())));
+}
@@ -0,0 +1,78 @@
+use std::{future::IntoFuture, time::Duration};
+
+use tokio::time::sleep;
+use webcontr::{
+  prelude::*,
+  transport::{channel, duplex, frame::ResponseErrorKind},
+  ClientError, Server,
+};
+
+#[webcontr::service]
+pub trait Export {
+  /// Latency sensitive, shouldn't wait on the global timeout.
+  #[timeout = "20ms"]
+  async fn lookup(delay_ms: u64) -> u64;
+
+  #[timeout = "2s"]
+  async fn export(delay_ms: u64) -> u64;
+
+  async fn other(delay_ms: u64) -> u64;
+}
+
+#[derive(Clone)]
+struct ExportServer;
+
+#[webcontr::async_trait]
+impl Export for ExportServer {
+  async fn lookup(&self, delay_ms: u64) -> u64 {
+    sleep(Duration::from_millis(delay_ms)).await;
+    delay_ms
+  }
+
+  async fn export(&self, delay_ms: u64) -> u64 {
+    sleep(Duration::from_millis(delay_ms)).await;
+    delay_ms
+  }
+
+  async fn other(&self, delay_ms: u64) -> u64 {
+    sleep(Duration::from_millis(delay_ms)).await;
+    delay_ms
+  }
+}
+
+#[tokio::test]
+async fn method_timeouts_override_server_timeout() {
+  let (connector, listener) = duplex::listener(1024);
+  let server = Server::default()
+    .add_service(ExportServer.into_serve())
+    .serve_with(listener)
+    .with_timeout(Duration::from_millis(100));
+  tokio::spawn(server.into_future());
+
+  // Overrides the declared deadlines, so the server's timeouts are observed.
+  let mut client = ExportClient::with_transport(connector)
+    .with_timeout(Duration::from_secs(5));
+
+  assert_eq!(client.lookup(0).await.unwrap(), 0);
+  assert!(matches!(
+    client.lookup(60).await.unwrap_err(),
+    ClientError::ServerError(ResponseErrorKind::Timeout)
+  ));
+
+  assert_eq!(client.export(200).await.unwrap(), 200);
+
+  assert_eq!(client.other(10).await.unwrap(), 10);
+  assert!(matches!(
+    client.other(200).await.unwrap_err(),
+    ClientError::ServerError(ResponseErrorKind::Timeout)
+  ));
+}
+
+#[tokio::test]
+async fn declared_timeout_is_client_deadline() {
+  // Nobody answers on the other end.
+  let (client, _server) = channel::unbounded();
+  let mut client = ExportClient::with_transport(client);
+
+  assert!(matches!(client.lookup(0).await.unwrap_err(), ClientError::Timeout));
+}
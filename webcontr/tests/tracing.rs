@@ -0,0 +1,110 @@
+#![cfg(feature = "tracing")]
+
+use std::{
+  fmt::Debug,
+  future::IntoFuture,
+  sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex,
+  },
+};
+
+use tracing::{
+  field::{Field, Visit},
+  span, Event, Metadata, Subscriber,
+};
+use webcontr::{
+  prelude::*,
+  transport::{duplex, frame::ResponseErrorKind},
+  Server,
+};
+
+#[webcontr::service]
+pub trait Calc {
+  async fn add(a: u32, b: u32) -> u32;
+}
+
+#[derive(Clone)]
+struct CalcServer;
+
+#[webcontr::async_trait]
+impl Calc for CalcServer {
+  async fn add(&self, a: u32, b: u32) -> u32 {
+    a + b
+  }
+}
+
+/// Name and `field=value` pairs of every span created.
+type Spans = Vec<(&'static str, Vec<String>)>;
+
+#[derive(Default, Clone)]
+struct Recorder {
+  next_id: Arc<AtomicU64>,
+  spans: Arc<Mutex<Spans>>,
+}
+
+struct FieldVisitor<'a>(&'a mut Vec<String>);
+
+impl Visit for FieldVisitor<'_> {
+  fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+    self.0.push(format!("{}={:?}", field.name(), value));
+  }
+
+  fn record_str(&mut self, field: &Field, value: &str) {
+    self.0.push(format!("{}={}", field.name(), value));
+  }
+}
+
+impl Subscriber for Recorder {
+  fn enabled(&self, _: &Metadata<'_>) -> bool {
+    true
+  }
+
+  fn new_span(&self, attrs: &span::Attributes<'_>) -> span::Id {
+    let mut fields = Vec::new();
+    attrs.record(&mut FieldVisitor(&mut fields));
+    self.spans.lock().unwrap().push((attrs.metadata().name(), fields));
+    span::Id::from_u64(self.next_id.fetch_add(1, Ordering::Relaxed) + 1)
+  }
+
+  fn record(&self, id: &span::Id, values: &span::Record<'_>) {
+    let mut spans = self.spans.lock().unwrap();
+    let (_, fields) = &mut spans[id.into_u64() as usize - 1];
+    values.record(&mut FieldVisitor(fields));
+  }
+
+  fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+  fn event(&self, _: &Event<'_>) {}
+  fn enter(&self, _: &span::Id) {}
+  fn exit(&self, _: &span::Id) {}
+}
+
+#[tokio::test]
+async fn rpc_spans_on_both_sides() {
+  let recorder = Recorder::default();
+  let _guard = tracing::subscriber::set_default(recorder.clone());
+
+  let (connector, listener) = duplex::listener(1024);
+  let server =
+    Server::default().add_service(CalcServer.into_serve()).serve_with(listener);
+  tokio::spawn(server.into_future());
+
+  let mut client = CalcClient::with_transport(connector);
+  assert_eq!(client.add(1, 2).await.unwrap(), 3);
+
+  let spans = recorder.spans.lock().unwrap();
+  let rpc_spans: Vec<_> =
+    spans.iter().filter(|(name, _)| *name == "webcontr.rpc").collect();
+  assert_eq!(rpc_spans.len(), 2);
+
+  for (_, fields) in rpc_spans {
+    for expected in
+      ["service=Calc", "method=add", "request_size=12", "status=ok"]
+    {
+      assert!(fields.iter().any(|field| field == expected), "{fields:?}");
+    }
+    assert!(fields.iter().any(|field| field.starts_with("duration=")));
+  }
+
+  assert!(spans.iter().any(|(name, _)| *name == "webcontr.connection"));
+}
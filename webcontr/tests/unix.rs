@@ -0,0 +1,79 @@
+#![cfg(unix)]
+
+use std::{
+  fs,
+  future::IntoFuture,
+  io,
+  os::unix::fs::{MetadataExt, PermissionsExt},
+};
+
+use webcontr::{
+  context::{self, PeerInfo},
+  prelude::*,
+  transport::{
+    frame::ResponseErrorKind,
+    unix::{client::UnixTransport, UnixSocket},
+  },
+  Server,
+};
+
+#[webcontr::service]
+pub trait Whoami {
+  async fn uid() -> Option<u32>;
+}
+
+#[derive(Clone)]
+struct WhoamiServer;
+
+#[webcontr::async_trait]
+impl Whoami for WhoamiServer {
+  async fn uid(&self) -> Option<u32> {
+    match context::peer() {
+      Some(PeerInfo::Unix(peer)) => Some(peer.uid),
+      _ => None,
+    }
+  }
+}
+
+#[tokio::test]
+async fn serve_over_unix_socket() {
+  let dir =
+    std::env::temp_dir().join(format!("webcontr-unix-{}", std::process::id()));
+  fs::create_dir_all(&dir).unwrap();
+  let path = dir.join("whoami.sock");
+  let _ = fs::remove_file(&path);
+
+  // Left behind like a crashed server would.
+  drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+
+  let err = UnixSocket::new(&path).bind().unwrap_err();
+  assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+
+  let listener = UnixSocket::new(&path)
+    .with_stale_cleanup()
+    .with_mode(0o600)
+    .bind()
+    .unwrap();
+  let mode = fs::metadata(&path).unwrap().permissions().mode();
+  assert_eq!(mode & 0o777, 0o600);
+
+  let err = UnixSocket::new(&path).with_stale_cleanup().bind().unwrap_err();
+  assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+  let err = UnixSocket::new(&path).with_mode(0o600).bind().unwrap_err();
+  assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+  assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+  let server = Server::default()
+    .add_service(WhoamiServer.into_serve())
+    .serve_unix(listener);
+  let mut client = WhoamiClient::with_transport(UnixTransport::new(&path));
+
+  tokio::select! {
+    res = IntoFuture::into_future(server) => panic!("server stopped: {res:?}"),
+    uid = client.uid() => {
+      assert_eq!(uid.unwrap(), Some(fs::metadata(&dir).unwrap().uid()));
+    }
+  }
+
+  fs::remove_dir_all(&dir).unwrap();
+}